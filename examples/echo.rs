@@ -2,6 +2,9 @@ use touche::{Request, Response, Server, StatusCode};
 
 fn main() -> std::io::Result<()> {
     Server::bind("0.0.0.0:4444").serve(|req: Request<_>| {
+        // The body is streamed back as it is received, without buffering it. Chunked uploads are
+        // answered with chunked responses, try it with:
+        // curl -T - -H "transfer-encoding: chunked" http://localhost:4444
         Response::builder()
             .status(StatusCode::OK)
             .body(req.into_body())
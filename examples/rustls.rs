@@ -7,15 +7,12 @@ fn main() {
 fn main() -> std::io::Result<()> {
     use std::{
         io::{self, ErrorKind::Other},
-        net::TcpListener,
         sync::Arc,
     };
 
-    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+    use rustls::ServerConfig;
     use touche::{Response, Server, StatusCode};
 
-    let listener = TcpListener::bind("0.0.0.0:4444")?;
-
     let tls_cfg = {
         let certs = certs::load_certs("examples/tls/cert.pem")?;
         let key = certs::load_private_key("examples/tls/key.pem")?;
@@ -29,20 +26,9 @@ fn main() -> std::io::Result<()> {
         Arc::new(cfg)
     };
 
-    let connections = listener
-        .incoming()
-        .filter_map(|conn| conn.ok())
-        .filter_map(|conn| {
-            Some(StreamOwned::new(
-                ServerConnection::new(tls_cfg.clone()).ok()?,
-                conn,
-            ))
-        })
-        .map(|tls_conn| tls_conn.into());
-
     Server::builder()
         .max_threads(100)
-        .from_connections(connections)
+        .bind_tls("0.0.0.0:4444", tls_cfg)
         .serve(|_req| {
             Response::builder()
                 .status(StatusCode::OK)
@@ -1,9 +1,20 @@
 use std::io::{BufRead, BufReader, BufWriter, Write};
 
-use touche::{header, upgrade::Upgrade, Body, Connection, Response, Server, StatusCode};
+use touche::{
+    header,
+    upgrade::{self, Upgrade},
+    Body, Connection, Request, Response, Server, StatusCode,
+};
 
 fn main() -> std::io::Result<()> {
-    Server::bind("0.0.0.0:4444").serve(|_req| {
+    Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+        if !upgrade::requested_protocols(&req)
+            .iter()
+            .any(|proto| proto == "line-protocol")
+        {
+            return upgrade::upgrade_required("line-protocol").body(Body::empty());
+        }
+
         Response::builder()
             .status(StatusCode::SWITCHING_PROTOCOLS)
             .header(header::UPGRADE, "line-protocol")
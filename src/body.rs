@@ -9,11 +9,17 @@
 //! - The [`Body`] concrete type, which is an implementation of [`HttpBody`] returned by touche
 //!   as a "receive stream". It is also a decent default implementation for your send streams.
 use std::{
+    cell::Cell,
+    collections::VecDeque,
     error::Error,
     fmt::Debug,
     fs::File,
-    io::{self, Cursor, Read},
-    sync::mpsc::{self, Sender},
+    io::{self, BufRead, BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdout},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
 };
 
 use headers::{HeaderMap, HeaderName, HeaderValue};
@@ -21,6 +27,8 @@ pub use http_body::*;
 
 mod http_body;
 
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
 /// The [`HttpBody`] used on receiving server requests.
 /// It is also a good default body to return as responses.
 #[derive(Default)]
@@ -32,22 +40,47 @@ enum BodyInner {
     Empty,
     Buffered(Vec<u8>),
     Iter(Box<dyn Iterator<Item = io::Result<Chunk>> + Send>),
-    Reader(Box<dyn Read + Send>, Option<usize>),
+    Reader(Box<dyn Read + Send>, Option<usize>, usize),
+    File(File, usize),
 }
 
+/// Summarizes the body without consuming it, showing only a short prefix of buffered bytes.
 impl Debug for Body {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut out = f.debug_tuple("Body");
         match self.0 {
-            Some(BodyInner::Empty) | None => out.field(&"empty"),
-            Some(BodyInner::Buffered(ref buffer)) => out.field(buffer),
-            Some(BodyInner::Iter(_)) => out.field(&"chunked"),
-            Some(BodyInner::Reader(..)) => out.field(&"streaming"),
+            Some(BodyInner::Empty) | None => out.field(&format_args!("Empty")),
+            Some(BodyInner::Buffered(ref buffer)) => out.field(&Summary("Buffered", buffer)),
+            Some(BodyInner::Iter(_)) => out.field(&format_args!("Iter")),
+            Some(BodyInner::Reader(_, len, _)) => out.field(&format_args!("Reader({len:?})")),
+            Some(BodyInner::File(_, len)) => out.field(&format_args!("File({len})")),
         };
         out.finish()
     }
 }
 
+/// Shows the length and the first bytes of a buffer, in hex.
+struct Summary<'a>(&'static str, &'a [u8]);
+
+impl Debug for Summary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const PREFIX_LEN: usize = 8;
+
+        let Summary(name, bytes) = *self;
+        write!(f, "{name}({}", bytes.len())?;
+        if !bytes.is_empty() {
+            f.write_str(", ")?;
+            for byte in bytes.iter().take(PREFIX_LEN) {
+                write!(f, "{byte:02x}")?;
+            }
+            if bytes.len() > PREFIX_LEN {
+                f.write_str("..")?;
+            }
+        }
+        f.write_str(")")
+    }
+}
+
 /// The sender half of a channel, used to stream chunks from another thread.
 #[derive(Debug)]
 pub struct BodyChannel(Sender<io::Result<Chunk>>);
@@ -57,7 +90,7 @@ impl BodyChannel {
     pub fn send<T: Into<Vec<u8>>>(&self, data: T) -> io::Result<()> {
         self.0
             .send(Ok(data.into().into()))
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "body closed"))
+            .map_err(|_| io::Error::other("body closed"))
     }
 
     /// Send a trailer header. Note that trailers will be buffered, so you are not required to send
@@ -83,17 +116,32 @@ impl BodyChannel {
     pub fn send_trailers(&self, trailers: HeaderMap) -> io::Result<()> {
         self.0
             .send(Ok(Chunk::Trailers(trailers)))
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "body closed"))
+            .map_err(|_| io::Error::other("body closed"))
     }
 
+    /// Ends the body, which is the same as dropping this channel.
+    pub fn close(self) {}
+
     /// Aborts the body in an abnormal fashion.
+    ///
+    /// When the body is being sent by the server, the connection is reset right away instead of
+    /// terminating the response, so the client knows it is incomplete whatever its framing.
     pub fn abort(self) {
-        self.0
-            .send(Err(io::Error::new(io::ErrorKind::Other, "aborted")))
-            .ok();
+        self.0.send(Err(io::Error::other(Aborted))).ok();
     }
 }
 
+/// Error of bodies aborted with [`BodyChannel::abort`].
+#[derive(Debug, thiserror::Error)]
+#[error("body aborted")]
+struct Aborted;
+
+/// Whether `err` comes from a body aborted with [`BodyChannel::abort`].
+#[cfg(feature = "server")]
+pub(crate) fn is_aborted(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|err| err.is::<Aborted>())
+}
+
 impl Body {
     /// Creates an empty [`Body`] stream.
     pub fn empty() -> Self {
@@ -123,12 +171,1199 @@ impl Body {
         ))))
     }
 
+    /// Creates a [`Body`] stream from an existing [`Receiver`]. The body ends when all the senders
+    /// are dropped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::{sync::mpsc, thread};
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let (tx, rx) = mpsc::channel();
+    ///     thread::spawn(move || {
+    ///         tx.send(b"lol".to_vec()).ok();
+    ///         tx.send(b"wut".to_vec()).ok();
+    ///     });
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(Body::from_receiver(rx))
+    /// })
+    /// # }
+    /// ```
+    pub fn from_receiver(rx: Receiver<Vec<u8>>) -> Self {
+        Body::from_iter(rx)
+    }
+
+    /// Same as [`Body::from_receiver`], but receiving [`Chunk`]s, so trailers can be sent as well.
+    pub fn from_receiver_chunks(rx: Receiver<Chunk>) -> Self {
+        Body::from_iter(rx)
+    }
+
+    /// Creates a [`Body`] stream from an Iterator of fallible chunks.
+    ///
+    /// An `Err` ends the body as a failure: reading it returns the error, and when it is being sent
+    /// the connection is closed right away. This way clients can tell the body is incomplete, as
+    /// the terminating chunk of chunked responses is never sent, and fixed length responses end
+    /// short. Close delimited responses however look complete to clients, as their end is only
+    /// marked by closing the connection.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::io;
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let rows = (0..3).map(|row| match row {
+    ///         2 => Err(io::Error::other("cursor failed")),
+    ///         row => Ok(format!("row {row}\n").into_bytes()),
+    ///     });
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(Body::try_from_iter(rows))
+    /// })
+    /// # }
+    /// ```
+    pub fn try_from_iter<I>(chunks: I) -> Self
+    where
+        I: IntoIterator<Item = io::Result<Vec<u8>>> + Send + 'static,
+        <I as IntoIterator>::IntoIter: Send,
+    {
+        Body(Some(BodyInner::Iter(Box::new(
+            chunks.into_iter().map(|chunk| chunk.map(Chunk::from)),
+        ))))
+    }
+
+    /// Same as [`Body::try_from_iter`], but taking anything convertible into a [`Chunk`], such as
+    /// bytes or trailers. Mostly useful in tests, to check how handlers and the server deal with
+    /// bodies failing mid-stream.
+    ///
+    /// # Example
+    /// ```
+    /// # use std::io;
+    /// # use touche::{Body, HttpBody};
+    /// let body = Body::from_results(vec![Ok("lol"), Err(io::Error::other("lolwut"))]);
+    /// assert!(body.into_bytes().is_err());
+    /// ```
+    pub fn from_results<I, T>(results: I) -> Self
+    where
+        I: IntoIterator<Item = io::Result<T>> + Send + 'static,
+        <I as IntoIterator>::IntoIter: Send,
+        T: Into<Chunk>,
+    {
+        Body(Some(BodyInner::Iter(Box::new(
+            results.into_iter().map(|chunk| chunk.map(T::into)),
+        ))))
+    }
+
+    /// Creates a [`Body`] stream that lazily serializes each item as a line of JSON
+    /// ([NDJSON](https://github.com/ndjson/ndjson-spec)), one chunk per item.
+    ///
+    /// If an item fails to serialize, the body fails as described in [`Body::try_from_iter`], so the
+    /// client gets only the lines serialized so far.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .header("content-type", "application/x-ndjson")
+    ///         .body(Body::from_ndjson((1..=3).map(|id| serde_json::json!({ "id": id }))))
+    /// })
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn from_ndjson<T, I>(items: I) -> Self
+    where
+        T: serde::Serialize,
+        I: IntoIterator<Item = T> + Send + 'static,
+        <I as IntoIterator>::IntoIter: Send,
+    {
+        Body::try_from_iter(items.into_iter().map(|item| {
+            let mut line = serde_json::to_vec(&item).map_err(io::Error::other)?;
+            line.push(b'\n');
+            Ok(line)
+        }))
+    }
+
+    /// Creates a [`Body`] stream with a single chunk.
+    ///
+    /// Unlike a buffered body, its length is not known up front, so it will be written with
+    /// chunked encoding.
+    pub fn once<T: Into<Vec<u8>>>(chunk: T) -> Self {
+        Body::from_iter(std::iter::once(chunk.into()))
+    }
+
     /// Creates a [`Body`] stream from an [`Read`], with an optional length.
     pub fn from_reader<T: Into<Option<usize>>>(
         reader: impl Read + Send + 'static,
         length: T,
     ) -> Self {
-        Body(Some(BodyInner::Reader(Box::new(reader), length.into())))
+        Body(Some(BodyInner::Reader(
+            Box::new(reader),
+            length.into(),
+            DEFAULT_BUFFER_SIZE,
+        )))
+    }
+
+    /// Creates a [`Body`] stream from an [`Read`] of unknown length, emitting chunks of at most
+    /// `chunk_size` bytes.
+    ///
+    /// The chunk size only affects how the body is split when written with chunked encoding,
+    /// larger chunks reduce the framing overhead while smaller ones are sent sooner. The bytes sent
+    /// are the same as with [`Body::from_reader`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn from_reader_chunked(reader: impl Read + Send + 'static, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+        Body(Some(BodyInner::Reader(Box::new(reader), None, chunk_size)))
+    }
+
+    /// Creates a [`Body`] stream of unknown length from the standard output of a child process,
+    /// which must have been spawned with [`Stdio::piped`](std::process::Stdio::piped).
+    ///
+    /// The child is reaped once the body is dropped, so it doesn't linger as a zombie. If the body
+    /// is dropped before the end of the output (e.g. because the client went away), it is killed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the standard output of the child is not piped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::process::{Command, Stdio};
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let tail = Command::new("tail")
+    ///         .args(["-f", "/var/log/syslog"])
+    ///         .stdout(Stdio::piped())
+    ///         .spawn()?;
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(Body::from_child_stdout(tail))
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn from_child_stdout(mut child: Child) -> Self {
+        let stdout = child.stdout.take().expect("child stdout is not piped");
+        let reader = BodyInner::Reader(
+            Box::new(ChildOutput {
+                stdout,
+                child,
+                eof: false,
+            }),
+            None,
+            DEFAULT_BUFFER_SIZE,
+        );
+        // Unlike readers, chunk iterators are not drained when an unsent body is dropped, which
+        // could wait for the child forever
+        Body(Some(BodyInner::Iter(reader.into_send_chunks())))
+    }
+
+    /// Creates a [`Body`] streaming the files at `paths` one after the other, with their combined
+    /// length. Useful to serve bundles made of several files.
+    ///
+    /// Files are only opened once the previous one was sent, so no more than one is kept open at a
+    /// time. Their lengths are taken upfront, so reading fails if a file shrinks in the meantime.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let bundle = Body::from_paths(["js/vendor.js", "js/app.js"])?;
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .header("content-type", "text/javascript")
+    ///         .body(bundle)
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn from_paths<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> io::Result<Self> {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let meta = std::fs::metadata(&path)?;
+                if !meta.is_file() {
+                    return Err(io::Error::other("not a file"));
+                }
+                Ok((path.as_ref().to_path_buf(), meta.len()))
+            })
+            .collect::<io::Result<VecDeque<_>>>()?;
+
+        let len = files.iter().map(|(_, len)| len).sum::<u64>();
+        let reader = Files {
+            files,
+            current: None,
+        };
+
+        Ok(Body::from_reader(reader, len as usize))
+    }
+
+    /// Creates a [`Body`] of known length from a memory-mapped file, so it is sent straight from
+    /// the mapped pages instead of reading the file piece by piece.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the body is alive, which is undefined
+    /// behavior. Most likely the process is killed with `SIGBUS` when the pages past the new end of
+    /// the file are accessed, so this is best suited for files that are replaced rather than
+    /// modified in place.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     // Safety: the file is never modified while the server runs
+    ///     let body = unsafe { Body::from_mmap("assets/video.mp4")? };
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(body)
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    #[cfg(feature = "mmap")]
+    pub unsafe fn from_mmap(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let meta = file.metadata()?;
+
+        if !meta.is_file() {
+            return Err(io::Error::other("not a file"));
+        }
+
+        // Empty files can't be mapped on every platform
+        if meta.len() == 0 {
+            return Ok(Body::empty());
+        }
+
+        let map = memmap2::Mmap::map(&file)?;
+        let len = map.len();
+        Ok(Body::from_reader(Cursor::new(map), len))
+    }
+
+    /// Creates a [`Body`] stream from an [`Read`], emitting chunks of at most `chunk_size` bytes
+    /// followed by trailers computed from them, such as a checksum of the whole stream.
+    ///
+    /// Each chunk is passed to `update` along with the `state` as it is streamed, and `finish`
+    /// turns the state into the trailers once the reader reaches its end. This is the same as
+    /// [`Body::from_reader_chunked`] followed by [`Body::with_computed_trailers`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::fs::File;
+    /// # use touche::{Body, HeaderMap, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let body = Body::from_reader_with_trailer_fn(
+    ///         File::open("video.mp4")?,
+    ///         64 * 1024,
+    ///         md5::Context::new(),
+    ///         |digest, chunk| digest.consume(chunk),
+    ///         |digest| {
+    ///             let mut trailers = HeaderMap::new();
+    ///             let digest = format!("{:x}", digest.compute());
+    ///             trailers.insert("x-md5", digest.parse().unwrap());
+    ///             trailers
+    ///         },
+    ///     );
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .header("trailer", "x-md5")
+    ///         .body(body)
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn from_reader_with_trailer_fn<S, U, F>(
+        reader: impl Read + Send + 'static,
+        chunk_size: usize,
+        state: S,
+        update: U,
+        finish: F,
+    ) -> Self
+    where
+        S: Send + 'static,
+        U: FnMut(&mut S, &[u8]) + Send + 'static,
+        F: FnOnce(S) -> HeaderMap + Send + 'static,
+    {
+        Body::from_reader_chunked(reader, chunk_size).with_computed_trailers(state, update, finish)
+    }
+
+    /// Attempts to clone this [`Body`].
+    ///
+    /// Only empty and buffered bodies can be cloned, streaming bodies return `None`.
+    pub fn try_clone(&self) -> Option<Self> {
+        match self.0 {
+            Some(BodyInner::Empty) => Some(Body::empty()),
+            Some(BodyInner::Buffered(ref bytes)) => Some(Body::from(bytes.clone())),
+            _ => None,
+        }
+    }
+
+    /// Limits this [`Body`] to at most `limit` bytes, similar to [`Read::take`].
+    ///
+    /// Any trailers emitted after the limit is reached are dropped.
+    pub fn take(mut self, limit: u64) -> Self {
+        let limit_usize = usize::try_from(limit).unwrap_or(usize::MAX);
+
+        match self.0.take() {
+            None | Some(BodyInner::Empty) => Body::empty(),
+            Some(BodyInner::Buffered(mut bytes)) => {
+                bytes.truncate(limit_usize);
+                Body::from(bytes)
+            }
+            Some(BodyInner::Iter(chunks)) => Body(Some(BodyInner::Iter(Box::new(TakeChunks {
+                chunks,
+                remaining: limit,
+            })))),
+            Some(BodyInner::Reader(reader, len, buffer_size)) => Body(Some(BodyInner::Reader(
+                Box::new(DrainOnDrop::new(reader, len).take(limit)),
+                len.map(|len| len.min(limit_usize)),
+                buffer_size,
+            ))),
+            Some(BodyInner::File(file, len)) => {
+                Body(Some(BodyInner::File(file, len.min(limit_usize))))
+            }
+        }
+    }
+
+    /// Emits `prefix` before the data of this [`Body`], such as a byte order mark or a preamble.
+    ///
+    /// Streaming bodies keep streaming after the prefix, and the length is still known if it was
+    /// before. Files are no longer sent with zero-copy.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let csv = Body::from_iter(["name,age\n", "lol,42\n"]);
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .header("content-type", "text/csv; charset=utf-8")
+    ///         .body(csv.prepend(b"\xEF\xBB\xBF".to_vec()))
+    /// })
+    /// # }
+    /// ```
+    pub fn prepend(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        let prefix = prefix.into();
+        if prefix.is_empty() {
+            return self;
+        }
+
+        let (reader, len, buffer_size): (Box<dyn Read + Send>, _, _) = match self.0.take() {
+            None | Some(BodyInner::Empty) => return Body::from(prefix),
+            Some(BodyInner::Buffered(mut bytes)) => {
+                bytes.splice(0..0, prefix);
+                return Body::from(bytes);
+            }
+            Some(BodyInner::Iter(chunks)) => {
+                let chunks = std::iter::once(Ok(prefix.into())).chain(chunks);
+                return Body(Some(BodyInner::Iter(Box::new(chunks))));
+            }
+            Some(BodyInner::Reader(reader, Some(len), buffer_size)) => {
+                (Box::new(reader.take(len as u64)), Some(len), buffer_size)
+            }
+            Some(BodyInner::Reader(reader, None, buffer_size)) => (reader, None, buffer_size),
+            Some(BodyInner::File(file, len)) => (
+                Box::new(file.take(len as u64)),
+                Some(len),
+                DEFAULT_BUFFER_SIZE,
+            ),
+        };
+
+        let len = len.map(|len| len + prefix.len());
+        let reader = Cursor::new(prefix).chain(reader);
+        Body(Some(BodyInner::Reader(Box::new(reader), len, buffer_size)))
+    }
+
+    /// Reads up to `n` bytes from the start of this [`Body`], returning them along with a body
+    /// that replays them followed by the rest of the data. Useful to sniff the type of the content
+    /// without buffering the whole body.
+    ///
+    /// Fewer bytes are returned if the body is shorter than `n`. The returned body keeps streaming
+    /// and has the same length and trailers as the original.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Request, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+    ///     let (prefix, body) = req.into_body().peek_prefix(8)?;
+    ///     let content_type = if prefix.starts_with(b"\x89PNG") {
+    ///         "image/png"
+    ///     } else {
+    ///         "application/octet-stream"
+    ///     };
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .header("content-type", content_type)
+    ///         .body(body)
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn peek_prefix(mut self, n: usize) -> io::Result<(Vec<u8>, Self)> {
+        let (reader, len, buffer_size): (Box<dyn Read + Send>, _, _) = match self.0.take() {
+            None | Some(BodyInner::Empty) => return Ok((Vec::new(), Body::empty())),
+            Some(BodyInner::Buffered(bytes)) => {
+                let prefix = bytes[..n.min(bytes.len())].to_vec();
+                return Ok((prefix, Body::from(bytes)));
+            }
+            Some(BodyInner::Iter(mut chunks)) => {
+                let mut prefix = Vec::new();
+                let mut pending = Vec::new();
+                while prefix.len() < n {
+                    match chunks.next().transpose()? {
+                        Some(Chunk::Data(mut data)) => {
+                            let rest = data.split_off(data.len().min(n - prefix.len()));
+                            prefix.extend(data);
+                            if !rest.is_empty() {
+                                pending.push(Ok(rest.into()));
+                            }
+                        }
+                        Some(trailers) => pending.push(Ok(trailers)),
+                        None => break,
+                    }
+                }
+
+                let chunks = pending.into_iter().chain(chunks);
+                let body = Body(Some(BodyInner::Iter(Box::new(chunks))));
+                return Ok((prefix.clone(), body.prepend(prefix)));
+            }
+            Some(BodyInner::Reader(reader, Some(len), buffer_size)) => {
+                (Box::new(reader.take(len as u64)), Some(len), buffer_size)
+            }
+            Some(BodyInner::Reader(reader, None, buffer_size)) => (reader, None, buffer_size),
+            Some(BodyInner::File(file, len)) => (
+                Box::new(file.take(len as u64)),
+                Some(len),
+                DEFAULT_BUFFER_SIZE,
+            ),
+        };
+
+        let mut prefix = Vec::with_capacity(n);
+        let mut reader = reader.take(n as u64);
+        reader.read_to_end(&mut prefix)?;
+
+        let len = len.map(|len| len - prefix.len());
+        let rest = Body(Some(BodyInner::Reader(
+            Box::new(reader.into_inner()),
+            len,
+            buffer_size,
+        )));
+        Ok((prefix.clone(), rest.prepend(prefix)))
+    }
+
+    /// Reads a streaming [`Body`] to the end, turning it into a buffered one with a known length.
+    ///
+    /// Useful when the body must be sent with a `Content-Length` instead of chunked encoding.
+    /// Trailers are discarded.
+    pub fn buffered(self) -> io::Result<Self> {
+        match self.0 {
+            Some(BodyInner::Empty | BodyInner::Buffered(_)) => Ok(self),
+            _ => Ok(Body::from(self.into_bytes()?)),
+        }
+    }
+
+    /// Returns the length of this [`Body`] along with the body itself, reading it into a buffer
+    /// first if its length is not known up front (see [`Body::buffered`]).
+    ///
+    /// Useful when the body must be sent with a `Content-Length`, e.g. to HTTP/1.0 clients that
+    /// don't support chunked encoding.
+    pub fn ensure_length(self) -> io::Result<(u64, Self)> {
+        match self.len() {
+            Some(len) => Ok((len, self)),
+            None => {
+                let body = self.buffered()?;
+                Ok((body.len().unwrap_or(0), body))
+            }
+        }
+    }
+
+    /// Computes trailers from the data of this [`Body`] as it is streamed, e.g. a digest of
+    /// everything that was sent.
+    ///
+    /// Each data chunk is passed to `update` along with the `state`, which is handed to `finish`
+    /// at the end of the body to produce the trailers. Trailers already present on the body are
+    /// kept, unless replaced by computed ones. If reading the body fails, no trailers are
+    /// computed.
+    ///
+    /// As trailers are only sent on chunked encoding, the resulting body has no known length.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, HeaderMap, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let body = Body::from_iter(["lol", "wut"]).with_computed_trailers(
+    ///         md5::Context::new(),
+    ///         |digest, data| digest.consume(data),
+    ///         |digest| {
+    ///             let mut trailers = HeaderMap::new();
+    ///             let digest = format!("{:x}", digest.compute());
+    ///             trailers.insert("x-md5", digest.parse().unwrap());
+    ///             trailers
+    ///         },
+    ///     );
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .header("trailer", "x-md5")
+    ///         .body(body)
+    /// })
+    /// # }
+    /// ```
+    pub fn with_computed_trailers<S, U, F>(mut self, state: S, update: U, finish: F) -> Self
+    where
+        S: Send + 'static,
+        U: FnMut(&mut S, &[u8]) + Send + 'static,
+        F: FnOnce(S) -> HeaderMap + Send + 'static,
+    {
+        let chunks = match self.0.take() {
+            // Unsent readers are still drained once dropped, as they are when wrapped in a body
+            Some(BodyInner::Reader(reader, len, buffer_size)) => {
+                let reader = DrainOnDrop::new(reader, len);
+                BodyInner::Reader(Box::new(reader), len, buffer_size).into_send_chunks()
+            }
+            Some(inner) => inner.into_send_chunks(),
+            None => Box::new(std::iter::empty()),
+        };
+
+        Body(Some(BodyInner::Iter(Box::new(ComputedTrailers {
+            chunks,
+            state: Some(state),
+            update,
+            finish: Some(finish),
+            trailers: HeaderMap::new(),
+        }))))
+    }
+
+    /// Adds `trailers` to the ones this body emits at its end.
+    ///
+    /// Trailers sent by the body itself are kept, except for the ones with the same name as any of
+    /// `trailers`, which are replaced. As trailers are only sent on chunked encoding, the resulting
+    /// body has no known length.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, HeaderMap, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let mut trailers = HeaderMap::new();
+    ///     trailers.insert("server-timing", "db;dur=53".parse().unwrap());
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .header("trailer", "server-timing")
+    ///         .body(Body::from_iter(["lol", "wut"]).with_trailers(trailers))
+    /// })
+    /// # }
+    /// ```
+    pub fn with_trailers(self, trailers: HeaderMap) -> Self {
+        self.with_computed_trailers((), |_, _| {}, move |_| trailers)
+    }
+
+    /// Limits the rate this [`Body`] is produced to approximately `bytes_per_sec`, by sleeping
+    /// between chunks. Useful to share the bandwidth among large downloads, or to simulate slow
+    /// networks in tests.
+    ///
+    /// Data is produced in pieces of at most a tenth of a second worth of bytes, so the rate stays
+    /// smooth even for large chunks. The length of the body is kept, but files are no longer sent
+    /// with zero-copy.
+    ///
+    /// Only data actually sent is paced, dropping an unsent body (e.g. answering a `HEAD` request)
+    /// discards the rest of it right away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is zero.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::fs::File;
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     let body = Body::try_from(File::open("video.mp4")?)?.throttle(512 * 1024);
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(body)
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn throttle(mut self, bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "bytes_per_sec must be greater than zero");
+        let throttled = |reader: Box<dyn Read + Send>, len, buffer_size| {
+            let reader = Throttled {
+                reader,
+                pacer: Pacer::new(bytes_per_sec),
+            };
+            BodyInner::Reader(Box::new(reader), Some(len), buffer_size)
+        };
+
+        Body(Some(match self.0.take() {
+            None | Some(BodyInner::Empty) => BodyInner::Empty,
+            Some(BodyInner::Buffered(bytes)) => {
+                let len = bytes.len();
+                throttled(Box::new(Cursor::new(bytes)), len, DEFAULT_BUFFER_SIZE)
+            }
+            Some(BodyInner::File(file, len)) => throttled(Box::new(file), len, DEFAULT_BUFFER_SIZE),
+            Some(BodyInner::Reader(reader, Some(len), buffer_size)) => {
+                throttled(Box::new(reader.take(len as u64)), len, buffer_size)
+            }
+            Some(inner) => BodyInner::Iter(Box::new(ThrottledChunks {
+                chunks: inner.into_send_chunks(),
+                pacer: Pacer::new(bytes_per_sec),
+                pending: Vec::new(),
+                offset: 0,
+            })),
+        }))
+    }
+
+    /// Reads the whole [`Body`], returning its bytes along with its trailers, such as the ones sent
+    /// after the last chunk of chunked requests. The trailers are empty if the body had none.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Request, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+    ///     let (bytes, trailers) = req.into_body().into_bytes_with_trailers()?;
+    ///     let checksum = trailers.get("x-checksum");
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(format!("Got {} bytes with checksum {checksum:?}", bytes.len()))
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn into_bytes_with_trailers(self) -> io::Result<(Vec<u8>, HeaderMap)> {
+        let mut bytes = Vec::new();
+        let mut trailers = HeaderMap::new();
+
+        for chunk in self.into_chunks() {
+            match chunk? {
+                Chunk::Data(data) => bytes.extend_from_slice(&data),
+                Chunk::Trailers(more) => trailers.extend(more),
+            }
+        }
+
+        Ok((bytes, trailers))
+    }
+
+    /// Reads this [`Body`] into a UTF-8 [`String`], up to `max` bytes.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the body is bigger than `max` or is not valid
+    /// UTF-8. Bodies of known length bigger than `max` fail without being read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Request, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+    ///     let name = req.into_body().into_string(1024)?;
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(format!("Hello {name}"))
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn into_string(self, max: usize) -> io::Result<String> {
+        self.into_string_with_charset(max, None)
+    }
+
+    /// Same as [`Body::into_string`], but decoding the body with the charset of the given
+    /// `Content-Type` header value, such as `text/plain; charset=iso-8859-1`.
+    ///
+    /// The supported charsets are `utf-8`, `us-ascii` and `iso-8859-1`, other charsets fail with
+    /// [`io::ErrorKind::InvalidData`]. Without a charset, the body is decoded as UTF-8.
+    pub fn into_string_with_charset(
+        self,
+        max: usize,
+        content_type: Option<&HeaderValue>,
+    ) -> io::Result<String> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let charset = match content_type {
+            Some(content_type) => {
+                charset(content_type).ok_or_else(|| invalid("invalid charset"))?
+            }
+            None => None,
+        };
+
+        if self.len().filter(|len| *len > max as u64).is_some() {
+            return Err(invalid("body too large"));
+        }
+
+        let mut bytes = Vec::new();
+        self.into_reader()
+            .take((max as u64).saturating_add(1))
+            .read_to_end(&mut bytes)?;
+        if bytes.len() > max {
+            return Err(invalid("body too large"));
+        }
+
+        match charset.as_deref() {
+            None | Some("utf-8" | "utf8") => {
+                String::from_utf8(bytes).map_err(|_| invalid("body is not valid utf-8"))
+            }
+            Some("us-ascii" | "ascii") if bytes.is_ascii() => Ok(String::from_utf8(bytes).unwrap()),
+            Some("us-ascii" | "ascii") => Err(invalid("body is not valid ascii")),
+            Some("iso-8859-1" | "latin1") => Ok(bytes.into_iter().map(char::from).collect()),
+            Some(_) => Err(invalid("unsupported charset")),
+        }
+    }
+
+    /// Reads this [`Body`] on a separate thread, sending its data through a channel bounded to
+    /// `capacity` chunks. Trailers are skipped.
+    ///
+    /// Reading stops while the channel is full, so a slow consumer slows down the reading of the
+    /// body (and of the connection it comes from) instead of piling up data in memory. A
+    /// `capacity` of `0` hands each chunk over only when the receiver asks for it. The thread
+    /// stops after sending an error, or when the receiver is dropped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::io::Write;
+    /// # use touche::{Body, Request, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+    ///     let mut file = std::fs::File::create("upload.bin")?;
+    ///     for data in req.into_body().into_channel(4) {
+    ///         file.write_all(&data?)?;
+    ///     }
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(Body::empty())
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn into_channel(mut self, capacity: usize) -> Receiver<io::Result<Vec<u8>>> {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let chunks = self.0.take().unwrap_or_default().into_send_chunks();
+
+        thread::spawn(move || {
+            for chunk in chunks {
+                let data = match chunk {
+                    Ok(Chunk::Data(data)) => Ok(data),
+                    Ok(Chunk::Trailers(_)) => continue,
+                    Err(err) => Err(err),
+                };
+                let failed = data.is_err();
+                if tx.send(data).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Reads and discards the rest of this [`Body`], returning the number of bytes drained.
+    ///
+    /// Dropping a body drains it as well, but silently ignores any errors. Draining it explicitly
+    /// surfaces them, such as the client disconnecting before sending the whole body.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Request, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+    ///     if !req.headers().contains_key("authorization") {
+    ///         req.into_body().drain()?;
+    ///         return Response::builder()
+    ///             .status(StatusCode::UNAUTHORIZED)
+    ///             .body(Body::empty())
+    ///             .map_err(std::io::Error::other);
+    ///     }
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(req.into_body())
+    ///         .map_err(std::io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn drain(self) -> io::Result<u64> {
+        let len = self.len();
+        let drained = io::copy(&mut self.into_reader(), &mut io::sink())?;
+
+        match len {
+            Some(len) if drained < len => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("body ended after {drained} of {len} bytes"),
+            )),
+            _ => Ok(drained),
+        }
+    }
+
+    /// Reads this [`Body`] line by line, without buffering it whole. Useful for line delimited
+    /// formats, such as NDJSON.
+    ///
+    /// Lines are terminated by either `\n` or `\r\n`, which are not included in the returned
+    /// strings. The last line doesn't need to be terminated.
+    ///
+    /// # Example
+    /// ```
+    /// # use touche::Body;
+    /// let body = Body::from("lol\r\nwut\nlolwut");
+    /// let lines = body.lines().collect::<std::io::Result<Vec<_>>>().unwrap();
+    /// assert_eq!(lines, ["lol", "wut", "lolwut"]);
+    /// ```
+    pub fn lines(self) -> impl Iterator<Item = io::Result<String>> {
+        BufReader::new(self.into_reader()).lines()
+    }
+
+    /// Sets the size of the buffers used when reading streaming bodies.
+    pub(crate) fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        if let Some(BodyInner::Reader(_, _, ref mut size)) = self.0 {
+            *size = buffer_size;
+        }
+        self
+    }
+}
+
+pub(crate) type SendChunks = Box<dyn Iterator<Item = io::Result<Chunk>> + Send>;
+
+impl Body {
+    /// Same as [`HttpBody::into_chunks`], but keeping the iterator [`Send`].
+    #[cfg(feature = "decompression")]
+    pub(crate) fn into_send_chunks(mut self) -> SendChunks {
+        self.0.take().unwrap_or_default().into_send_chunks()
+    }
+}
+
+/// Reads the output of a child process, reaping it once dropped.
+struct ChildOutput {
+    stdout: ChildStdout,
+    child: Child,
+    eof: bool,
+}
+
+impl Read for ChildOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stdout.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            self.eof = true;
+        }
+        Ok(read)
+    }
+}
+
+impl Drop for ChildOutput {
+    fn drop(&mut self) {
+        // Children that wrote their whole output are left to finish
+        if !self.eof {
+            self.child.kill().ok();
+        }
+        self.child.wait().ok();
+    }
+}
+
+/// Reads files in sequence, opening each one only once the previous one is done.
+struct Files {
+    files: VecDeque<(PathBuf, u64)>,
+    current: Option<io::Take<File>>,
+}
+
+impl Read for Files {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(ref mut file) = self.current {
+                let read = file.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+                if file.limit() > 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+
+            match self.files.pop_front() {
+                Some((path, len)) => self.current = Some(File::open(path)?.take(len)),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+impl BodyInner {
+    /// Same as [`HttpBody::into_chunks`], but keeping the iterator [`Send`].
+    fn into_send_chunks(self) -> SendChunks {
+        match self {
+            BodyInner::Empty => Box::new(std::iter::empty()),
+            BodyInner::Buffered(bytes) => Box::new(std::iter::once(Ok(bytes.into()))),
+            BodyInner::Iter(chunks) => chunks,
+            BodyInner::File(file, len) => {
+                BodyInner::Reader(Box::new(file), Some(len), DEFAULT_BUFFER_SIZE).into_send_chunks()
+            }
+            BodyInner::Reader(reader, len, buffer_size) => {
+                let mut reader: Box<dyn Read + Send> = match len {
+                    Some(len) => Box::new(reader.take(len as u64)),
+                    None => reader,
+                };
+                let mut buf = vec![0_u8; buffer_size];
+                let mut done = false;
+
+                Box::new(std::iter::from_fn(move || {
+                    if done {
+                        return None;
+                    }
+
+                    match reader.read(&mut buf) {
+                        Ok(0) => None,
+                        Ok(bytes) => Some(Ok(buf[..bytes].to_vec().into())),
+                        Err(err) => {
+                            done = true;
+                            Some(Err(err))
+                        }
+                    }
+                }))
+            }
+        }
+    }
+}
+
+struct ComputedTrailers<S, U, F> {
+    chunks: SendChunks,
+    state: Option<S>,
+    update: U,
+    finish: Option<F>,
+    trailers: HeaderMap,
+}
+
+impl<S, U, F> Iterator for ComputedTrailers<S, U, F>
+where
+    U: FnMut(&mut S, &[u8]),
+    F: FnOnce(S) -> HeaderMap,
+{
+    type Item = io::Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.chunks.next() {
+                Some(Ok(Chunk::Data(data))) => {
+                    if let Some(ref mut state) = self.state {
+                        (self.update)(state, &data);
+                    }
+                    return Some(Ok(Chunk::Data(data)));
+                }
+                Some(Ok(Chunk::Trailers(trailers))) => self.trailers.extend(trailers),
+                Some(Err(err)) => {
+                    self.state = None;
+                    return Some(Err(err));
+                }
+                None => {
+                    let (state, finish) = (self.state.take()?, self.finish.take()?);
+                    let mut trailers = std::mem::take(&mut self.trailers);
+                    trailers.extend(finish(state));
+                    return Some(Ok(Chunk::Trailers(trailers)));
+                }
+            }
+        }
+    }
+}
+
+/// Extracts the lowercase `charset` parameter of a `Content-Type` header value.
+fn charset(content_type: &HeaderValue) -> Option<Option<String>> {
+    let content_type = content_type.to_str().ok()?;
+    let charset = content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_ascii_lowercase())
+    });
+    Some(charset)
+}
+
+/// Paces data to a target rate, see [`Body::throttle`].
+struct Pacer {
+    bytes_per_sec: u64,
+    start: Option<Instant>,
+    sent: u64,
+}
+
+impl Pacer {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            start: None,
+            sent: 0,
+        }
+    }
+
+    /// The maximum amount of bytes produced at once.
+    fn max_len(&self) -> usize {
+        (self.bytes_per_sec / 10).clamp(1, DEFAULT_BUFFER_SIZE as u64) as usize
+    }
+
+    /// Sleeps until the bytes sent so far are due at the target rate.
+    fn wait(&mut self) {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let due = Duration::from_secs_f64(self.sent as f64 / self.bytes_per_sec as f64);
+        if let Some(remaining) = due.checked_sub(start.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+}
+
+struct Throttled<R> {
+    reader: R,
+    pacer: Pacer,
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Only data actually sent is paced
+        if DRAINING.get() {
+            return self.reader.read(buf);
+        }
+
+        self.pacer.wait();
+        let len = buf.len().min(self.pacer.max_len());
+        let read = self.reader.read(&mut buf[..len])?;
+        self.pacer.sent += read as u64;
+        Ok(read)
+    }
+}
+
+struct ThrottledChunks {
+    chunks: SendChunks,
+    pacer: Pacer,
+    pending: Vec<u8>,
+    offset: usize,
+}
+
+impl Iterator for ThrottledChunks {
+    type Item = io::Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset == self.pending.len() {
+            match self.chunks.next()? {
+                Ok(Chunk::Data(data)) => {
+                    self.pending = data;
+                    self.offset = 0;
+                }
+                // Trailers and errors are passed through right away
+                chunk => return Some(chunk),
+            }
+        }
+
+        self.pacer.wait();
+        let len = (self.pending.len() - self.offset).min(self.pacer.max_len());
+        let piece = self.pending[self.offset..self.offset + len].to_vec();
+        self.offset += len;
+        self.pacer.sent += len as u64;
+        Some(Ok(Chunk::Data(piece)))
+    }
+}
+
+/// Reads whatever is left of a reader when dropped, up to its length if known, as dropping a
+/// [`Body`] does. Keeps adapted bodies, such as limited ones, from leaving unread bytes behind.
+struct DrainOnDrop<R: Read> {
+    reader: R,
+    remaining: Option<u64>,
+}
+
+impl<R: Read> DrainOnDrop<R> {
+    fn new(reader: R, len: Option<usize>) -> Self {
+        Self {
+            reader,
+            remaining: len.map(|len| len as u64),
+        }
+    }
+}
+
+impl<R: Read> Read for DrainOnDrop<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = match self.remaining {
+            Some(remaining) => buf
+                .len()
+                .min(usize::try_from(remaining).unwrap_or(usize::MAX)),
+            None => buf.len(),
+        };
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let read = self.reader.read(&mut buf[..len])?;
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= read as u64;
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Read> Drop for DrainOnDrop<R> {
+    fn drop(&mut self) {
+        drain(self);
+    }
+}
+
+thread_local! {
+    /// Whether this thread is discarding an unsent body, which is never throttled.
+    static DRAINING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Reads and discards the rest of `reader`.
+fn drain<R: Read + ?Sized>(reader: &mut R) {
+    let draining = DRAINING.replace(true);
+    io::copy(reader, &mut io::sink()).ok();
+    DRAINING.set(draining);
+}
+
+struct TakeChunks<I> {
+    chunks: I,
+    remaining: u64,
+}
+
+impl<I: Iterator<Item = io::Result<Chunk>>> Iterator for TakeChunks<I> {
+    type Item = io::Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        match self.chunks.next()? {
+            Ok(Chunk::Data(mut data)) => {
+                if data.len() as u64 > self.remaining {
+                    data.truncate(self.remaining as usize);
+                }
+                self.remaining -= data.len() as u64;
+                Some(Ok(Chunk::Data(data)))
+            }
+            chunk => Some(chunk),
+        }
     }
 }
 
@@ -141,32 +1376,36 @@ impl HttpBody for Body {
             Some(BodyInner::Empty) => Some(0),
             Some(BodyInner::Buffered(bytes)) => Some(bytes.len() as u64),
             Some(BodyInner::Iter(_)) => None,
-            Some(BodyInner::Reader(_, Some(len))) => Some(*len as u64),
-            Some(BodyInner::Reader(_, None)) => None,
+            Some(BodyInner::Reader(_, Some(len), _)) => Some(*len as u64),
+            Some(BodyInner::Reader(_, None, _)) => None,
+            Some(BodyInner::File(_, len)) => Some(*len as u64),
             None => None,
         }
     }
 
     fn into_reader(mut self) -> Self::Reader {
         match self.0.take().unwrap() {
-            BodyInner::Empty => BodyReader(BodyReaderInner::Buffered(Cursor::new(Vec::new()))),
-            BodyInner::Buffered(bytes) => BodyReader(BodyReaderInner::Buffered(Cursor::new(bytes))),
+            BodyInner::Empty => BodyReader::new(BodyReaderInner::Buffered(Cursor::new(Vec::new()))),
+            BodyInner::Buffered(bytes) => {
+                BodyReader::new(BodyReaderInner::Buffered(Cursor::new(bytes)))
+            }
             BodyInner::Iter(chunks) => {
-                let mut chunks = chunks.filter_map(|chunk| match chunk {
+                let chunks = chunks.filter_map(|chunk| match chunk {
                     Ok(Chunk::Data(data)) => Some(Ok(data)),
                     Ok(Chunk::Trailers(_)) => None,
                     Err(err) => Some(Err(err)),
                 });
-                let cursor = chunks
-                    .next()
-                    .map(|chunk| chunk.unwrap_or_default())
-                    .map(Cursor::new);
-                BodyReader(BodyReaderInner::Iter(Box::new(chunks), cursor))
+                // The first chunk is only pulled on the first read, so its errors are not lost
+                let cursor = Some(Cursor::new(Vec::new()));
+                BodyReader::new(BodyReaderInner::Iter(Box::new(chunks), cursor))
             }
-            BodyInner::Reader(stream, Some(len)) => {
-                BodyReader(BodyReaderInner::Reader(Box::new(stream.take(len as u64))))
+            BodyInner::Reader(stream, Some(len), _) => {
+                BodyReader::new(BodyReaderInner::Reader(Box::new(stream.take(len as u64))))
+            }
+            BodyInner::Reader(stream, None, _) => BodyReader::new(BodyReaderInner::Reader(stream)),
+            BodyInner::File(file, len) => {
+                BodyReader::new(BodyReaderInner::Reader(Box::new(file.take(len as u64))))
             }
-            BodyInner::Reader(stream, None) => BodyReader(BodyReaderInner::Reader(stream)),
         }
     }
 
@@ -184,16 +1423,22 @@ impl HttpBody for Body {
                 .into_iter()
                 .flatten()
                 .collect()),
-            BodyInner::Reader(stream, Some(len)) => {
-                let mut buf = Vec::with_capacity(len);
+            BodyInner::Reader(stream, Some(len), buffer_size) => {
+                // The length may come from the peer, so don't trust it for the allocation
+                let mut buf = Vec::with_capacity(len.min(buffer_size));
                 stream.take(len as u64).read_to_end(&mut buf)?;
                 Ok(buf)
             }
-            BodyInner::Reader(mut stream, None) => {
-                let mut buf = Vec::with_capacity(8 * 1024);
+            BodyInner::Reader(mut stream, None, buffer_size) => {
+                let mut buf = Vec::with_capacity(buffer_size);
                 stream.read_to_end(&mut buf)?;
                 Ok(buf)
             }
+            BodyInner::File(file, len) => {
+                let mut buf = Vec::with_capacity(len.min(DEFAULT_BUFFER_SIZE));
+                file.take(len as u64).read_to_end(&mut buf)?;
+                Ok(buf)
+            }
         }
     }
 
@@ -202,8 +1447,23 @@ impl HttpBody for Body {
             BodyInner::Empty => ChunkIterator(None),
             BodyInner::Buffered(bytes) => ChunkIterator(Some(ChunkIteratorInner::Single(bytes))),
             BodyInner::Iter(chunks) => ChunkIterator(Some(ChunkIteratorInner::Iter(chunks))),
-            BodyInner::Reader(reader, len) => {
-                ChunkIterator(Some(ChunkIteratorInner::Reader(reader, len)))
+            BodyInner::Reader(reader, len, buffer_size) => ChunkIterator(Some(
+                ChunkIteratorInner::Reader(reader, len, vec![0_u8; buffer_size]),
+            )),
+            BodyInner::File(file, len) => ChunkIterator(Some(ChunkIteratorInner::Reader(
+                Box::new(file),
+                Some(len),
+                vec![0_u8; DEFAULT_BUFFER_SIZE],
+            ))),
+        }
+    }
+
+    fn into_file(mut self, _: sealed::Token) -> Result<io::Take<File>, Self> {
+        match self.0.take() {
+            Some(BodyInner::File(file, len)) => Ok(file.take(len as u64)),
+            inner => {
+                self.0 = inner;
+                Err(self)
             }
         }
     }
@@ -211,13 +1471,12 @@ impl HttpBody for Body {
 
 impl Drop for Body {
     fn drop(&mut self) {
-        #[allow(unused_must_use)]
         match self.0.take() {
-            Some(BodyInner::Reader(ref mut stream, Some(len))) => {
-                io::copy(&mut stream.take(len as u64), &mut io::sink());
+            Some(BodyInner::Reader(ref mut stream, Some(len), _)) => {
+                drain(&mut stream.take(len as u64));
             }
-            Some(BodyInner::Reader(ref mut stream, None)) => {
-                io::copy(stream, &mut io::sink());
+            Some(BodyInner::Reader(ref mut stream, None, _)) => {
+                drain(stream);
             }
             _ => {}
         }
@@ -248,25 +1507,35 @@ impl From<String> for Body {
     }
 }
 
+/// Files are sent straight from the kernel when possible, without copying their contents through
+/// the server. This relies on the standard library specializing [`io::copy`] from files into plain
+/// sockets, which uses `sendfile` (or `copy_file_range` and `splice`) on Linux. Other platforms and
+/// TLS connections copy the file through userspace.
 impl TryFrom<File> for Body {
     type Error = io::Error;
 
     fn try_from(file: File) -> Result<Self, Self::Error> {
         match file.metadata() {
-            Ok(meta) if meta.is_file() => Ok(Body::from_reader(file, meta.len() as usize)),
-            Ok(_) => Err(io::Error::new(io::ErrorKind::Other, "not a file")),
+            Ok(meta) if meta.is_file() => {
+                Ok(Body(Some(BodyInner::File(file, meta.len() as usize))))
+            }
+            Ok(_) => Err(io::Error::other("not a file")),
             Err(err) => Err(err),
         }
     }
 }
 
 /// Wraps a body and turns into a [`Read`].
-pub struct BodyReader(BodyReaderInner);
+pub struct BodyReader(BodyReaderInner, u64);
 
 impl BodyReader {
+    fn new(inner: BodyReaderInner) -> Self {
+        BodyReader(inner, 0)
+    }
+
     /// Creates a [`BodyReader`] from an [`Read`]
     pub fn from_reader(reader: impl Read + 'static) -> Self {
-        BodyReader(BodyReaderInner::Reader(Box::new(reader)))
+        BodyReader::new(BodyReaderInner::Reader(Box::new(reader)))
     }
 
     /// Creates a [`BodyReader`] from an [`Iterator`]
@@ -274,7 +1543,33 @@ impl BodyReader {
     pub fn from_iter(iter: impl IntoIterator<Item = Vec<u8>> + 'static) -> Self {
         let mut iter = iter.into_iter();
         let cursor = iter.next().map(Cursor::new);
-        BodyReader(BodyReaderInner::Iter(Box::new(iter.map(Ok)), cursor))
+        BodyReader::new(BodyReaderInner::Iter(Box::new(iter.map(Ok)), cursor))
+    }
+
+    /// Returns how many bytes were read so far, e.g. to report the progress of an upload.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::io::{self, Read};
+    /// # use touche::{Body, HttpBody, Request, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+    ///     let mut reader = req.into_body().into_reader();
+    ///     let mut buf = [0; 8 * 1024];
+    ///
+    ///     while reader.read(&mut buf)? > 0 {
+    ///         println!("Received {} bytes", reader.bytes_read());
+    ///     }
+    ///
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(())
+    ///         .map_err(io::Error::other)
+    /// })
+    /// # }
+    /// ```
+    pub fn bytes_read(&self) -> u64 {
+        self.1
     }
 }
 
@@ -289,6 +1584,14 @@ enum BodyReaderInner {
 
 impl Read for BodyReader {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read_inner(buf)?;
+        self.1 += read as u64;
+        Ok(read)
+    }
+}
+
+impl BodyReader {
+    fn read_inner(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self.0 {
             BodyReaderInner::Buffered(ref mut cursor) => cursor.read(buf),
             BodyReaderInner::Reader(ref mut reader) => reader.read(buf),
@@ -300,8 +1603,7 @@ impl Read for BodyReader {
                     if read > 0 {
                         return Ok(read);
                     }
-                    let next = iter.next().and_then(|next| next.ok()).map(Cursor::new);
-                    *leftover = next;
+                    *leftover = iter.next().transpose()?.map(Cursor::new);
                 }
                 Ok(0)
             }
@@ -311,7 +1613,7 @@ impl Read for BodyReader {
 
 impl From<Vec<u8>> for BodyReader {
     fn from(buf: Vec<u8>) -> Self {
-        Self(BodyReaderInner::Buffered(Cursor::new(buf)))
+        Self::new(BodyReaderInner::Buffered(Cursor::new(buf)))
     }
 }
 
@@ -321,21 +1623,22 @@ impl From<Body> for BodyReader {
             BodyInner::Empty => Vec::new().into(),
             BodyInner::Buffered(bytes) => bytes.into(),
             BodyInner::Iter(chunks) => {
-                let mut chunks = chunks.filter_map(|chunk| match chunk {
+                let chunks = chunks.filter_map(|chunk| match chunk {
                     Ok(Chunk::Data(data)) => Some(Ok(data)),
                     Ok(Chunk::Trailers(_)) => None,
                     Err(err) => Some(Err(err)),
                 });
-                let cursor = chunks
-                    .next()
-                    .map(|chunk| chunk.unwrap_or_default())
-                    .map(Cursor::new);
-                BodyReader(BodyReaderInner::Iter(Box::new(chunks), cursor))
+                // The first chunk is only pulled on the first read, so its errors are not lost
+                let cursor = Some(Cursor::new(Vec::new()));
+                BodyReader::new(BodyReaderInner::Iter(Box::new(chunks), cursor))
             }
-            BodyInner::Reader(stream, Some(len)) => {
-                BodyReader(BodyReaderInner::Reader(Box::new(stream.take(len as u64))))
+            BodyInner::Reader(stream, Some(len), _) => {
+                BodyReader::new(BodyReaderInner::Reader(Box::new(stream.take(len as u64))))
+            }
+            BodyInner::Reader(stream, None, _) => BodyReader::new(BodyReaderInner::Reader(stream)),
+            BodyInner::File(file, len) => {
+                BodyReader::new(BodyReaderInner::Reader(Box::new(file.take(len as u64))))
             }
-            BodyInner::Reader(stream, None) => BodyReader(BodyReaderInner::Reader(stream)),
         }
     }
 }
@@ -348,6 +1651,7 @@ impl ChunkIterator {
         Self(Some(ChunkIteratorInner::Reader(
             Box::new(reader),
             length.into(),
+            vec![0_u8; DEFAULT_BUFFER_SIZE],
         )))
     }
 }
@@ -355,7 +1659,7 @@ impl ChunkIterator {
 enum ChunkIteratorInner {
     Single(Vec<u8>),
     Iter(Box<dyn Iterator<Item = io::Result<Chunk>>>),
-    Reader(Box<dyn Read>, Option<usize>),
+    Reader(Box<dyn Read>, Option<usize>, Vec<u8>),
 }
 
 impl Iterator for ChunkIterator {
@@ -365,44 +1669,58 @@ impl Iterator for ChunkIterator {
         match self.0.take()? {
             ChunkIteratorInner::Single(bytes) => Some(Ok(bytes.into())),
             ChunkIteratorInner::Iter(mut iter) => {
-                let item = iter.next()?.ok()?;
-                self.0 = Some(ChunkIteratorInner::Iter(iter));
-                Some(Ok(item))
+                // Errors end the stream
+                let item = iter.next()?;
+                if item.is_ok() {
+                    self.0 = Some(ChunkIteratorInner::Iter(iter));
+                }
+                Some(item)
             }
-            ChunkIteratorInner::Reader(mut reader, Some(len)) => {
-                let mut buf = [0_u8; 8 * 1024];
-                match reader.read(&mut buf) {
+            ChunkIteratorInner::Reader(mut reader, Some(len), mut buf) => {
+                let max = buf.len().min(len);
+                match reader.read(&mut buf[..max]) {
                     Ok(0) => None,
                     Ok(bytes) => {
+                        let chunk = buf[..bytes].to_vec();
                         self.0 = match len.checked_sub(bytes) {
-                            r @ Some(rem) if rem > 0 => Some(ChunkIteratorInner::Reader(reader, r)),
+                            r @ Some(rem) if rem > 0 => {
+                                Some(ChunkIteratorInner::Reader(reader, r, buf))
+                            }
                             _ => None,
                         };
-                        Some(Ok(buf[0..bytes].to_vec().into()))
+                        Some(Ok(chunk.into()))
                     }
                     Err(err) => Some(Err(err)),
                 }
             }
-            ChunkIteratorInner::Reader(mut reader, None) => {
-                let mut buf = [0_u8; 8 * 1024];
-                match reader.read(&mut buf) {
-                    Ok(0) => None,
-                    Ok(bytes) => {
-                        self.0 = Some(ChunkIteratorInner::Reader(reader, None));
-                        Some(Ok(buf[0..bytes].to_vec().into()))
-                    }
-                    Err(err) => Some(Err(err)),
+            ChunkIteratorInner::Reader(mut reader, None, mut buf) => match reader.read(&mut buf) {
+                Ok(0) => None,
+                Ok(bytes) => {
+                    let chunk = buf[..bytes].to_vec();
+                    self.0 = Some(ChunkIteratorInner::Reader(reader, None, buf));
+                    Some(Ok(chunk.into()))
                 }
-            }
+                Err(err) => Some(Err(err)),
+            },
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Cursor, Read};
+    use std::{
+        io::{self, Cursor, Read, Write},
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use headers::{HeaderMap, HeaderValue};
 
-    use crate::{body::HttpBody, Body};
+    use crate::{
+        body::{sealed, Chunk, HttpBody},
+        Body,
+    };
 
     #[test]
     fn test_body_reader_buffered() {
@@ -496,4 +1814,768 @@ mod tests {
         channel.abort();
         assert!(body.into_bytes().is_err());
     }
+
+    #[test]
+    fn test_reader_chunks_with_buffer_size() {
+        let body = Body::from_reader(Cursor::new(vec![1_u8; 25]), None).with_buffer_size(10);
+
+        let chunks = body
+            .into_chunks()
+            .map(|chunk| match chunk.unwrap() {
+                Chunk::Data(data) => (data.len(), data.capacity()),
+                Chunk::Trailers(_) => (0, 0),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(chunks, vec![(10, 10), (10, 10), (5, 5)]);
+    }
+
+    #[test]
+    fn test_channel_close() {
+        let (channel, body) = Body::channel();
+        channel.send("lol").unwrap();
+        channel.close();
+        assert_eq!(body.into_bytes().unwrap(), b"lol");
+    }
+
+    #[test]
+    fn test_once() {
+        let body = Body::once("lolwut");
+        assert_eq!(body.len(), None);
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+    }
+
+    #[test]
+    fn test_try_clone() {
+        let body = Body::from("lolwut");
+        let clone = body.try_clone().unwrap();
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+        assert_eq!(clone.into_bytes().unwrap(), b"lolwut");
+
+        assert!(Body::empty().try_clone().is_some());
+        assert!(Body::from_iter(vec!["lol"]).try_clone().is_none());
+        assert!(Body::from_reader(Cursor::new(b"lol"), 3)
+            .try_clone()
+            .is_none());
+    }
+
+    #[test]
+    fn test_cursor_body() {
+        let mut cursor = Cursor::new(b"lolwut".to_vec());
+        cursor.set_position(3);
+        assert_eq!(HttpBody::len(&cursor), Some(3));
+        assert_eq!(cursor.into_bytes().unwrap(), b"wut");
+    }
+
+    #[test]
+    fn test_boxed_reader_body() {
+        let reader: Box<dyn Read + Send> = Box::new(Cursor::new(b"lolwut"));
+        assert_eq!(reader.len(), None);
+        assert_eq!(reader.into_bytes().unwrap(), b"lolwut");
+    }
+
+    #[test]
+    fn test_take() {
+        let body = Body::from("lolwut").take(3);
+        assert_eq!(body.len(), Some(3));
+        assert_eq!(body.into_bytes().unwrap(), b"lol");
+
+        let body = Body::from_reader(Cursor::new(b"lolwut"), 6).take(3);
+        assert_eq!(body.len(), Some(3));
+        assert_eq!(body.into_bytes().unwrap(), b"lol");
+
+        let body = Body::from_reader(Cursor::new(b"lolwut"), None).take(3);
+        assert_eq!(body.len(), None);
+        assert_eq!(body.into_bytes().unwrap(), b"lol");
+
+        let body = Body::from("lol").take(10);
+        assert_eq!(body.len(), Some(3));
+        assert_eq!(body.into_bytes().unwrap(), b"lol");
+
+        // Dropping a limited body still drains the rest of it
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"lolwutnext").unwrap();
+        drop(writer);
+        let mut rest = reader.try_clone().unwrap();
+        drop(Body::from_reader(reader, 6).take(3));
+        let mut buf = String::new();
+        rest.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "next");
+
+        let body = Body::default().take(3);
+        assert_eq!(body.len(), Some(0));
+        assert_eq!(body.into_bytes().unwrap(), b"");
+    }
+
+    #[test]
+    fn test_prepend() {
+        let body = Body::from("wut").prepend("lol");
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        let body = Body::empty().prepend("lol");
+        assert_eq!(body.len(), Some(3));
+        assert_eq!(body.into_bytes().unwrap(), b"lol");
+
+        let body = Body::from_reader(Cursor::new(b"wutlol"), 3).prepend("lol");
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        let body = Body::from_reader(Cursor::new(b"wut"), None).prepend("lol");
+        assert_eq!(body.len(), None);
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        let body = Body::from_iter(["w", "ut"]).prepend("lol");
+        assert_eq!(body.len(), None);
+        let chunks = body.into_chunks().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(matches!(&chunks[0], Chunk::Data(data) if data == b"lol"));
+    }
+
+    #[test]
+    fn test_peek_prefix() {
+        let (prefix, body) = Body::from("lolwut").peek_prefix(3).unwrap();
+        assert_eq!(prefix, b"lol");
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        let (prefix, body) = Body::from_reader(Cursor::new(b"lolwutlol"), 6)
+            .peek_prefix(3)
+            .unwrap();
+        assert_eq!(prefix, b"lol");
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        // Shorter than the prefix
+        let (prefix, body) = Body::from_reader(Cursor::new(b"lol"), None)
+            .peek_prefix(8)
+            .unwrap();
+        assert_eq!(prefix, b"lol");
+        assert_eq!(body.len(), None);
+        assert_eq!(body.into_bytes().unwrap(), b"lol");
+
+        let (channel, body) = Body::channel();
+        channel.send("lo").unwrap();
+        channel.send("lwut").unwrap();
+        channel.send_trailer("x-checksum", "123").unwrap();
+        drop(channel);
+
+        let (prefix, body) = body.peek_prefix(3).unwrap();
+        assert_eq!(prefix, b"lol");
+        let (bytes, trailers) = body.into_bytes_with_trailers().unwrap();
+        assert_eq!(bytes, b"lolwut");
+        assert_eq!(trailers["x-checksum"], "123");
+    }
+
+    #[test]
+    fn test_into_channel() {
+        let (channel, body) = Body::channel();
+        let rx = body.into_channel(1);
+
+        channel.send("lol").unwrap();
+        channel.send("wut").unwrap();
+        channel.send_trailer("x-checksum", "123").unwrap();
+        drop(channel);
+
+        let data = rx.iter().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(data, [b"lol".to_vec(), b"wut".to_vec()]);
+
+        // Only reads ahead of the receiver up to the capacity
+        let (read_tx, read_rx) = mpsc::channel();
+        let chunks = (0..10).map(move |_| {
+            read_tx.send(()).unwrap();
+            "lol"
+        });
+        let rx = Body::from_iter(chunks).into_channel(2);
+
+        thread::sleep(Duration::from_millis(100));
+        // Two chunks queued, plus the one blocked on sending
+        assert_eq!(read_rx.try_iter().count(), 3);
+
+        assert_eq!(rx.iter().count(), 10);
+    }
+
+    #[test]
+    fn test_drain() {
+        assert_eq!(Body::empty().drain().unwrap(), 0);
+        assert_eq!(Body::from("lolwut").drain().unwrap(), 6);
+        assert_eq!(Body::from_iter(["lol", "wut"]).drain().unwrap(), 6);
+
+        // Only drains up to the length of the body
+        let body = Body::from_reader(Cursor::new(b"lolwut".to_vec()), 3);
+        assert_eq!(body.drain().unwrap(), 3);
+
+        let err = Body::from_reader(Cursor::new(b"lol".to_vec()), 6)
+            .drain()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let err = Body::try_from_iter([Ok("lol".into()), Err(io::Error::other("wut"))])
+            .drain()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "wut");
+    }
+
+    #[test]
+    fn test_take_chunks() {
+        let (channel, body) = Body::channel();
+        channel.send("lol").unwrap();
+        channel.send("wut").unwrap();
+        channel.send_trailer("x-checksum", "123").unwrap();
+        drop(channel);
+
+        let chunks = body.take(4).into_chunks().collect::<io::Result<Vec<_>>>();
+        let chunks = chunks.unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert!(matches!(&chunks[0], Chunk::Data(data) if data == b"lol"));
+        assert!(matches!(&chunks[1], Chunk::Data(data) if data == b"w"));
+    }
+
+    #[test]
+    fn test_buffered() {
+        let body = Body::from_iter(vec!["lol", "wut"]).buffered().unwrap();
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        let body = Body::from_reader(Cursor::new(b"lolwut"), None)
+            .buffered()
+            .unwrap();
+        assert_eq!(body.len(), Some(6));
+
+        let body = Body::try_from_iter(vec![Ok(b"lol".to_vec()), Err(io::ErrorKind::Other.into())]);
+        assert!(body.buffered().is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_from_child_stdout() {
+        use std::process::{Command, Stdio};
+
+        let child = Command::new("echo")
+            .arg("lolwut")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let body = Body::from_child_stdout(child);
+        assert_eq!(body.len(), None);
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut\n");
+
+        let child = Command::new("sleep")
+            .arg("60")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        // Dropping the body kills and reaps the child, so it is gone rather than a zombie
+        drop(Body::from_child_stdout(child));
+        assert!(!std::path::Path::new(&format!("/proc/{pid}")).exists());
+
+        // Children are left to finish once their whole output was read
+        let marker = std::env::temp_dir().join(format!("touche-child-{}", std::process::id()));
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(format!(
+                "echo lolwut; exec >&-; sleep 0.2; touch {}",
+                marker.display()
+            ))
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let body = Body::from_child_stdout(child);
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut\n");
+        assert!(marker.exists());
+        std::fs::remove_file(marker).unwrap();
+    }
+
+    #[test]
+    fn test_from_paths() {
+        let dir = std::env::temp_dir().join(format!("touche-paths-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lol"), b"lol").unwrap();
+        std::fs::write(dir.join("empty"), b"").unwrap();
+        std::fs::write(dir.join("wut"), b"wut").unwrap();
+
+        let body = Body::from_paths([dir.join("lol"), dir.join("empty"), dir.join("wut")]).unwrap();
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        let body = Body::from_paths([dir.join("lol"), dir.join("wut")]).unwrap();
+        std::fs::write(dir.join("wut"), b"w").unwrap();
+        assert!(body.into_bytes().is_err());
+
+        assert!(Body::from_paths([dir.join("lol"), dir.join("missing")]).is_err());
+        assert!(Body::from_paths([&dir]).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_from_mmap() {
+        let path = std::env::temp_dir().join(format!("touche-mmap-{}", std::process::id()));
+        std::fs::write(&path, b"lolwut").unwrap();
+
+        let body = unsafe { Body::from_mmap(&path).unwrap() };
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        std::fs::write(&path, b"").unwrap();
+        let body = unsafe { Body::from_mmap(&path).unwrap() };
+        assert_eq!(body.len(), Some(0));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(unsafe { Body::from_mmap(&path) }.is_err());
+    }
+
+    #[test]
+    fn test_from_receiver() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(b"lol".to_vec()).unwrap();
+        tx.send(b"wut".to_vec()).unwrap();
+        drop(tx);
+
+        let body = Body::from_receiver(rx);
+        assert_eq!(body.len(), None);
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+    }
+
+    #[test]
+    fn test_from_receiver_chunks() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(Chunk::Data(b"lol".to_vec())).unwrap();
+        let mut trailers = headers::HeaderMap::new();
+        trailers.insert("x-checksum", "123".parse().unwrap());
+        tx.send(Chunk::Trailers(trailers)).unwrap();
+        drop(tx);
+
+        let chunks = Body::from_receiver_chunks(rx)
+            .into_chunks()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert!(matches!(&chunks[0], Chunk::Data(data) if data == b"lol"));
+        assert!(matches!(&chunks[1], Chunk::Trailers(trailers) if trailers["x-checksum"] == "123"));
+    }
+
+    #[test]
+    fn test_body_from_reader_chunked() {
+        let body = Body::from_reader_chunked(Cursor::new(b"lolwut".to_vec()), 4);
+        assert_eq!(body.len(), None);
+
+        let chunks = body
+            .into_chunks()
+            .map(|chunk| match chunk.unwrap() {
+                Chunk::Data(data) => data,
+                Chunk::Trailers(_) => panic!("unexpected trailers"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, vec![b"lolw".to_vec(), b"ut".to_vec()]);
+    }
+
+    #[test]
+    fn test_body_lines() {
+        let body = Body::from_iter(["lol\r", "\nwut\n\nlol", "wut"]);
+        let lines = body.lines().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(lines, vec!["lol", "wut", "", "lolwut"]);
+
+        let body = Body::from("lol\n");
+        let lines = body.lines().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(lines, vec!["lol"]);
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_body_from_ndjson() {
+        let body = Body::from_ndjson([serde_json::json!({ "lol": 1 }), serde_json::json!("wut")]);
+        assert_eq!(body.len(), None);
+        assert_eq!(body.into_bytes().unwrap(), b"{\"lol\":1}\n\"wut\"\n");
+    }
+
+    #[test]
+    fn test_body_ensure_length() {
+        let (len, body) = Body::from_reader(Cursor::new(b"lolwut".to_vec()), 6)
+            .ensure_length()
+            .unwrap();
+        assert_eq!(len, 6);
+        assert!(body.try_clone().is_none());
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        let (len, body) = Body::from_iter(["lol", "wut"]).ensure_length().unwrap();
+        assert_eq!(len, 6);
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+    }
+
+    #[test]
+    fn test_body_with_computed_trailers() {
+        let count = |count: usize| {
+            let mut trailers = headers::HeaderMap::new();
+            trailers.insert("x-count", count.into());
+            trailers
+        };
+
+        let body = Body::from_reader(Cursor::new(b"lolwut".to_vec()), 6)
+            .with_buffer_size(4)
+            .with_computed_trailers(0, |count, data| *count += data.len(), count);
+        assert_eq!(body.len(), None);
+
+        let chunks = body.into_chunks().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(matches!(chunks[0], Chunk::Data(ref data) if data == b"lolw"));
+        assert!(matches!(chunks[1], Chunk::Data(ref data) if data == b"ut"));
+        assert!(matches!(chunks[2], Chunk::Trailers(ref trailers) if trailers["x-count"] == "6"));
+
+        let mut existing = headers::HeaderMap::new();
+        existing.insert("x-lol", "wut".parse().unwrap());
+        let body = Body::from_iter([Chunk::Data(b"lol".to_vec()), Chunk::Trailers(existing)])
+            .with_computed_trailers(0, |count, data| *count += data.len(), count);
+
+        let chunks = body.into_chunks().collect::<io::Result<Vec<_>>>().unwrap();
+        assert!(matches!(chunks[1], Chunk::Trailers(ref trailers)
+            if trailers["x-lol"] == "wut" && trailers["x-count"] == "3"));
+
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"lolwutnext").unwrap();
+        drop(writer);
+        let mut rest = reader.try_clone().unwrap();
+        drop(Body::from_reader(reader, 6).with_computed_trailers(0, |_, _| {}, count));
+        let mut buf = String::new();
+        rest.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "next");
+    }
+
+    #[test]
+    fn test_body_does_not_trust_the_length_for_allocations() {
+        let body = Body::from_reader(Cursor::new(b"lolwut".to_vec()), usize::MAX / 2);
+        assert_eq!(body.into_bytes().unwrap(), b"lolwut");
+
+        let body = Body::from_reader(io::repeat(0).take(1024 * 1024), usize::MAX / 2);
+        drop(body);
+    }
+
+    #[test]
+    fn test_boxed_bodies() {
+        let bodies = vec![
+            "lol".boxed(),
+            Body::from_iter(["lol", "wut"]).boxed(),
+            Body::from(b"lolwut".to_vec()).boxed().boxed(),
+        ];
+
+        let lens = bodies.iter().map(|body| body.len()).collect::<Vec<_>>();
+        assert_eq!(lens, vec![Some(3), None, Some(6)]);
+
+        let bytes = bodies
+            .into_iter()
+            .map(|body| body.into_bytes().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bytes,
+            vec![b"lol".to_vec(), b"lolwut".to_vec(), b"lolwut".to_vec()]
+        );
+
+        let mut reader = Body::from("lolwut").boxed().into_reader();
+        let mut buf = String::new();
+        reader.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "lolwut");
+    }
+
+    #[test]
+    fn test_body_from_file() {
+        let path = std::env::temp_dir().join(format!("touche-body-{}", std::process::id()));
+        std::fs::write(&path, "lolwut").unwrap();
+
+        let body = Body::try_from(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(body.len(), Some(6));
+        assert_eq!(body.take(3).into_bytes().unwrap(), b"lol");
+
+        let body = Body::try_from(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut file = body.into_file(sealed::Token).unwrap();
+        let mut buf = String::new();
+        file.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "lolwut");
+
+        assert!(Body::from("lolwut").into_file(sealed::Token).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_body_reader_bytes_read() {
+        let bodies = [
+            Body::from("lolwut"),
+            Body::from_iter(vec!["lol", "wut"]),
+            Body::from_reader(Cursor::new(b"lolwut"), None),
+        ];
+
+        for body in bodies {
+            let mut reader = body.into_reader();
+            assert_eq!(reader.bytes_read(), 0);
+
+            let mut buf = [0; 2];
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(reader.bytes_read(), 2);
+
+            reader.read_to_end(&mut Vec::new()).unwrap();
+            assert_eq!(reader.bytes_read(), 6);
+        }
+    }
+
+    #[test]
+    fn test_body_try_from_iter_errors() {
+        let chunks = || {
+            vec![
+                Ok(b"lol".to_vec()),
+                Err(io::Error::other("lolwut")),
+                Ok(b"wut".to_vec()),
+            ]
+        };
+
+        let err = Body::try_from_iter(chunks()).into_bytes().unwrap_err();
+        assert_eq!(err.to_string(), "lolwut");
+
+        let mut reader = Body::try_from_iter(chunks()).into_reader();
+        let mut buf = Vec::new();
+        assert!(reader.read_to_end(&mut buf).is_err());
+        assert_eq!(buf, b"lol");
+
+        let mut reader = Body::try_from_iter(vec![Err(io::Error::other("lolwut"))]).into_reader();
+        assert!(reader.read(&mut [0; 8]).is_err());
+
+        let chunks = Body::try_from_iter(chunks())
+            .into_chunks()
+            .collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].is_ok());
+        assert!(chunks[1].is_err());
+    }
+
+    #[test]
+    fn test_body_debug() {
+        assert_eq!(format!("{:?}", Body::empty()), "Body(Empty)");
+        assert_eq!(format!("{:?}", Body::from(Vec::new())), "Body(Buffered(0))");
+        assert_eq!(
+            format!("{:?}", Body::from("lol")),
+            "Body(Buffered(3, 6c6f6c))"
+        );
+        assert_eq!(
+            format!("{:?}", Body::from("lolwut lolwut")),
+            "Body(Buffered(13, 6c6f6c777574206c..))"
+        );
+        assert_eq!(format!("{:?}", Body::from_iter(["lol"])), "Body(Iter)");
+        assert_eq!(
+            format!("{:?}", Body::from_reader(Cursor::new(b"lol"), 3)),
+            "Body(Reader(Some(3)))"
+        );
+        assert_eq!(
+            format!("{:?}", Body::from_reader(Cursor::new(b"lol"), None)),
+            "Body(Reader(None))"
+        );
+
+        assert_eq!(format!("{:?}", Chunk::from("lol")), "Data(3, 6c6f6c)");
+        let mut trailers = HeaderMap::new();
+        trailers.insert("lol", HeaderValue::from_static("wut"));
+        assert_eq!(
+            format!("{:?}", Chunk::Trailers(trailers)),
+            r#"Trailers({"lol": "wut"})"#
+        );
+    }
+
+    #[test]
+    fn test_body_with_trailers() {
+        let (sender, body) = Body::channel();
+        let mut inner = HeaderMap::new();
+        inner.insert("lol", HeaderValue::from_static("inner"));
+        inner.insert("wut", HeaderValue::from_static("inner"));
+        sender.send("lol").unwrap();
+        sender.send_trailers(inner).unwrap();
+        drop(sender);
+
+        let mut extra = HeaderMap::new();
+        extra.insert("wut", HeaderValue::from_static("extra"));
+        extra.insert("lolwut", HeaderValue::from_static("extra"));
+
+        let body = body.with_trailers(extra);
+        assert_eq!(body.len(), None);
+
+        let mut chunks = body.into_chunks();
+        assert!(matches!(chunks.next(), Some(Ok(Chunk::Data(data))) if data == b"lol"));
+        match chunks.next() {
+            Some(Ok(Chunk::Trailers(trailers))) => {
+                assert_eq!(trailers.len(), 3);
+                assert_eq!(trailers["lol"], "inner");
+                assert_eq!(trailers["wut"], "extra");
+                assert_eq!(trailers["lolwut"], "extra");
+            }
+            chunk => panic!("expected trailers, got {chunk:?}"),
+        }
+        assert!(chunks.next().is_none());
+
+        let body = Body::from("lol").with_trailers(HeaderMap::new());
+        assert_eq!(body.into_bytes().unwrap(), b"lol");
+
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"lolwutnext").unwrap();
+        drop(writer);
+        let mut rest = reader.try_clone().unwrap();
+        drop(Body::from_reader(reader, 6).with_trailers(HeaderMap::new()));
+        let mut buf = String::new();
+        rest.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "next");
+    }
+
+    #[test]
+    fn test_body_throttle() {
+        use std::time::{Duration, Instant};
+
+        let start = Instant::now();
+        let body = Body::from(vec![b'a'; 300]).throttle(1000);
+        assert_eq!(body.len(), Some(300));
+        assert_eq!(body.into_bytes().unwrap(), vec![b'a'; 300]);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("lol", HeaderValue::from_static("wut"));
+        let body = Body::from_iter(vec![vec![b'a'; 150], vec![b'b'; 50]])
+            .with_trailers(trailers)
+            .throttle(1000);
+        assert_eq!(body.len(), None);
+
+        let start = Instant::now();
+        let chunks = body.into_chunks().collect::<io::Result<Vec<_>>>().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let sizes = chunks
+            .iter()
+            .map(|chunk| match chunk {
+                Chunk::Data(data) => data.len(),
+                Chunk::Trailers(_) => 0,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [100, 50, 50, 0]);
+        assert!(
+            matches!(chunks.last(), Some(Chunk::Trailers(trailers)) if trailers["lol"] == "wut")
+        );
+
+        // Unsent bodies are drained right away
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(&[b'a'; 3000]).unwrap();
+        writer.write_all(b"next").unwrap();
+        drop(writer);
+        let mut rest = reader.try_clone().unwrap();
+
+        let start = Instant::now();
+        drop(Body::from_reader(reader, 3000).throttle(1000));
+        drop(Body::from(vec![b'a'; 3000]).throttle(1000));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let mut buf = String::new();
+        rest.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "next");
+    }
+
+    #[test]
+    fn test_body_from_reader_with_trailer_fn() {
+        let body = Body::from_reader_with_trailer_fn(
+            Cursor::new(b"lolwut".repeat(3)),
+            4,
+            md5::Context::new(),
+            |digest, chunk| digest.consume(chunk),
+            |digest| {
+                let mut trailers = HeaderMap::new();
+                let digest = format!("{:x}", digest.compute());
+                trailers.insert("x-md5", digest.parse().unwrap());
+                trailers
+            },
+        );
+        assert_eq!(body.len(), None);
+
+        let chunks = body.into_chunks().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(chunks.len(), 6);
+
+        let data = chunks
+            .iter()
+            .filter_map(|chunk| match chunk {
+                Chunk::Data(data) => Some(data.as_slice()),
+                Chunk::Trailers(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(data.iter().all(|chunk| <[u8]>::len(chunk) <= 4));
+        assert_eq!(data.concat(), b"lolwut".repeat(3));
+
+        let digest = format!("{:x}", md5::compute(b"lolwut".repeat(3)));
+        assert!(matches!(
+            chunks.last(),
+            Some(Chunk::Trailers(trailers)) if trailers["x-md5"] == digest.as_str()
+        ));
+    }
+
+    #[test]
+    fn test_body_into_string() {
+        assert_eq!(Body::from("lolwut").into_string(6).unwrap(), "lolwut");
+        assert_eq!(Body::empty().into_string(0).unwrap(), "");
+        assert_eq!(
+            Body::from_iter(["lol", "wut"]).into_string(6).unwrap(),
+            "lolwut"
+        );
+
+        let err = Body::from("lolwut").into_string(5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = Body::from_iter(["lol", "wut"]).into_string(5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = Body::from(vec![0xff, 0xfe]).into_string(10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_body_into_string_with_charset() {
+        let content_type = |value| Some(HeaderValue::from_static(value));
+
+        let utf8 = content_type("text/plain; charset=UTF-8");
+        let body = Body::from("café");
+        assert_eq!(
+            body.into_string_with_charset(10, utf8.as_ref()).unwrap(),
+            "café"
+        );
+
+        let latin1 = content_type("text/plain; charset=\"ISO-8859-1\"");
+        let body = Body::from(vec![b'c', b'a', b'f', 0xe9]);
+        assert_eq!(
+            body.into_string_with_charset(10, latin1.as_ref()).unwrap(),
+            "café"
+        );
+
+        let ascii = content_type("text/plain;charset=us-ascii");
+        let body = Body::from("lol");
+        assert_eq!(
+            body.into_string_with_charset(10, ascii.as_ref()).unwrap(),
+            "lol"
+        );
+        let body = Body::from("café");
+        assert!(body.into_string_with_charset(10, ascii.as_ref()).is_err());
+
+        let json = content_type("application/json");
+        let body = Body::from("{}");
+        assert_eq!(
+            body.into_string_with_charset(10, json.as_ref()).unwrap(),
+            "{}"
+        );
+
+        let unsupported = content_type("text/plain; charset=utf-16");
+        let err = Body::from("lol")
+            .into_string_with_charset(10, unsupported.as_ref())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // No limit at all
+        let body = Body::from("lolwut");
+        assert_eq!(
+            body.into_string_with_charset(usize::MAX, utf8.as_ref())
+                .unwrap(),
+            "lolwut"
+        );
+    }
 }
@@ -1,10 +1,20 @@
 use std::{
+    fs::File,
     io::{self, Cursor, Read},
     iter,
 };
 
 use headers::HeaderMap;
 
+use super::{ChunkIterator, Summary};
+
+pub(crate) mod sealed {
+    /// Only nameable inside this crate, keeping the [`HttpBody`](super::HttpBody) methods that
+    /// take it private to the crate.
+    #[derive(Debug)]
+    pub struct Token;
+}
+
 /// Trait representing a streaming body
 pub trait HttpBody: Sized {
     type Reader: Read;
@@ -32,6 +42,115 @@ pub trait HttpBody: Sized {
         self.into_reader().read_to_end(&mut buf)?;
         Ok(buf)
     }
+
+    /// Returns the underlying file of file backed bodies, so they can be sent without copying
+    /// their contents through userspace. Takes a [`sealed::Token`], so it can neither be called
+    /// nor overridden outside of this crate.
+    #[doc(hidden)]
+    fn into_file(self, _: sealed::Token) -> Result<io::Take<File>, Self> {
+        Err(self)
+    }
+
+    /// Erases the type of this body, so bodies of different types can be used interchangeably.
+    fn boxed(self) -> BoxBody
+    where
+        Self: 'static,
+    {
+        BoxBody::new(self)
+    }
+}
+
+/// A type erased [`HttpBody`], see [`HttpBody::boxed`].
+///
+/// # Example
+/// ```no_run
+/// # use touche::{body::BoxBody, Body, HttpBody, Request, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+///     let body = match req.uri().path() {
+///         "/" => "Hello world".boxed(),
+///         _ => req.into_body().boxed(),
+///     };
+///
+///     Response::builder()
+///         .status(StatusCode::OK)
+///         .body(body)
+/// })
+/// # }
+/// ```
+pub struct BoxBody(Box<dyn DynBody>);
+
+impl BoxBody {
+    /// Boxes the given `body`.
+    pub fn new<B: HttpBody + 'static>(body: B) -> Self {
+        Self(Box::new(body))
+    }
+}
+
+impl std::fmt::Debug for BoxBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("BoxBody").field(&self.0.dyn_len()).finish()
+    }
+}
+
+impl HttpBody for BoxBody {
+    type Reader = Box<dyn Read>;
+    type Chunks = Box<dyn Iterator<Item = io::Result<Chunk>>>;
+
+    fn len(&self) -> Option<u64> {
+        self.0.dyn_len()
+    }
+
+    fn into_reader(self) -> Self::Reader {
+        self.0.dyn_into_reader()
+    }
+
+    fn into_chunks(self) -> Self::Chunks {
+        self.0.dyn_into_chunks()
+    }
+
+    fn into_bytes(self) -> io::Result<Vec<u8>> {
+        self.0.dyn_into_bytes()
+    }
+
+    fn into_file(self, _: sealed::Token) -> Result<io::Take<File>, Self> {
+        self.0.dyn_into_file()
+    }
+
+    fn boxed(self) -> BoxBody {
+        self
+    }
+}
+
+/// Object safe version of [`HttpBody`], backing [`BoxBody`].
+trait DynBody {
+    fn dyn_len(&self) -> Option<u64>;
+    fn dyn_into_reader(self: Box<Self>) -> Box<dyn Read>;
+    fn dyn_into_chunks(self: Box<Self>) -> Box<dyn Iterator<Item = io::Result<Chunk>>>;
+    fn dyn_into_bytes(self: Box<Self>) -> io::Result<Vec<u8>>;
+    fn dyn_into_file(self: Box<Self>) -> Result<io::Take<File>, BoxBody>;
+}
+
+impl<B: HttpBody + 'static> DynBody for B {
+    fn dyn_len(&self) -> Option<u64> {
+        self.len()
+    }
+
+    fn dyn_into_reader(self: Box<Self>) -> Box<dyn Read> {
+        Box::new(self.into_reader())
+    }
+
+    fn dyn_into_chunks(self: Box<Self>) -> Box<dyn Iterator<Item = io::Result<Chunk>>> {
+        Box::new(self.into_chunks())
+    }
+
+    fn dyn_into_bytes(self: Box<Self>) -> io::Result<Vec<u8>> {
+        self.into_bytes()
+    }
+
+    fn dyn_into_file(self: Box<Self>) -> Result<io::Take<File>, BoxBody> {
+        self.into_file(sealed::Token).map_err(BoxBody::new)
+    }
 }
 
 impl HttpBody for () {
@@ -139,8 +258,49 @@ impl HttpBody for Vec<u8> {
     }
 }
 
+impl HttpBody for Cursor<Vec<u8>> {
+    type Reader = Self;
+    type Chunks = iter::Once<io::Result<Chunk>>;
+
+    /// The remaining length, from the current position up to the end of the buffer.
+    fn len(&self) -> Option<u64> {
+        Some((self.get_ref().len() as u64).saturating_sub(self.position()))
+    }
+
+    fn into_reader(self) -> Self::Reader {
+        self
+    }
+
+    fn into_bytes(self) -> io::Result<Vec<u8>> {
+        let position = usize::try_from(self.position()).unwrap_or(usize::MAX);
+        let mut bytes = self.into_inner();
+        bytes.drain(..position.min(bytes.len()));
+        Ok(bytes)
+    }
+
+    fn into_chunks(self) -> Self::Chunks {
+        iter::once(self.into_bytes().map(Chunk::from))
+    }
+}
+
+impl HttpBody for Box<dyn Read + Send> {
+    type Reader = Self;
+    type Chunks = ChunkIterator;
+
+    fn len(&self) -> Option<u64> {
+        None
+    }
+
+    fn into_reader(self) -> Self::Reader {
+        self
+    }
+
+    fn into_chunks(self) -> Self::Chunks {
+        ChunkIterator::from_reader(self, None)
+    }
+}
+
 /// A message of a chunked encoded body.
-#[derive(Debug)]
 pub enum Chunk {
     /// Data chunk.
     Data(Vec<u8>),
@@ -148,6 +308,16 @@ pub enum Chunk {
     Trailers(HeaderMap),
 }
 
+/// Data chunks only show their length and a short prefix of their bytes.
+impl std::fmt::Debug for Chunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Chunk::Data(data) => Summary("Data", data).fmt(f),
+            Chunk::Trailers(trailers) => f.debug_tuple("Trailers").field(trailers).finish(),
+        }
+    }
+}
+
 impl<T: Into<Vec<u8>>> From<T> for Chunk {
     fn from(chunk: T) -> Self {
         Self::Data(chunk.into())
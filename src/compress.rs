@@ -0,0 +1,336 @@
+//! Compression of response bodies for clients that accept it.
+//!
+//! Responses are compressed with `gzip` when the client lists it on its `Accept-Encoding` header
+//! and they match the [`CompressionConfig`] set with
+//! [`ServerBuilder::compression`](crate::server::ServerBuilder::compression). Compressed bodies
+//! are streamed, so their length is not known upfront and they are sent with chunked encoding.
+//!
+//! # Example
+//! ```no_run
+//! use touche::{compress::CompressionConfig, Response, Server, StatusCode};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let compression = CompressionConfig::new()
+//!         .level(9)
+//!         .min_size(4096)
+//!         .deny_content_types(["text/event-stream"]);
+//!
+//!     Server::builder()
+//!         .compression(compression)
+//!         .bind("0.0.0.0:4444")
+//!         .serve(|_req| {
+//!             Response::builder()
+//!                 .status(StatusCode::OK)
+//!                 .header("content-type", "text/plain")
+//!                 .body("lolwut ".repeat(1024))
+//!         })
+//! }
+//! ```
+use std::io::{self, Read};
+
+use flate2::{read::GzEncoder, Compression};
+use http::{header, HeaderMap, HeaderValue, Response, StatusCode};
+
+use crate::{body::Chunk, upgrade::UpgradeExtension, HttpBody};
+
+/// Response compression configuration.
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    level: u32,
+    min_size: u64,
+    allowed: Vec<String>,
+    denied: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            level: 6,
+            min_size: 1024,
+            allowed: [
+                "text/*",
+                "application/javascript",
+                "application/json",
+                "application/xml",
+                "image/svg+xml",
+            ]
+            .map(String::from)
+            .to_vec(),
+            denied: Vec::new(),
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Creates a configuration compressing text, JavaScript, JSON, XML and SVG responses of at
+    /// least 1KiB, with a level of `6`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `gzip` compression level, from `0` (no compression) to `9` (smallest output, but
+    /// the most CPU intensive).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` is greater than `9`.
+    pub fn level(self, level: u32) -> Self {
+        assert!(level <= 9, "compression level must be between 0 and 9");
+        Self { level, ..self }
+    }
+
+    /// Sets the minimum length of the bodies to compress, as compressing small bodies is often not
+    /// worth it. Bodies of unknown length are always compressed.
+    pub fn min_size(self, min_size: u64) -> Self {
+        Self { min_size, ..self }
+    }
+
+    /// Replaces the content types that are compressed. Types ending in `/*` match any subtype,
+    /// such as `text/*`.
+    pub fn allow_content_types<T: Into<String>>(self, types: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            allowed: types.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Sets content types that are never compressed, even if allowed. Types ending in `/*` match
+    /// any subtype.
+    pub fn deny_content_types<T: Into<String>>(self, types: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            denied: types.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Whether a response should be compressed according to this configuration.
+    pub(crate) fn applies<B: HttpBody>(&self, res: &Response<B>) -> bool {
+        let headers = res.headers();
+        let status = res.status();
+
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_RANGE)
+            || has_token(headers, header::CACHE_CONTROL, "no-transform")
+            || res.extensions().get::<UpgradeExtension>().is_some()
+            || res.body().len().is_some_and(|len| len < self.min_size)
+        {
+            return false;
+        }
+
+        let content_type = match headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(content_type) => content_type.split(';').next().unwrap_or_default().trim(),
+            None => return false,
+        };
+
+        let matches = |pattern: &String| match pattern.strip_suffix("/*") {
+            Some(kind) => content_type
+                .split_once('/')
+                .is_some_and(|(ty, _)| ty.eq_ignore_ascii_case(kind)),
+            None => content_type.eq_ignore_ascii_case(pattern),
+        };
+
+        !self.denied.iter().any(matches) && self.allowed.iter().any(matches)
+    }
+
+    /// Compresses the body of a response, updating its headers accordingly.
+    pub(crate) fn compress<B: HttpBody>(&self, res: Response<B>) -> Response<Gzip<B::Reader>> {
+        let (mut parts, body) = res.into_parts();
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts
+            .headers
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        if !has_token(&parts.headers, header::VARY, "accept-encoding") {
+            parts
+                .headers
+                .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+
+        // The compressed representation is no longer byte for byte identical
+        if let Some(etag) = parts.headers.get(header::ETAG) {
+            if etag.as_bytes().starts_with(b"\"") {
+                let mut weak = b"W/".to_vec();
+                weak.extend_from_slice(etag.as_bytes());
+                if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                    parts.headers.insert(header::ETAG, weak);
+                }
+            }
+        }
+
+        let body = Gzip(GzEncoder::new(
+            body.into_reader(),
+            Compression::new(self.level),
+        ));
+        Response::from_parts(parts, body)
+    }
+}
+
+/// Whether the request headers list `gzip` as an acceptable encoding.
+pub(crate) fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let rejected = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            !rejected
+                && ["gzip", "x-gzip", "*"]
+                    .iter()
+                    .any(|accepted| name.eq_ignore_ascii_case(accepted))
+        })
+}
+
+fn has_token(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// A body compressed with `gzip` as it is read. Trailers of the original body are dropped.
+pub(crate) struct Gzip<R: Read>(GzEncoder<R>);
+
+impl<R: Read> HttpBody for Gzip<R> {
+    type Reader = GzEncoder<R>;
+    type Chunks = GzipChunks<R>;
+
+    fn len(&self) -> Option<u64> {
+        None
+    }
+
+    fn into_reader(self) -> Self::Reader {
+        self.0
+    }
+
+    fn into_chunks(self) -> Self::Chunks {
+        GzipChunks(Some(self.0))
+    }
+}
+
+pub(crate) struct GzipChunks<R: Read>(Option<GzEncoder<R>>);
+
+impl<R: Read> Iterator for GzipChunks<R> {
+    type Item = io::Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let encoder = self.0.as_mut()?;
+        let mut buf = vec![0; crate::body::DEFAULT_BUFFER_SIZE];
+
+        match encoder.read(&mut buf) {
+            Ok(0) => {
+                self.0 = None;
+                None
+            }
+            Ok(read) => {
+                buf.truncate(read);
+                Some(Ok(buf.into()))
+            }
+            Err(err) => {
+                self.0 = None;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn response(content_type: &str, body: impl Into<String>) -> Response<String> {
+        Response::builder()
+            .header("content-type", content_type)
+            .body(body.into())
+            .unwrap()
+    }
+
+    fn compressed_size(config: &CompressionConfig, body: &str) -> usize {
+        let res = config.compress(response("text/plain", body));
+        assert_eq!(res.headers()["content-encoding"], "gzip");
+        let compressed = res.into_body().into_bytes().unwrap();
+
+        let mut decompressed = String::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body);
+
+        compressed.len()
+    }
+
+    #[test]
+    fn compresses_with_the_configured_level() {
+        // Repetitive but not trivially so, giving the slower levels room to do better
+        let mut seed = 42_u32;
+        let body = (0..20_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ["lol", "wut", "lolwut", "wutlol", " ", "\n"][(seed >> 16) as usize % 6]
+            })
+            .collect::<String>();
+
+        let fast = compressed_size(&CompressionConfig::new().level(1), &body);
+        let best = compressed_size(&CompressionConfig::new().level(9), &body);
+
+        assert!(
+            best < fast,
+            "level 9 ({best}) not smaller than level 1 ({fast})"
+        );
+    }
+
+    #[test]
+    fn only_compresses_matching_responses() {
+        let config = CompressionConfig::new().min_size(3);
+
+        assert!(config.applies(&response("text/html; charset=utf-8", "lolwut")));
+        assert!(config.applies(&response("application/json", "lolwut")));
+        assert!(!config.applies(&response("image/png", "lolwut")));
+        assert!(!config.applies(&response("text/plain", "lo")));
+
+        let config = config.deny_content_types(["text/event-stream"]);
+        assert!(!config.applies(&response("text/event-stream", "lolwut")));
+
+        let config = config.allow_content_types(["image/*"]);
+        assert!(config.applies(&response("image/png", "lolwut")));
+        assert!(!config.applies(&response("text/plain", "lolwut")));
+
+        let mut res = response("image/png", "lolwut");
+        res.headers_mut()
+            .insert("content-encoding", HeaderValue::from_static("br"));
+        assert!(!config.applies(&res));
+    }
+
+    #[test]
+    fn checks_the_accepted_encodings() {
+        let accepts = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(value));
+            accepts_gzip(&headers)
+        };
+
+        assert!(accepts("gzip, deflate, br"));
+        assert!(accepts("br;q=1.0, GZIP;q=0.5"));
+        assert!(accepts("*"));
+        assert!(!accepts("br, deflate"));
+        assert!(!accepts("gzip;q=0"));
+        assert!(!accepts_gzip(&HeaderMap::new()));
+    }
+}
@@ -1,13 +1,15 @@
 use std::{
     any::{Any, TypeId},
     io::{self, Read, Write},
-    net::{SocketAddr, TcpStream},
+    net::{Shutdown, SocketAddr, TcpStream},
     time::Duration,
 };
 
 #[cfg(feature = "unix-sockets")]
 use std::os::unix::net::UnixStream;
 
+#[cfg(feature = "server")]
+use crate::test::MemoryStream;
 #[cfg(feature = "rustls")]
 use crate::tls::RustlsConnection;
 
@@ -22,9 +24,95 @@ enum ConnectionInner {
     Unix(UnixStream),
     #[cfg(feature = "rustls")]
     Rustls(RustlsConnection),
+    #[cfg(feature = "server")]
+    Memory(MemoryStream),
+}
+
+/// Transport independent information about the [`Connection`] a request was received from.
+///
+/// The server makes it available on every request extensions. The peer address is also inserted
+/// on its own as a [`SocketAddr`], except for Unix sockets, which have none.
+///
+/// # Example
+/// ```no_run
+/// # use std::net::SocketAddr;
+/// # use touche::{Body, ConnectionInfo, Request, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+///     let conn = req.extensions().get::<ConnectionInfo>().unwrap();
+///     let peer_addr = req.extensions().get::<SocketAddr>();
+///     Response::builder()
+///         .status(StatusCode::OK)
+///         .body(format!("Peer: {:?} (tls: {})", peer_addr, conn.is_tls()))
+/// })
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionInfo {
+    peer_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    tls: bool,
+    server_name: Option<String>,
+    alpn_protocol: Option<Vec<u8>>,
+}
+
+impl ConnectionInfo {
+    /// The address of the remote peer. Not available on Unix sockets.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The local address of the connection. Not available on Unix sockets.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Whether the connection is encrypted with TLS.
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+
+    /// The server name (SNI) sent by the client on the TLS handshake.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// The protocol negotiated with ALPN on the TLS handshake.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
 }
 
 impl Connection {
+    /// Returns the [`ConnectionInfo`] of this connection.
+    pub fn info(&self) -> ConnectionInfo {
+        let info = ConnectionInfo {
+            peer_addr: self.peer_addr(),
+            local_addr: self.local_addr(),
+            ..Default::default()
+        };
+
+        match self.0 {
+            #[cfg(feature = "rustls")]
+            ConnectionInner::Rustls(ref tls) => ConnectionInfo {
+                tls: true,
+                server_name: tls.server_name(),
+                alpn_protocol: tls.alpn_protocol(),
+                ..info
+            },
+            _ => info,
+        }
+    }
+
+    /// Whether this connection is encrypted with TLS.
+    pub fn is_tls(&self) -> bool {
+        match self.0 {
+            #[cfg(feature = "rustls")]
+            ConnectionInner::Rustls(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn peer_addr(&self) -> Option<SocketAddr> {
         match self.0 {
             ConnectionInner::Tcp(ref tcp) => tcp.peer_addr().ok(),
@@ -32,6 +120,8 @@ impl Connection {
             ConnectionInner::Unix(_) => None,
             #[cfg(feature = "rustls")]
             ConnectionInner::Rustls(ref tls) => tls.peer_addr().ok(),
+            #[cfg(feature = "server")]
+            ConnectionInner::Memory(_) => None,
         }
     }
 
@@ -42,6 +132,8 @@ impl Connection {
             ConnectionInner::Unix(_) => None,
             #[cfg(feature = "rustls")]
             ConnectionInner::Rustls(ref tls) => tls.local_addr().ok(),
+            #[cfg(feature = "server")]
+            ConnectionInner::Memory(_) => None,
         }
     }
 
@@ -52,6 +144,129 @@ impl Connection {
             ConnectionInner::Unix(ref unix) => unix.set_read_timeout(timeout),
             #[cfg(feature = "rustls")]
             ConnectionInner::Rustls(ref tls) => tls.set_read_timeout(timeout),
+            #[cfg(feature = "server")]
+            ConnectionInner::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Sets the write timeout of the connection. Writes blocking for longer than `timeout` fail
+    /// with [`io::ErrorKind::WouldBlock`] or [`io::ErrorKind::TimedOut`], depending on the
+    /// platform.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self.0 {
+            ConnectionInner::Tcp(ref tcp) => tcp.set_write_timeout(timeout),
+            #[cfg(feature = "unix-sockets")]
+            ConnectionInner::Unix(ref unix) => unix.set_write_timeout(timeout),
+            #[cfg(feature = "rustls")]
+            ConnectionInner::Rustls(ref tls) => tls.set_write_timeout(timeout),
+            #[cfg(feature = "server")]
+            ConnectionInner::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Sets the `TCP_NODELAY` option, disabling Nagle's algorithm so small writes are sent right
+    /// away. Does nothing on connections not backed by TCP.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        match self.0 {
+            ConnectionInner::Tcp(ref tcp) => tcp.set_nodelay(nodelay),
+            #[cfg(feature = "unix-sockets")]
+            ConnectionInner::Unix(_) => Ok(()),
+            #[cfg(feature = "rustls")]
+            ConnectionInner::Rustls(ref tls) => tls.set_nodelay(nodelay),
+            #[cfg(feature = "server")]
+            ConnectionInner::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Reads data from the connection without removing it from the queue, so the next read
+    /// returns it again. Useful for detecting the protocol spoken by the client, e.g. telling TLS
+    /// handshakes apart from plaintext HTTP on the same port.
+    ///
+    /// Only supported on plain TCP connections, as there's no meaningful way to peek at the
+    /// plaintext of TLS connections.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::net::TcpListener;
+    /// # use touche::Connection;
+    /// # fn main() -> std::io::Result<()> {
+    /// # let listener = TcpListener::bind("0.0.0.0:4444")?;
+    /// let connection = Connection::from(listener.accept()?);
+    ///
+    /// let mut buf = [0; 1];
+    /// if connection.peek(&mut buf)? == 1 && buf[0] == 0x16 {
+    ///     println!("Looks like a TLS handshake");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0 {
+            ConnectionInner::Tcp(ref tcp) => tcp.peek(buf),
+            #[cfg(feature = "unix-sockets")]
+            ConnectionInner::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "peeking is not supported on unix sockets",
+            )),
+            #[cfg(feature = "rustls")]
+            ConnectionInner::Rustls(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "peeking is not supported on tls connections",
+            )),
+            #[cfg(feature = "server")]
+            ConnectionInner::Memory(ref memory) => memory.peek(buf),
+        }
+    }
+
+    /// Finishes the TLS handshake of encrypted connections, doing nothing on plain ones.
+    #[cfg(feature = "server")]
+    pub(crate) fn complete_handshake(&self) -> io::Result<()> {
+        match self.0 {
+            #[cfg(feature = "rustls")]
+            ConnectionInner::Rustls(ref tls) => tls.complete_handshake(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Copies the `file` into the connection. The kernel copies it directly into plain sockets
+    /// when possible (e.g. with `sendfile` on Linux), otherwise it is copied through userspace.
+    #[cfg(feature = "server")]
+    pub(crate) fn send_file(&mut self, file: &mut io::Take<std::fs::File>) -> io::Result<u64> {
+        match self.0 {
+            // The std specializes copies between these, avoiding the userspace buffers
+            ConnectionInner::Tcp(ref mut tcp) => io::copy(file, tcp),
+            #[cfg(feature = "unix-sockets")]
+            ConnectionInner::Unix(ref mut unix) => io::copy(file, unix),
+            _ => io::copy(file, self),
+        }
+    }
+
+    /// Shuts down the read, write, or both halves of this connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self.0 {
+            ConnectionInner::Tcp(ref tcp) => tcp.shutdown(how),
+            #[cfg(feature = "unix-sockets")]
+            ConnectionInner::Unix(ref unix) => unix.shutdown(how),
+            #[cfg(feature = "rustls")]
+            ConnectionInner::Rustls(ref tls) => tls.shutdown(how),
+            #[cfg(feature = "server")]
+            ConnectionInner::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Makes closing the connection reset it (with a TCP `RST`) instead of shutting it down
+    /// gracefully, so the peer can tell it was aborted. Unsent data is discarded.
+    #[cfg(feature = "server")]
+    pub(crate) fn reset(&self) -> io::Result<()> {
+        match self.0 {
+            ConnectionInner::Tcp(ref tcp) => {
+                socket2::SockRef::from(tcp).set_linger(Some(Duration::ZERO))
+            }
+            #[cfg(feature = "unix-sockets")]
+            ConnectionInner::Unix(ref unix) => unix.shutdown(Shutdown::Both),
+            #[cfg(feature = "rustls")]
+            ConnectionInner::Rustls(ref tls) => tls.reset(),
+            ConnectionInner::Memory(_) => Ok(()),
         }
     }
 
@@ -109,6 +324,8 @@ impl Read for Connection {
             Connection(ConnectionInner::Unix(unix)) => unix.read(buf),
             #[cfg(feature = "rustls")]
             Connection(ConnectionInner::Rustls(tls)) => tls.read(buf),
+            #[cfg(feature = "server")]
+            Connection(ConnectionInner::Memory(memory)) => memory.read(buf),
         }
     }
 }
@@ -121,6 +338,8 @@ impl Write for Connection {
             Connection(ConnectionInner::Unix(unix)) => unix.write(buf),
             #[cfg(feature = "rustls")]
             Connection(ConnectionInner::Rustls(tls)) => tls.write(buf),
+            #[cfg(feature = "server")]
+            Connection(ConnectionInner::Memory(memory)) => memory.write(buf),
         }
     }
 
@@ -131,6 +350,8 @@ impl Write for Connection {
             Connection(ConnectionInner::Unix(unix)) => unix.flush(),
             #[cfg(feature = "rustls")]
             Connection(ConnectionInner::Rustls(tls)) => tls.flush(),
+            #[cfg(feature = "server")]
+            Connection(ConnectionInner::Memory(_)) => Ok(()),
         }
     }
 }
@@ -149,6 +370,10 @@ impl Clone for Connection {
             Connection(ConnectionInner::Rustls(tls)) => {
                 Connection(ConnectionInner::Rustls(tls.clone()))
             }
+            #[cfg(feature = "server")]
+            Connection(ConnectionInner::Memory(memory)) => {
+                Connection(ConnectionInner::Memory(memory.clone()))
+            }
         }
     }
 }
@@ -178,3 +403,51 @@ impl From<rustls::StreamOwned<rustls::ServerConnection, TcpStream>> for Connecti
         Connection(ConnectionInner::Rustls(tls.into()))
     }
 }
+
+#[cfg(feature = "server")]
+impl From<MemoryStream> for Connection {
+    fn from(memory: MemoryStream) -> Self {
+        Connection(ConnectionInner::Memory(memory))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    #[test]
+    fn peeks_without_consuming() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut conn = Connection::from(listener.accept().unwrap());
+
+        client.write_all(b"lolwut").unwrap();
+
+        let mut buf = [0_u8; 3];
+        assert_eq!(conn.peek(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"lol");
+
+        let mut buf = [0_u8; 6];
+        conn.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"lolwut");
+    }
+
+    #[test]
+    fn sets_nodelay_on_tcp_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (tcp, _) = listener.accept().unwrap();
+        let conn = Connection::from(tcp.try_clone().unwrap());
+
+        conn.set_nodelay(true).unwrap();
+        assert!(tcp.nodelay().unwrap());
+
+        conn.set_nodelay(false).unwrap();
+        assert!(!tcp.nodelay().unwrap());
+    }
+}
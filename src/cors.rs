@@ -0,0 +1,388 @@
+//! [CORS](https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS) support.
+//!
+//! The [`Cors`] configuration wraps a [`Service`], answering preflight requests and adding the
+//! `Access-Control-Allow-*` headers to the responses of allowed origins.
+//!
+//! # Example
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use touche::{cors::Cors, Method, Response, Server, StatusCode};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let cors = Cors::new()
+//!         .allow_origin("https://example.com")
+//!         .allow_methods([Method::GET, Method::POST])
+//!         .allow_headers(["content-type"])
+//!         .max_age(Duration::from_secs(3600));
+//!
+//!     Server::bind("0.0.0.0:4444").serve(cors.wrap(|_req| {
+//!         Response::builder()
+//!             .status(StatusCode::OK)
+//!             .body("Hello from the other origin")
+//!     }))
+//! }
+//! ```
+use std::time::Duration;
+
+use http::{
+    header::{self, HeaderName},
+    HeaderMap, HeaderValue, Method, Request, Response, StatusCode,
+};
+
+use crate::{server::Service, Body};
+
+/// CORS configuration.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Origins,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+enum Origins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            origins: Origins::List(Vec::new()),
+            methods: vec![Method::GET, Method::HEAD, Method::POST],
+            headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl Cors {
+    /// Creates a configuration that allows no origins, and the `GET`, `HEAD` and `POST` methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows requests from any origin.
+    pub fn allow_any_origin(self) -> Self {
+        Self {
+            origins: Origins::Any,
+            ..self
+        }
+    }
+
+    /// Allows requests from the given `origin`. Origins are matched exactly, so they must include
+    /// the scheme and the port (if not the default one), e.g. `https://example.com:8080`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the origin is not a valid header value.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        let origin = HeaderValue::from_str(origin).expect("invalid origin");
+        match self.origins {
+            Origins::Any => self.origins = Origins::List(vec![origin]),
+            Origins::List(ref mut origins) => origins.push(origin),
+        };
+        self
+    }
+
+    /// Sets the methods allowed on cross origin requests.
+    pub fn allow_methods(self, methods: impl IntoIterator<Item = Method>) -> Self {
+        Self {
+            methods: methods.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Sets the headers the client is allowed to send on cross origin requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the names is not a valid header name.
+    pub fn allow_headers<H>(self, headers: impl IntoIterator<Item = H>) -> Self
+    where
+        HeaderName: TryFrom<H>,
+    {
+        Self {
+            headers: headers
+                .into_iter()
+                .map(|name| {
+                    HeaderName::try_from(name)
+                        .ok()
+                        .expect("invalid header name")
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Allows cross origin requests to include credentials (cookies and authorization headers).
+    ///
+    /// Browsers refuse credentials with the `*` origin, so when any origin is allowed the request
+    /// origin is echoed back instead.
+    pub fn allow_credentials(self, credentials: bool) -> Self {
+        Self {
+            credentials,
+            ..self
+        }
+    }
+
+    /// Sets for how long browsers may cache the preflight responses.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..self
+        }
+    }
+
+    /// Wraps a [`Service`], answering preflight requests and adding the CORS headers to its
+    /// responses.
+    pub fn wrap<S: Service>(self, service: S) -> CorsService<S> {
+        CorsService {
+            cors: self,
+            service,
+        }
+    }
+
+    /// Returns the response to a preflight request, or `None` if the request is not a preflight
+    /// one.
+    ///
+    /// A server wide `OPTIONS *` request is also answered, with the `Allow` header listing the
+    /// allowed methods.
+    pub fn preflight<B>(&self, req: &Request<B>) -> Option<Response<()>> {
+        if req.method() != Method::OPTIONS {
+            return None;
+        }
+
+        if req.uri() == "*" {
+            let res = Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .header(header::ALLOW, self.allowed_methods())
+                .body(())
+                .unwrap();
+            return Some(res);
+        }
+
+        if !req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return None;
+        }
+
+        let mut res = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(())
+            .unwrap();
+
+        if let Some(origin) = self.allowed_origin(req.headers()) {
+            let headers = res.headers_mut();
+            self.insert_origin_headers(headers, origin);
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, self.allowed_methods());
+            if !self.headers.is_empty() {
+                headers.insert(
+                    header::ACCESS_CONTROL_ALLOW_HEADERS,
+                    join(self.headers.iter().map(|name| name.as_str())),
+                );
+            }
+            if let Some(max_age) = self.max_age {
+                headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+            }
+        }
+
+        Some(res)
+    }
+
+    /// Adds the CORS headers to a response, if the request `Origin` is allowed.
+    pub fn apply<B>(&self, req_headers: &HeaderMap, res: &mut Response<B>) {
+        if let Some(origin) = self.allowed_origin(req_headers) {
+            self.insert_origin_headers(res.headers_mut(), origin);
+        }
+    }
+
+    fn allowed_origin(&self, req_headers: &HeaderMap) -> Option<HeaderValue> {
+        let origin = req_headers.get(header::ORIGIN)?;
+
+        match self.origins {
+            Origins::Any if self.credentials => Some(origin.clone()),
+            Origins::Any => Some(HeaderValue::from_static("*")),
+            Origins::List(ref origins) => origins
+                .iter()
+                .find(|allowed| allowed.as_bytes().eq_ignore_ascii_case(origin.as_bytes()))
+                .map(|_| origin.clone()),
+        }
+    }
+
+    fn insert_origin_headers(&self, headers: &mut HeaderMap, origin: HeaderValue) {
+        if origin != "*" {
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+        }
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+
+    fn allowed_methods(&self) -> HeaderValue {
+        join(self.methods.iter().map(|method| method.as_str()))
+    }
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> HeaderValue {
+    HeaderValue::from_str(&values.collect::<Vec<_>>().join(", ")).unwrap()
+}
+
+/// A [`Service`] wrapped by a [`Cors`] configuration. See [`Cors::wrap`].
+#[derive(Debug, Clone)]
+pub struct CorsService<S> {
+    cors: Cors,
+    service: S,
+}
+
+impl<S> Service for CorsService<S>
+where
+    S: Service,
+    S::Body: Default,
+{
+    type Body = S::Body;
+    type Error = S::Error;
+
+    fn call(&self, req: Request<Body>) -> Result<Response<Self::Body>, Self::Error> {
+        if let Some(res) = self.cors.preflight(&req) {
+            return Ok(res.map(|_| Default::default()));
+        }
+
+        let req_headers = req.headers().clone();
+        let mut res = self.service.call(req)?;
+        self.cors.apply(&req_headers, &mut res);
+        Ok(res)
+    }
+
+    fn should_continue(&self, req: &Request<Body>) -> StatusCode {
+        self.service.should_continue(req)
+    }
+
+    fn wants_stop(&self) -> bool {
+        self.service.wants_stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    fn app(_req: Request<Body>) -> Result<Response<&'static str>, Infallible> {
+        Ok(Response::new("lolwut"))
+    }
+
+    #[test]
+    fn answers_preflight_requests_of_allowed_origins() {
+        let service = Cors::new()
+            .allow_origin("https://lol.com")
+            .allow_methods([Method::GET, Method::PUT])
+            .allow_headers(["content-type", "x-lol"])
+            .max_age(Duration::from_secs(60))
+            .wrap(app);
+
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .header("origin", "https://lol.com")
+            .header("access-control-request-method", "PUT")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = service.call(req).unwrap();
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            res.headers()["access-control-allow-origin"],
+            "https://lol.com"
+        );
+        assert_eq!(res.headers()["access-control-allow-methods"], "GET, PUT");
+        assert_eq!(
+            res.headers()["access-control-allow-headers"],
+            "content-type, x-lol"
+        );
+        assert_eq!(res.headers()["access-control-max-age"], "60");
+        assert_eq!(res.headers()["vary"], "origin");
+        assert_eq!(res.into_body(), "");
+    }
+
+    #[test]
+    fn does_not_allow_unknown_origins() {
+        let service = Cors::new().allow_origin("https://lol.com").wrap(app);
+
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .header("origin", "https://wut.com")
+            .header("access-control-request-method", "PUT")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = service.call(req).unwrap();
+        assert!(!res.headers().contains_key("access-control-allow-origin"));
+
+        let req = Request::builder()
+            .header("origin", "https://wut.com")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = service.call(req).unwrap();
+        assert!(!res.headers().contains_key("access-control-allow-origin"));
+        assert_eq!(res.into_body(), "lolwut");
+    }
+
+    #[test]
+    fn adds_cors_headers_to_normal_responses() {
+        let service = Cors::new().allow_any_origin().wrap(app);
+
+        let req = Request::builder()
+            .header("origin", "https://lol.com")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = service.call(req).unwrap();
+        assert_eq!(res.headers()["access-control-allow-origin"], "*");
+        assert_eq!(res.into_body(), "lolwut");
+
+        let service = Cors::new()
+            .allow_any_origin()
+            .allow_credentials(true)
+            .wrap(app);
+
+        let req = Request::builder()
+            .header("origin", "https://lol.com")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = service.call(req).unwrap();
+        assert_eq!(
+            res.headers()["access-control-allow-origin"],
+            "https://lol.com"
+        );
+        assert_eq!(res.headers()["access-control-allow-credentials"], "true");
+    }
+
+    #[test]
+    fn answers_server_wide_options_requests() {
+        let service = Cors::new().wrap(app);
+
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("*")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = service.call(req).unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()["allow"], "GET, HEAD, POST");
+    }
+}
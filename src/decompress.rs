@@ -0,0 +1,331 @@
+//! Decompression of request bodies sent with a `Content-Encoding`.
+//!
+//! The [`Decompress`] configuration wraps a [`Service`], decoding the bodies of `gzip` and
+//! `deflate` requests as they are read. Decompressed bodies are limited in size, so small payloads
+//! can't expand into gigabytes of data (known as decompression bombs).
+//!
+//! # Example
+//! ```no_run
+//! use touche::{decompress::Decompress, Body, HttpBody, Request, Response, Server, StatusCode};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let decompress = Decompress::new().max_size(1024 * 1024);
+//!
+//!     Server::bind("0.0.0.0:4444").serve(decompress.wrap(|req: Request<Body>| {
+//!         // Fails if the body decompresses to more than 1MiB, answered with `413`
+//!         let body = req.into_body().into_bytes()?;
+//!
+//!         Response::builder()
+//!             .status(StatusCode::OK)
+//!             .body(format!("Got {} bytes", body.len()))
+//!             .map_err(std::io::Error::other)
+//!     }))
+//! }
+//! ```
+use std::{
+    error::Error,
+    io::{self, Cursor, Read},
+};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use http::{header, HeaderValue, Request, Response, StatusCode};
+
+use crate::{
+    body::{Chunk, SendChunks},
+    server::Service,
+    Body,
+};
+
+/// Request decompression configuration.
+#[derive(Debug, Clone)]
+pub struct Decompress {
+    max_size: u64,
+}
+
+impl Default for Decompress {
+    fn default() -> Self {
+        Self {
+            max_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl Decompress {
+    /// Creates a configuration that limits decompressed bodies to 16MiB.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum size of decompressed bodies. Reading past it fails with a
+    /// [`SizeLimitExceeded`] error, which the wrapped service answers with
+    /// `413 Payload Too Large`.
+    pub fn max_size(self, max_size: u64) -> Self {
+        Self { max_size }
+    }
+
+    /// Wraps a [`Service`], decompressing the bodies of its requests.
+    pub fn wrap<S: Service>(self, service: S) -> DecompressService<S> {
+        DecompressService {
+            decompress: self,
+            service,
+        }
+    }
+
+    /// Replaces the body of a request sent with a supported `Content-Encoding` with its
+    /// decompressed stream, removing the `Content-Encoding` and `Content-Length` headers.
+    ///
+    /// Fails with `415 Unsupported Media Type` if the body is encoded with something else.
+    pub fn decode(&self, req: Request<Body>) -> Result<Request<Body>, StatusCode> {
+        let encoding = match req.headers().get(header::CONTENT_ENCODING) {
+            Some(encoding) => encoding.to_str().unwrap_or_default().trim().to_lowercase(),
+            None => return Ok(req),
+        };
+
+        // Rejected requests keep their body untouched, so it is drained once dropped
+        let decoder: fn(ChunksReader) -> Box<dyn Read + Send> = match encoding.as_str() {
+            "identity" => |body| Box::new(body),
+            "gzip" | "x-gzip" => |body| Box::new(GzDecoder::new(body)),
+            "deflate" => |body| Box::new(ZlibDecoder::new(body)),
+            _ => return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        };
+
+        let (mut parts, body) = req.into_parts();
+
+        let reader = decoder(ChunksReader {
+            chunks: body.into_send_chunks(),
+            chunk: Cursor::default(),
+        });
+
+        parts.headers.remove(header::CONTENT_ENCODING);
+        parts.headers.remove(header::CONTENT_LENGTH);
+
+        let reader = Limited {
+            reader,
+            remaining: self.max_size,
+        };
+
+        Ok(Request::from_parts(parts, Body::from_reader(reader, None)))
+    }
+}
+
+/// Error reading a decompressed body bigger than [`Decompress::max_size`].
+#[derive(Debug, thiserror::Error)]
+#[error("decompressed body exceeds the size limit")]
+pub struct SizeLimitExceeded;
+
+impl SizeLimitExceeded {
+    /// Whether `err` was caused by a decompressed body exceeding its size limit.
+    pub fn is(err: &(dyn Error + 'static)) -> bool {
+        err.is::<Self>()
+            || err
+                .downcast_ref::<io::Error>()
+                .and_then(|err| err.get_ref())
+                .filter(|err| err.is::<Self>())
+                .is_some()
+    }
+}
+
+/// Reads the data of the body chunks, skipping trailers. Unlike [`HttpBody::into_reader`](crate::HttpBody::into_reader), it can be
+/// sent to other threads, so it can back a new [`Body`].
+struct ChunksReader {
+    chunks: SendChunks,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl Read for ChunksReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+
+            match self.chunks.next().transpose()? {
+                Some(Chunk::Data(data)) => self.chunk = Cursor::new(data),
+                Some(Chunk::Trailers(_)) => {}
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Fails as soon as more than `remaining` bytes are read, without decompressing any further.
+struct Limited<R> {
+    reader: R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            return match self.reader.read(&mut [0])? {
+                0 => Ok(0),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    SizeLimitExceeded,
+                )),
+            };
+        }
+
+        let max = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        let read = self.reader.read(&mut buf[..max])?;
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// A [`Service`] wrapped by a [`Decompress`] configuration. See [`Decompress::wrap`].
+#[derive(Debug, Clone)]
+pub struct DecompressService<S> {
+    decompress: Decompress,
+    service: S,
+}
+
+impl<S> Service for DecompressService<S>
+where
+    S: Service,
+    S::Body: Default,
+{
+    type Body = S::Body;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn call(&self, req: Request<Body>) -> Result<Response<Self::Body>, Self::Error> {
+        let req = match self.decompress.decode(req) {
+            Ok(req) => req,
+            Err(status) => {
+                return Ok(Response::builder()
+                    .status(status)
+                    .header(
+                        header::ACCEPT_ENCODING,
+                        HeaderValue::from_static("gzip, deflate"),
+                    )
+                    .body(Default::default())?)
+            }
+        };
+
+        match self.service.call(req).map_err(Into::into) {
+            Err(err) if SizeLimitExceeded::is(&*err) => Ok(Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .header(header::CONNECTION, "close")
+                .body(Default::default())?),
+            res => res,
+        }
+    }
+
+    fn should_continue(&self, req: &Request<Body>) -> StatusCode {
+        self.service.should_continue(req)
+    }
+
+    fn wants_stop(&self) -> bool {
+        self.service.wants_stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{
+        write::{GzEncoder, ZlibEncoder},
+        Compression,
+    };
+
+    use crate::HttpBody;
+
+    use super::*;
+
+    fn app(req: Request<Body>) -> io::Result<Response<Body>> {
+        assert!(!req.headers().contains_key("content-encoding"));
+        let body = req.into_body().into_bytes()?;
+        Ok(Response::new(body.into()))
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn request(encoding: &str, body: Vec<u8>) -> Request<Body> {
+        Request::post("/")
+            .header("content-encoding", encoding)
+            .header("content-length", body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[test]
+    fn decompresses_request_bodies() {
+        let service = Decompress::new().wrap(app);
+
+        let res = service.call(request("gzip", gzip(b"lolwut"))).unwrap();
+        assert_eq!(res.into_body().into_bytes().unwrap(), b"lolwut");
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"lolwut").unwrap();
+        let res = service
+            .call(request("deflate", encoder.finish().unwrap()))
+            .unwrap();
+        assert_eq!(res.into_body().into_bytes().unwrap(), b"lolwut");
+
+        let req = Request::post("/").body(Body::from("lolwut")).unwrap();
+        let res = service.call(req).unwrap();
+        assert_eq!(res.into_body().into_bytes().unwrap(), b"lolwut");
+    }
+
+    #[test]
+    fn rejects_bodies_bigger_than_the_limit() {
+        let service = Decompress::new().max_size(1024).wrap(app);
+
+        let res = service.call(request("gzip", gzip(&[0; 1024]))).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let bomb = gzip(&vec![0; 1024 * 1024]);
+        assert!(bomb.len() < 2048);
+
+        let res = service.call(request("gzip", bomb)).unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn stops_decompressing_at_the_limit() {
+        let req = request("gzip", gzip(&vec![0; 1024 * 1024]));
+        let req = Decompress::new().max_size(10).decode(req).unwrap();
+
+        let mut reader = req.into_body().into_reader();
+        let mut buf = vec![0; 64];
+        assert_eq!(reader.read(&mut buf).unwrap(), 10);
+
+        let err = reader.read(&mut buf).unwrap_err();
+        assert!(SizeLimitExceeded::is(&err));
+    }
+
+    #[test]
+    fn rejects_unsupported_encodings() {
+        let service = Decompress::new().wrap(app);
+
+        let res = service.call(request("br", b"lolwut".to_vec())).unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(res.headers()["accept-encoding"], "gzip, deflate");
+
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"lolwutnext").unwrap();
+        drop(writer);
+        let mut rest = reader.try_clone().unwrap();
+        let req = Request::post("/")
+            .header("content-encoding", "br")
+            .body(Body::from_reader(reader, 6))
+            .unwrap();
+        assert!(Decompress::new().decode(req).is_err());
+
+        let mut buf = String::new();
+        rest.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "next");
+    }
+}
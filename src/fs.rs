@@ -0,0 +1,598 @@
+//! Helpers for serving files from the filesystem.
+//!
+//! # Example
+//! ```no_run
+//! use std::fs::File;
+//!
+//! use touche::{fs, Body, Request, Response, Server, StatusCode};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+//!         let file = fs::resolve("public", req.uri().path()).and_then(|path| File::open(path).ok());
+//!
+//!         match file.map(Body::try_from) {
+//!             Some(Ok(body)) => Response::builder().status(StatusCode::OK).body(body),
+//!             _ => Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()),
+//!         }
+//!     })
+//! }
+//! ```
+use std::{
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified};
+use http::{Method, Request, Response, StatusCode};
+
+use crate::Body;
+
+/// Maps a request path to a path inside `root`.
+///
+/// The path is percent-decoded and its `.` and `..` segments are normalized. Returns `None` when
+/// the path would escape `root`, or when it can't be safely mapped to a filesystem path (invalid
+/// percent-encoding, non UTF-8 bytes, NUL bytes or backslashes).
+///
+/// Any query string or fragment is ignored. Note that symbolic links inside `root` are not
+/// resolved, so they may still point outside of it.
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use touche::fs::resolve;
+/// assert_eq!(resolve("public", "/css/../app%20v2.js"), Some(PathBuf::from("public/app v2.js")));
+/// assert_eq!(resolve("public", "/../secret.txt"), None);
+/// assert_eq!(resolve("public", "/%2e%2e/secret.txt"), None);
+/// ```
+pub fn resolve(root: impl AsRef<Path>, request_path: &str) -> Option<PathBuf> {
+    let path = request_path.split(['?', '#']).next().unwrap_or_default();
+
+    let path = String::from_utf8(percent_decode(path)?).ok()?;
+
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment if segment.contains(['\0', '\\']) => return None,
+            segment => segments.push(segment),
+        }
+    }
+
+    let mut resolved = root.as_ref().to_path_buf();
+    for segment in segments {
+        let segment = Path::new(segment);
+        // Guards against prefixes and roots, like `C:` on Windows
+        if segment.has_root() || segment.components().count() != 1 {
+            return None;
+        }
+        resolved.push(segment);
+    }
+    Some(resolved)
+}
+
+/// Cache validators of a file, used to answer conditional requests with `304 Not Modified`.
+///
+/// The `ETag` is derived from the modification time and size of the file, so it changes whenever
+/// the file is modified.
+///
+/// # Example
+/// ```no_run
+/// use std::fs::{self, File};
+///
+/// use touche::{fs::Validators, Body, Request, Server};
+///
+/// fn main() -> std::io::Result<()> {
+///     Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+///         let validators = Validators::from_metadata(&fs::metadata("index.html")?)?;
+///         validators.respond(&req, || Body::try_from(File::open("index.html")?))
+///     })
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Validators {
+    etag: ETag,
+    modified: SystemTime,
+}
+
+impl Validators {
+    /// Creates the validators of a file modified at `modified` with `len` bytes.
+    pub fn new(modified: SystemTime, len: u64) -> Self {
+        let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let etag = format!(
+            "\"{:x}.{:x}-{len:x}\"",
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos()
+        )
+        .parse()
+        .expect("hex digits are valid etag characters");
+        Self { etag, modified }
+    }
+
+    /// Creates the validators from the metadata of a file.
+    pub fn from_metadata(metadata: &Metadata) -> io::Result<Self> {
+        Ok(Self::new(metadata.modified()?, metadata.len()))
+    }
+
+    /// The `ETag` of the file.
+    pub fn etag(&self) -> &ETag {
+        &self.etag
+    }
+
+    /// The `Last-Modified` date of the file.
+    pub fn last_modified(&self) -> LastModified {
+        self.modified.into()
+    }
+
+    /// Checks if the client already has the current version of the file, according to the
+    /// request `If-None-Match` and `If-Modified-Since` headers.
+    ///
+    /// Only `GET` and `HEAD` requests can be answered as not modified. When `If-None-Match` is
+    /// present, `If-Modified-Since` is ignored.
+    pub fn is_not_modified<B>(&self, req: &Request<B>) -> bool {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return false;
+        }
+
+        let headers = req.headers();
+        if let Some(if_none_match) = headers.typed_get::<IfNoneMatch>() {
+            !if_none_match.precondition_passes(&self.etag)
+        } else if let Some(if_modified_since) = headers.typed_get::<IfModifiedSince>() {
+            !if_modified_since.is_modified(self.modified)
+        } else {
+            false
+        }
+    }
+
+    /// Answers the request with `304 Not Modified` when the client already has the current
+    /// version of the file, or with `200 OK` and the `body` otherwise.
+    ///
+    /// Both responses carry the `ETag` and `Last-Modified` headers. The `body` is only created
+    /// when the file must be sent, and `304` responses never have a body.
+    pub fn respond<F>(&self, req: &Request<impl Sized>, body: F) -> io::Result<Response<Body>>
+    where
+        F: FnOnce() -> io::Result<Body>,
+    {
+        let mut res = if self.is_not_modified(req) {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::NOT_MODIFIED;
+            res
+        } else {
+            Response::new(body()?)
+        };
+
+        res.headers_mut().typed_insert(self.etag.clone());
+        res.headers_mut().typed_insert(self.last_modified());
+        Ok(res)
+    }
+}
+
+/// Lists the entries of `dir` as a simple HTML page, with links to each of them. Hidden entries
+/// are left out, see [`DirectoryListing`] to configure it.
+pub fn directory_listing(dir: impl AsRef<Path>) -> io::Result<Body> {
+    DirectoryListing::new().render(dir)
+}
+
+/// Configuration of auto-generated directory listings, meant for development servers.
+///
+/// Listings are simple HTML pages with the name, type and size of each entry. Directories come
+/// first, and entries are sorted by name. Links are relative, so the page must be served from a
+/// path ending with a `/`, which [`DirectoryListing::respond`] takes care of.
+///
+/// # Example
+/// ```no_run
+/// use touche::{fs::DirectoryListing, Body, Request, Server};
+///
+/// fn main() -> std::io::Result<()> {
+///     let listing = DirectoryListing::new().show_hidden(true);
+///
+///     Server::bind("0.0.0.0:4444").serve(move |req: Request<Body>| listing.respond("public", &req))
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryListing {
+    show_hidden: bool,
+}
+
+impl DirectoryListing {
+    /// Creates a configuration that leaves hidden entries out of the listings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether entries starting with a `.` are listed and can be browsed. Defaults to `false`.
+    pub fn show_hidden(self, show_hidden: bool) -> Self {
+        Self { show_hidden }
+    }
+
+    /// Lists the entries of `dir` as an HTML page.
+    pub fn render(&self, dir: impl AsRef<Path>) -> io::Result<Body> {
+        self.html(dir.as_ref(), "Directory listing")
+    }
+
+    /// Answers the request with the listing of the directory its path maps to inside `root`,
+    /// using [`resolve`] to keep the request inside `root`.
+    ///
+    /// Responds with `404 Not Found` when the path can't be resolved, is not a directory or goes
+    /// through hidden entries that are not shown. Requests to a directory without a trailing `/`
+    /// are redirected to the path with it, so relative links work.
+    pub fn respond<B>(
+        &self,
+        root: impl AsRef<Path>,
+        req: &Request<B>,
+    ) -> io::Result<Response<Body>> {
+        let path = req.uri().path();
+        let not_found = || {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::NOT_FOUND;
+            Ok(res)
+        };
+
+        let dir = match resolve(&root, path) {
+            Some(dir) if dir.is_dir() => dir,
+            _ => return not_found(),
+        };
+
+        let hidden = dir
+            .strip_prefix(&root)
+            .map_or(true, |relative| relative.iter().any(is_hidden));
+        if hidden && !self.show_hidden {
+            return not_found();
+        }
+
+        if !path.ends_with('/') {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::MOVED_PERMANENTLY;
+            let location = format!("{path}/").parse().map_err(io::Error::other)?;
+            res.headers_mut().insert(http::header::LOCATION, location);
+            return Ok(res);
+        }
+
+        let title = String::from_utf8_lossy(&percent_decode(path).unwrap_or_default()).into_owned();
+        let mut res = Response::new(self.html(&dir, &format!("Index of {title}"))?);
+        res.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        Ok(res)
+    }
+
+    fn html(&self, dir: &Path, title: &str) -> io::Result<Body> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if !self.show_hidden && is_hidden(&name) {
+                continue;
+            }
+            // Follows symbolic links, skipping the broken ones
+            let Ok(metadata) = std::fs::metadata(entry.path()) else {
+                continue;
+            };
+            entries.push((
+                !metadata.is_dir(),
+                name.to_string_lossy().into_owned(),
+                metadata,
+            ));
+        }
+        entries.sort_by(|(a_file, a_name, _), (b_file, b_name, _)| {
+            (a_file, a_name).cmp(&(b_file, b_name))
+        });
+
+        let title = escape_html(title);
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+             <body>\n<h1>{title}</h1>\n<table>\n\
+             <tr><th>Name</th><th>Type</th><th>Size</th></tr>\n"
+        );
+
+        for (is_file, name, metadata) in entries {
+            let (href, kind, size) = if is_file {
+                (percent_encode(&name), "file", metadata.len().to_string())
+            } else {
+                (
+                    format!("{}/", percent_encode(&name)),
+                    "directory",
+                    String::new(),
+                )
+            };
+            let name = escape_html(&name);
+            html.push_str(&format!(
+                "<tr><td><a href=\"{href}\">{name}</a></td><td>{kind}</td><td>{size}</td></tr>\n"
+            ));
+        }
+
+        html.push_str("</table>\n</body>\n</html>\n");
+        Ok(html.into())
+    }
+}
+
+fn is_hidden(name: &std::ffi::OsStr) -> bool {
+    name.as_encoded_bytes().starts_with(b".")
+}
+
+fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Encodes everything but unreserved characters, so names are always taken as a single relative
+/// path segment.
+fn percent_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+fn percent_decode(input: &str) -> Option<Vec<u8>> {
+    let mut bytes = input.bytes();
+    let mut decoded = Vec::with_capacity(input.len());
+
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hi = (bytes.next()? as char).to_digit(16)?;
+            let lo = (bytes.next()? as char).to_digit(16)?;
+            decoded.push((hi * 16 + lo) as u8);
+        } else {
+            decoded.push(byte);
+        }
+    }
+
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::{header, HeaderValue};
+
+    use crate::HttpBody;
+
+    use super::*;
+
+    fn resolved(path: &str) -> Option<PathBuf> {
+        resolve("/srv/public", path)
+    }
+
+    #[test]
+    fn resolves_paths_inside_root() {
+        assert_eq!(resolved("/"), Some(PathBuf::from("/srv/public")));
+        assert_eq!(resolved(""), Some(PathBuf::from("/srv/public")));
+        assert_eq!(
+            resolved("/index.html"),
+            Some(PathBuf::from("/srv/public/index.html"))
+        );
+        assert_eq!(
+            resolved("/css/app.css"),
+            Some(PathBuf::from("/srv/public/css/app.css"))
+        );
+        assert_eq!(
+            resolved("//css///./app.css"),
+            Some(PathBuf::from("/srv/public/css/app.css"))
+        );
+        assert_eq!(
+            resolved("/css/../js/app.js"),
+            Some(PathBuf::from("/srv/public/js/app.js"))
+        );
+        assert_eq!(
+            resolved("/...hidden"),
+            Some(PathBuf::from("/srv/public/...hidden"))
+        );
+    }
+
+    #[test]
+    fn percent_decodes_paths() {
+        assert_eq!(
+            resolved("/hello%20world.txt"),
+            Some(PathBuf::from("/srv/public/hello world.txt"))
+        );
+        assert_eq!(
+            resolved("/caf%C3%A9.txt"),
+            Some(PathBuf::from("/srv/public/café.txt"))
+        );
+        assert_eq!(
+            resolved("/a%2fb.txt"),
+            Some(PathBuf::from("/srv/public/a/b.txt"))
+        );
+        assert_eq!(resolved("/100%.txt"), None);
+        assert_eq!(resolved("/%zz.txt"), None);
+        assert_eq!(resolved("/%"), None);
+        assert_eq!(resolved("/%ff.txt"), None);
+    }
+
+    #[test]
+    fn ignores_query_and_fragment() {
+        assert_eq!(
+            resolved("/index.html?v=../../etc/passwd"),
+            Some(PathBuf::from("/srv/public/index.html"))
+        );
+        assert_eq!(
+            resolved("/index.html#../.."),
+            Some(PathBuf::from("/srv/public/index.html"))
+        );
+    }
+
+    #[test]
+    fn lists_directories() {
+        let root = std::env::temp_dir().join(format!("touche-listing-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("css")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join("a <b>.txt"), "lolwut").unwrap();
+        std::fs::write(root.join(".env"), "secret").unwrap();
+
+        let listing = |config: DirectoryListing, path: &str| {
+            let req = Request::get(path).body(()).unwrap();
+            let res = config.respond(&root, &req).unwrap();
+            let status = res.status();
+            (status, res.into_body().into_string(1 << 20).unwrap())
+        };
+
+        let (status, html) = listing(DirectoryListing::new(), "/");
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains("<title>Index of /</title>"));
+        assert!(html.contains("<a href=\"css/\">css</a></td><td>directory"));
+        assert!(html.contains(
+            "<a href=\"a%20%3Cb%3E.txt\">a &lt;b&gt;.txt</a></td><td>file</td><td>6</td>"
+        ));
+        assert!(html.find("css/").unwrap() < html.find("a%20").unwrap());
+        assert!(!html.contains(".env") && !html.contains(".git"));
+
+        let (status, html) = listing(DirectoryListing::new().show_hidden(true), "/");
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains(".env") && html.contains(".git/"));
+
+        assert_eq!(
+            listing(DirectoryListing::new(), "/.git/").0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            listing(DirectoryListing::new(), "/../").0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            listing(DirectoryListing::new(), "/nope/").0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            listing(DirectoryListing::new(), "/css").0,
+            StatusCode::MOVED_PERMANENTLY
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rejects_traversal_attempts() {
+        assert_eq!(resolved("/.."), None);
+        assert_eq!(resolved("/../etc/passwd"), None);
+        assert_eq!(resolved("/css/../../etc/passwd"), None);
+        assert_eq!(resolved("/%2e%2e/etc/passwd"), None);
+        assert_eq!(resolved("/%2E%2E%2Fetc/passwd"), None);
+        assert_eq!(resolved("/css%2f..%2f..%2fetc/passwd"), None);
+        assert_eq!(resolved("/..%5cetc%5cpasswd"), None);
+        assert_eq!(resolved("/..\\etc\\passwd"), None);
+        assert_eq!(resolved("/index.html%00.png"), None);
+        assert_eq!(
+            resolved("/%252e%252e/etc"),
+            Some(PathBuf::from("/srv/public/%2e%2e/etc"))
+        );
+    }
+
+    fn encode(header: impl headers::Header) -> String {
+        let mut headers = http::HeaderMap::new();
+        headers.typed_insert(header);
+        let value = headers.values().next().unwrap();
+        value.to_str().unwrap().to_string()
+    }
+
+    fn conditional(header: &str, value: &str) -> Request<()> {
+        Request::get("/").header(header, value).body(()).unwrap()
+    }
+
+    #[test]
+    fn derives_validators_from_modification_time_and_size() {
+        let modified = UNIX_EPOCH + Duration::new(1_700_000_000, 500);
+        let validators = Validators::new(modified, 1024);
+
+        assert_eq!(
+            validators.etag(),
+            &"\"6553f100.1f4-400\"".parse::<ETag>().unwrap()
+        );
+        assert_eq!(validators.last_modified(), LastModified::from(modified));
+
+        assert_ne!(validators.etag(), Validators::new(modified, 1025).etag());
+        let touched = modified + Duration::from_millis(1);
+        assert_ne!(validators.etag(), Validators::new(touched, 1024).etag());
+    }
+
+    #[test]
+    fn answers_not_modified_to_matching_etags() {
+        let validators = Validators::new(SystemTime::now(), 3);
+        let etag = encode(validators.etag().clone());
+
+        assert!(validators.is_not_modified(&conditional("if-none-match", &etag)));
+        assert!(validators.is_not_modified(&conditional("if-none-match", "*")));
+        assert!(validators
+            .is_not_modified(&conditional("if-none-match", &format!("\"lol\", W/{etag}"))));
+        assert!(!validators.is_not_modified(&conditional("if-none-match", "\"lol\"")));
+        assert!(!validators.is_not_modified(&Request::get("/").body(()).unwrap()));
+
+        let post = Request::post("/")
+            .header("if-none-match", &etag)
+            .body(())
+            .unwrap();
+        assert!(!validators.is_not_modified(&post));
+    }
+
+    #[test]
+    fn answers_not_modified_to_requests_since_the_modification() {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let validators = Validators::new(modified, 3);
+
+        let date =
+            |time: SystemTime| HeaderValue::from_str(&encode(LastModified::from(time))).unwrap();
+        let since = |time| {
+            let mut req = Request::get("/").body(()).unwrap();
+            req.headers_mut()
+                .insert(header::IF_MODIFIED_SINCE, date(time));
+            req
+        };
+
+        assert!(validators.is_not_modified(&since(modified)));
+        assert!(validators.is_not_modified(&since(modified + Duration::from_secs(60))));
+        assert!(!validators.is_not_modified(&since(modified - Duration::from_secs(60))));
+
+        // If-None-Match takes precedence
+        let mut req = since(modified);
+        req.headers_mut()
+            .insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"lol\""));
+        assert!(!validators.is_not_modified(&req));
+    }
+
+    #[test]
+    fn responds_without_body_when_not_modified() {
+        let validators = Validators::new(SystemTime::now(), 3);
+        let etag = encode(validators.etag().clone());
+
+        let res = validators
+            .respond(&conditional("if-none-match", &etag), || {
+                panic!("body must not be created")
+            })
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()["etag"], etag.as_str());
+        assert!(res.headers().contains_key("last-modified"));
+        assert!(res.body().is_empty());
+
+        let res = validators
+            .respond(
+                &conditional("if-none-match", "\"lol\""),
+                || Ok("lol".into()),
+            )
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["etag"], etag.as_str());
+        assert_eq!(res.into_body().into_bytes().unwrap(), b"lol");
+    }
+}
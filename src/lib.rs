@@ -3,25 +3,47 @@
 pub mod body;
 #[cfg(feature = "client")]
 pub mod client;
+#[cfg(feature = "compression")]
+pub mod compress;
 mod connection;
+#[cfg(feature = "server")]
+pub mod cors;
+#[cfg(feature = "decompression")]
+pub mod decompress;
+pub mod fs;
+pub mod proxy;
 mod read_queue;
 mod request;
 mod response;
 #[cfg(feature = "server")]
+pub mod router;
+#[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "server")]
+pub mod test;
 #[cfg(feature = "rustls")]
 mod tls;
 pub mod upgrade;
+#[cfg(feature = "websocket-deflate")]
+pub mod websocket;
 
 pub use body::Body;
 pub use body::HttpBody;
 #[cfg(feature = "client")]
 pub use client::Client;
-pub use connection::Connection;
+pub use connection::{Connection, ConnectionInfo};
 #[doc(hidden)]
 pub use http;
 #[doc(no_inline)]
 pub use http::HeaderMap;
 pub use http::{header, Method, Request, Response, StatusCode, Uri, Version};
 #[cfg(feature = "server")]
+pub use request::RawRequest;
+pub use request::{ProtocolError, RequestExt, TypedHeaders};
+#[cfg(feature = "json")]
+pub use response::JsonError;
+pub use response::ResponseExt;
+#[cfg(feature = "server")]
+pub use router::Router;
+#[cfg(feature = "server")]
 pub use server::Server;
@@ -0,0 +1,247 @@
+//! Helpers for services acting as intermediaries, such as reverse proxies and gateways, and for
+//! services running behind them.
+#[cfg(feature = "server")]
+use std::net::{IpAddr, SocketAddr};
+
+use http::{
+    header::{self, HeaderName},
+    HeaderMap,
+};
+
+/// Headers that only concern a single connection, and must not be forwarded by intermediaries.
+const HOP_BY_HOP: [HeaderName; 9] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Removes the hop-by-hop headers from a request or response about to be forwarded, as required
+/// by [RFC 7230](https://datatracker.ietf.org/doc/html/rfc7230#section-6.1).
+///
+/// That's every header listed on the `Connection` header, as well as the standard hop-by-hop
+/// headers (`Connection`, `Keep-Alive`, `Proxy-Connection`, `Proxy-Authenticate`,
+/// `Proxy-Authorization`, `TE`, `Trailer`, `Transfer-Encoding` and `Upgrade`).
+///
+/// # Example
+/// ```
+/// # use touche::{proxy, HeaderMap};
+/// let mut headers = HeaderMap::new();
+/// headers.insert("connection", "keep-alive, x-session".parse().unwrap());
+/// headers.insert("keep-alive", "timeout=5".parse().unwrap());
+/// headers.insert("x-session", "lolwut".parse().unwrap());
+/// headers.insert("content-type", "text/plain".parse().unwrap());
+///
+/// proxy::remove_hop_by_hop_headers(&mut headers);
+/// assert_eq!(headers.len(), 1);
+/// assert!(headers.contains_key("content-type"));
+/// ```
+pub fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(header::CONNECTION)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+
+    for name in listed.iter().chain(&HOP_BY_HOP) {
+        headers.remove(name);
+    }
+}
+
+/// The address of the client that originated a request, as seen through the trusted proxies
+/// configured with [`ServerBuilder::trusted_proxies`](crate::server::ServerBuilder::trusted_proxies).
+///
+/// The server makes it available on the request extensions. When the request doesn't come from a
+/// trusted proxy, it is the address of the peer itself. Not available on Unix sockets.
+///
+/// # Example
+/// ```no_run
+/// # use touche::{proxy::ClientIp, Body, Request, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::builder()
+///     .trusted_proxies(["127.0.0.1".parse().unwrap()])
+///     .bind("0.0.0.0:4444")
+///     .serve(|req: Request<Body>| {
+///         let ClientIp(ip) = req.extensions().get::<ClientIp>().unwrap();
+///         Response::builder()
+///             .status(StatusCode::OK)
+///             .body(format!("Hello {ip}"))
+///     })
+/// # }
+/// ```
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+/// Finds the client address of a request received from `peer`.
+///
+/// Hops are walked from the rightmost one, which was added by the proxy closest to the server, and
+/// trusted for as long as they were added by a trusted proxy. The `Forwarded` header takes
+/// precedence over `X-Forwarded-For`, as clients could still send the latter.
+#[cfg(feature = "server")]
+pub(crate) fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[IpAddr]) -> ClientIp {
+    let mut client = peer;
+
+    if !trusted.contains(&peer) {
+        return ClientIp(client);
+    }
+
+    let hops = if headers.contains_key(header::FORWARDED) {
+        forwarded_hops(headers)
+    } else {
+        list(headers, HeaderName::from_static("x-forwarded-for"))
+            .map(parse_node)
+            .collect()
+    };
+
+    for hop in hops.into_iter().rev() {
+        match hop {
+            // Unknown or obfuscated hops can't be vouched for
+            None => break,
+            Some(ip) => {
+                client = ip;
+                if !trusted.contains(&ip) {
+                    break;
+                }
+            }
+        }
+    }
+
+    ClientIp(client)
+}
+
+#[cfg(feature = "server")]
+fn list(headers: &HeaderMap, name: HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(name)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|element| !element.is_empty())
+}
+
+/// The `for` parameter of every element of the `Forwarded` headers.
+#[cfg(feature = "server")]
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    list(headers, header::FORWARDED)
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                .and_then(|(_, node)| parse_node(node.trim().trim_matches('"')))
+        })
+        .collect()
+}
+
+/// Parses a node such as `192.0.2.43`, `192.0.2.43:47011`, `[2001:db8::17]:47011` or `2001:db8::17`.
+#[cfg(feature = "server")]
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| node.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn removes_the_headers_listed_on_connection() {
+        let mut headers = HeaderMap::new();
+        headers.append("connection", HeaderValue::from_static("X-Custom, upgrade"));
+        headers.append("connection", HeaderValue::from_static("x-other"));
+        headers.insert("x-custom", HeaderValue::from_static("1"));
+        headers.insert("x-other", HeaderValue::from_static("2"));
+        headers.insert("upgrade", HeaderValue::from_static("websocket"));
+        headers.insert("x-kept", HeaderValue::from_static("3"));
+
+        remove_hop_by_hop_headers(&mut headers);
+
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["x-kept"], "3");
+    }
+
+    #[test]
+    fn removes_the_standard_hop_by_hop_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("transfer-encoding", HeaderValue::from_static("chunked"));
+        headers.insert("te", HeaderValue::from_static("trailers"));
+        headers.insert("trailer", HeaderValue::from_static("x-checksum"));
+        headers.insert(
+            "proxy-authorization",
+            HeaderValue::from_static("Basic bG9s"),
+        );
+        headers.insert("upgrade", HeaderValue::from_static("websocket"));
+        headers.insert("content-length", HeaderValue::from_static("6"));
+
+        remove_hop_by_hop_headers(&mut headers);
+
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["content-length"], "6");
+    }
+
+    #[cfg(feature = "server")]
+    fn header_map(name: &'static str, values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[cfg(feature = "server")]
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn ignores_forwarded_headers_from_untrusted_peers() {
+        let headers = header_map("x-forwarded-for", &["203.0.113.1"]);
+        let client = client_ip(ip("198.51.100.7"), &headers, &[ip("10.0.0.1")]);
+        assert_eq!(client, ClientIp(ip("198.51.100.7")));
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn takes_the_rightmost_untrusted_hop() {
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+
+        let headers = header_map("x-forwarded-for", &["1.1.1.1, 203.0.113.1", "10.0.0.2"]);
+        let client = client_ip(ip("10.0.0.1"), &headers, &trusted);
+        assert_eq!(client, ClientIp(ip("203.0.113.1")));
+
+        let headers = header_map("x-forwarded-for", &["10.0.0.2"]);
+        let client = client_ip(ip("10.0.0.1"), &headers, &trusted);
+        assert_eq!(client, ClientIp(ip("10.0.0.2")));
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn parses_the_forwarded_header() {
+        let trusted = [ip("10.0.0.1")];
+
+        let headers = header_map(
+            "forwarded",
+            &[r#"for=1.1.1.1;proto=https, For="[2001:db8:cafe::17]:4711";by=10.0.0.1"#],
+        );
+        let client = client_ip(ip("10.0.0.1"), &headers, &trusted);
+        assert_eq!(client, ClientIp(ip("2001:db8:cafe::17")));
+
+        let headers = header_map("forwarded", &["for=203.0.113.1:80, for=_hidden"]);
+        let client = client_ip(ip("10.0.0.1"), &headers, &trusted);
+        assert_eq!(client, ClientIp(ip("10.0.0.1")));
+    }
+}
@@ -46,6 +46,16 @@ impl<R: Read + Send> ReadQueue<R> {
     }
 }
 
+impl<R: Read + Send> QueuedReader<R> {
+    /// Blocks until the previous reader is dropped and this one gets its turn, without reading.
+    pub fn wait(&mut self) {
+        if let Some(QueuedReaderInner::Waiting(ref rx)) = self.reader {
+            let reader = rx.recv().unwrap();
+            self.reader = Some(QueuedReaderInner::Current(reader));
+        }
+    }
+}
+
 impl<R: Read + Send> Read for QueuedReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self.reader.as_mut().unwrap() {
@@ -1,13 +1,23 @@
-#[cfg(feature = "client")]
+#[cfg(any(feature = "client", feature = "server"))]
 use std::io::Write;
 use std::io::{self, BufRead, Read};
+#[cfg(feature = "server")]
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
-use http::Request;
+use http::{header::HeaderName, HeaderMap, HeaderValue, Request, StatusCode};
 use thiserror::Error;
 
-use crate::body::Body;
+use crate::body::{Body, Chunk};
+#[cfg(feature = "server")]
+use crate::Connection;
 
-#[cfg(feature = "client")]
+#[cfg(any(feature = "client", feature = "server"))]
 use crate::HttpBody;
 
 #[derive(Error, Debug)]
@@ -28,34 +38,357 @@ pub enum ParseError {
     InvalidHeader(#[from] headers::Error),
     #[error("failed to parse http request")]
     Unknown,
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+}
+
+impl ParseError {
+    /// The [`ProtocolError`] caused by the client, if any.
+    pub(crate) fn protocol_error(&self) -> Option<ProtocolError> {
+        match self {
+            ParseError::ConnectionClosed | ParseError::Io(_) => None,
+            ParseError::Invalid(httparse::Error::TooManyHeaders) => {
+                Some(ProtocolError::TooManyHeaders)
+            }
+            ParseError::Invalid(
+                httparse::Error::HeaderName
+                | httparse::Error::HeaderValue
+                | httparse::Error::NewLine,
+            ) => Some(ProtocolError::InvalidHeader),
+            ParseError::Invalid(_) | ParseError::IncompleteRequest => {
+                Some(ProtocolError::InvalidRequestLine)
+            }
+            ParseError::UnsupportedHttpVersion(version) => {
+                Some(ProtocolError::UnsupportedHttpVersion(*version))
+            }
+            ParseError::InvalidTransferEncoding => Some(ProtocolError::UnsupportedTransferEncoding),
+            ParseError::InvalidHeader(_) => Some(ProtocolError::InvalidHeader),
+            ParseError::Unknown => Some(ProtocolError::Malformed),
+            ParseError::Protocol(err) => Some(err.clone()),
+        }
+    }
+}
+
+/// Errors caused by clients sending requests that violate the HTTP protocol.
+///
+/// The server answers these requests with the status returned by [`ProtocolError::status`] and
+/// closes the connection.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProtocolError {
+    #[error("invalid request line")]
+    InvalidRequestLine,
+    #[error("invalid header")]
+    InvalidHeader,
+    #[error("too many headers")]
+    TooManyHeaders,
+    #[error("unsupported http version: {0}")]
+    UnsupportedHttpVersion(u8),
+    #[error("unsupported transfer encoding")]
+    UnsupportedTransferEncoding,
+    #[error("malformed request")]
+    Malformed,
+    #[error("request head not received in time")]
+    RequestTimeout,
+    #[error("ambiguous request body length")]
+    AmbiguousLength,
+    #[error("request line too long")]
+    RequestLineTooLong,
+    #[error("line not terminated with CRLF")]
+    BareLineFeed,
+    #[error("obsolete line folding")]
+    ObsoleteLineFolding,
+}
+
+impl ProtocolError {
+    /// The status code that should be answered to the client.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ProtocolError::InvalidRequestLine
+            | ProtocolError::InvalidHeader
+            | ProtocolError::AmbiguousLength
+            | ProtocolError::BareLineFeed
+            | ProtocolError::ObsoleteLineFolding
+            | ProtocolError::Malformed => StatusCode::BAD_REQUEST,
+            ProtocolError::TooManyHeaders => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ProtocolError::UnsupportedHttpVersion(_) => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ProtocolError::UnsupportedTransferEncoding => StatusCode::NOT_IMPLEMENTED,
+            ProtocolError::RequestTimeout => StatusCode::REQUEST_TIMEOUT,
+            ProtocolError::RequestLineTooLong => StatusCode::URI_TOO_LONG,
+        }
+    }
+}
+
+/// Commonly used request headers, decoded with their [`headers`] typed representation.
+///
+/// See [`RequestExt::typed_headers`].
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct TypedHeaders {
+    pub content_type: Option<headers::ContentType>,
+    pub content_length: Option<headers::ContentLength>,
+    pub basic_auth: Option<headers::Authorization<headers::authorization::Basic>>,
+    pub bearer_auth: Option<headers::Authorization<headers::authorization::Bearer>>,
+    pub host: Option<headers::Host>,
+    pub user_agent: Option<headers::UserAgent>,
+}
+
+/// Extension methods for [`Request`].
+pub trait RequestExt {
+    /// Decodes the commonly used request headers in one go. Missing or malformed headers are
+    /// returned as `None`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Request, RequestExt, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|req: Request<_>| {
+    ///     let headers = req.typed_headers();
+    ///     match headers.bearer_auth {
+    ///         Some(auth) if auth.token() == "secret" => {
+    ///             Response::builder().status(StatusCode::OK).body(())
+    ///         }
+    ///         _ => Response::builder().status(StatusCode::UNAUTHORIZED).body(()),
+    ///     }
+    /// })
+    /// # }
+    /// ```
+    fn typed_headers(&self) -> TypedHeaders;
+
+    /// Same as [`RequestExt::typed_headers`], but fails if any of the headers is present and
+    /// malformed.
+    fn try_typed_headers(&self) -> Result<TypedHeaders, headers::Error>;
+
+    /// Resolves the host and port the request was sent to, with the host lowercased and IPv6
+    /// literals kept in brackets.
+    ///
+    /// Absolute-form URIs take precedence over the `Host` header, as mandated by
+    /// [RFC 9112](https://datatracker.ietf.org/doc/html/rfc9112#section-3.2.2). When no port is
+    /// given, the default one for the scheme is used: `443` for `https` URIs or requests received
+    /// over TLS, `80` otherwise. HTTP/1.0 requests without a `Host` header fall back to the local
+    /// address of the connection.
+    ///
+    /// Fails with `400 Bad Request` when the authority is missing, repeated or malformed.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Request, RequestExt, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|req: Request<_>| {
+    ///     match req.effective_authority() {
+    ///         Ok((host, port)) => Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(format!("Welcome to {host}:{port}")),
+    ///         Err(status) => Response::builder().status(status).body(String::new()),
+    ///     }
+    /// })
+    /// # }
+    /// ```
+    fn effective_authority(&self) -> Result<(String, u16), StatusCode>;
+}
+
+impl<B> RequestExt for Request<B> {
+    fn typed_headers(&self) -> TypedHeaders {
+        use headers::HeaderMapExt;
+
+        let headers = self.headers();
+        TypedHeaders {
+            content_type: headers.typed_get(),
+            content_length: headers.typed_get(),
+            basic_auth: headers.typed_get(),
+            bearer_auth: headers.typed_get(),
+            host: headers.typed_get(),
+            user_agent: headers.typed_get(),
+        }
+    }
+
+    fn try_typed_headers(&self) -> Result<TypedHeaders, headers::Error> {
+        use headers::HeaderMapExt;
+
+        let headers = self.headers();
+        let typed = self.typed_headers();
+
+        // The authorization header is only malformed if it matches none of the known schemes
+        if headers.contains_key(http::header::AUTHORIZATION)
+            && typed.basic_auth.is_none()
+            && typed.bearer_auth.is_none()
+        {
+            return Err(headers::Error::invalid());
+        }
+
+        Ok(TypedHeaders {
+            content_type: headers.typed_try_get()?,
+            content_length: headers.typed_try_get()?,
+            host: headers.typed_try_get()?,
+            user_agent: headers.typed_try_get()?,
+            ..typed
+        })
+    }
+
+    fn effective_authority(&self) -> Result<(String, u16), StatusCode> {
+        use http::{uri::Authority, Version};
+
+        let conn = self.extensions().get::<crate::ConnectionInfo>();
+        let https = self.uri().scheme_str() == Some("https") || conn.is_some_and(|c| c.is_tls());
+        let default_port = if https { 443 } else { 80 };
+
+        let authority = match self.uri().authority() {
+            Some(authority) => authority.clone(),
+            None => {
+                let mut hosts = self.headers().get_all(http::header::HOST).iter();
+                match (hosts.next(), hosts.next()) {
+                    (Some(host), None) => {
+                        Authority::try_from(host.as_bytes()).map_err(|_| StatusCode::BAD_REQUEST)?
+                    }
+                    (None, _) if self.version() < Version::HTTP_11 => {
+                        let addr = conn
+                            .and_then(|conn| conn.local_addr())
+                            .ok_or(StatusCode::BAD_REQUEST)?;
+                        let host = match addr.ip() {
+                            std::net::IpAddr::V4(ip) => ip.to_string(),
+                            std::net::IpAddr::V6(ip) => format!("[{ip}]"),
+                        };
+                        return Ok((host, addr.port()));
+                    }
+                    _ => return Err(StatusCode::BAD_REQUEST),
+                }
+            }
+        };
+
+        // User info is deprecated in http URIs and never valid on the Host header
+        if authority.host().is_empty() || authority.as_str().contains('@') {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        let port = authority.port_u16().unwrap_or(default_port);
+        Ok((authority.host().to_ascii_lowercase(), port))
+    }
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub(crate) struct ParseConfig {
+    pub(crate) body_buffer_size: usize,
+    pub(crate) max_chunk_size: Option<u64>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) header_read_timeout: Option<Duration>,
+    pub(crate) capture_limit: Option<usize>,
+    pub(crate) max_headers: usize,
+    pub(crate) max_request_line: usize,
+    pub(crate) allow_bare_lf: bool,
+    pub(crate) allow_obs_fold: bool,
+}
+
+#[cfg(feature = "server")]
+impl Default for ParseConfig {
+    fn default() -> Self {
+        Self {
+            body_buffer_size: crate::body::DEFAULT_BUFFER_SIZE,
+            max_chunk_size: None,
+            read_timeout: None,
+            header_read_timeout: None,
+            capture_limit: None,
+            max_headers: 64,
+            max_request_line: 8 * 1024,
+            allow_bare_lf: false,
+            allow_obs_fold: false,
+        }
+    }
 }
 
 #[cfg(feature = "server")]
 pub(crate) fn parse_request(
     mut stream: impl BufRead + Send + 'static,
+    config: &ParseConfig,
+    conn: Option<&Connection>,
 ) -> Result<Request<Body>, ParseError> {
     use headers::HeaderMapExt;
-    use http::{Method, Version};
+    use http::{HeaderValue, Method, Uri, Version};
 
     let mut buf = Vec::with_capacity(800);
+    let mut lines = 0;
+
+    // Deadline for the whole request head, started by its first byte. Reads are bounded by the
+    // remaining time, so clients can't keep the connection busy by sending it byte by byte.
+    let mut deadline = None;
 
     loop {
-        if stream.read_until(b'\n', &mut buf)? == 0 {
+        if let (Some(deadline), Some(conn)) = (deadline, conn) {
+            let remaining = deadline_remaining(deadline)?;
+            let timeout = config.read_timeout.map_or(remaining, |t| t.min(remaining));
+            conn.set_read_timeout(Some(timeout))?;
+        }
+
+        let available = match stream.fill_buf() {
+            Ok(available) => available,
+            Err(err) if deadline.is_some() && is_timeout(&err) => {
+                deadline_remaining(deadline.unwrap())?;
+                return Err(err.into());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        if available.is_empty() {
             break;
         }
 
+        if deadline.is_none() {
+            deadline = config
+                .header_read_timeout
+                .map(|timeout| Instant::now() + timeout);
+        }
+
+        let (line, complete) = match available.iter().position(|byte| *byte == b'\n') {
+            Some(pos) => (&available[..=pos], true),
+            None => (available, false),
+        };
+        let len = line.len();
+        buf.extend_from_slice(line);
+        stream.consume(len);
+
+        // Checked while the request line is still being read, before it is ever parsed
+        if lines == 0 && request_line_len(&buf, complete) > config.max_request_line {
+            return Err(ProtocolError::RequestLineTooLong.into());
+        }
+
+        if !complete {
+            continue;
+        }
+
+        // Parsers disagreeing on line terminators is a known request smuggling vector
+        if !config.allow_bare_lf && !buf.ends_with(b"\r\n") {
+            return Err(ProtocolError::BareLineFeed.into());
+        }
+
         match buf.as_slice() {
             [.., b'\r', b'\n', b'\r', b'\n'] => break,
             [.., b'\n', b'\n'] => break,
-            _ => continue,
+            [b'\r', b'\n'] | [b'\n'] => continue,
+            _ => {}
         }
+
+        // Bail out as soon as there are too many headers, instead of reading them all first
+        lines += 1;
+        if lines > config.max_headers + 1 {
+            return Err(ProtocolError::TooManyHeaders.into());
+        }
+    }
+
+    if let (Some(_), Some(conn)) = (deadline, conn) {
+        conn.set_read_timeout(config.read_timeout)?;
     }
 
     if buf.is_empty() {
         return Err(ParseError::ConnectionClosed);
     }
 
-    let mut headers = [httparse::EMPTY_HEADER; 64];
+    // Captured before unfolding, so the capture holds the bytes as they arrived
+    let raw = config
+        .capture_limit
+        .map(|limit| RawRequest::new(&buf, limit));
+    let buf = unfold(buf, config.allow_obs_fold)?;
+
+    let mut headers = vec![httparse::EMPTY_HEADER; config.max_headers];
     let mut req = httparse::Request::new(&mut headers);
     req.parse(&buf)?;
 
@@ -72,42 +405,443 @@ pub(crate) fn parse_request(
         version => return Err(ParseError::UnsupportedHttpVersion(version)),
     };
 
+    let uri = path
+        .parse::<Uri>()
+        .map_err(|_| ParseError::IncompleteRequest)?;
+
+    // Proxies receive absolute-form targets, whose authority overrides the Host header.
+    // https://datatracker.ietf.org/doc/html/rfc7230#section-5.4
+    let absolute_authority = uri
+        .authority()
+        .filter(|_| uri.scheme().is_some())
+        .map(|authority| HeaderValue::from_str(authority.as_str()))
+        .transpose()
+        .map_err(|_| ParseError::IncompleteRequest)?;
+
     let request = Request::builder()
         .method(Method::from_bytes(method).map_err(|_| ParseError::IncompleteRequest)?)
-        .uri(path)
+        .uri(uri)
         .version(version);
 
-    let request = headers
+    let mut request = headers
         .into_iter()
         .take_while(|header| *header != httparse::EMPTY_HEADER)
         .map(|header| (header.name, header.value))
         .fold(request, |req, (name, value)| req.header(name, value));
 
+    if let (Some(authority), Some(headers)) = (absolute_authority, request.headers_mut()) {
+        headers.insert(http::header::HOST, authority);
+    }
+
     let headers = request.headers_ref().ok_or(ParseError::Unknown)?;
 
-    let body = if let Some(encoding) = headers.typed_try_get::<headers::TransferEncoding>()? {
-        if !encoding.is_chunked() {
-            // https://datatracker.ietf.org/doc/html/rfc2616#section-3.6
-            return Err(ParseError::InvalidTransferEncoding);
-        }
-        Body::from_iter(ChunkedReader(Box::new(stream)))
-    } else if let Some(len) = headers.typed_try_get::<headers::ContentLength>()? {
+    // Clients expecting `100 Continue` only send the body after being told so, and not at all if
+    // the server answers with a final status instead.
+    let expects_continue = headers
+        .typed_get::<headers::Expect>()
+        .filter(|expect| expect == &headers::Expect::CONTINUE)
+        .is_some();
+
+    let stream: Box<dyn BufRead + Send> = match raw {
+        Some(ref raw) => Box::new(Capturing {
+            inner: stream,
+            raw: raw.clone(),
+        }),
+        None => Box::new(stream),
+    };
+
+    let discarder = expects_continue.then(BodyDiscarder::default);
+    let mut stream: Box<dyn BufRead + Send> = match discarder {
+        Some(ref discarder) => Box::new(Discardable {
+            inner: stream,
+            discarded: discarder.0.clone(),
+        }),
+        None => Box::new(stream),
+    };
+
+    // Requests with both headers may be framed differently by intermediaries, which is the basis
+    // of request smuggling.
+    // https://datatracker.ietf.org/doc/html/rfc7230#section-3.3.3
+    if headers.contains_key(http::header::TRANSFER_ENCODING)
+        && headers.contains_key(http::header::CONTENT_LENGTH)
+    {
+        return Err(ProtocolError::AmbiguousLength.into());
+    }
+
+    let end = BodyEnd::default();
+    let body = if is_chunked(headers)? {
+        Body::from_results(
+            ChunkedReader::new(stream)
+                .max_chunk_size(config.max_chunk_size)
+                .drain_on_drop()
+                .track_end(end.clone()),
+        )
+    } else if let Some(len) = content_length(headers)? {
         // Let's automatically buffer small bodies
-        if len.0 < 1024 {
-            let mut buf = vec![0_u8; len.0 as usize];
+        if len < 1024 && !expects_continue {
+            let mut buf = vec![0_u8; len as usize];
             stream.read_exact(&mut buf)?;
+            end.reach();
             Body::from(buf)
         } else {
-            Body::from_reader(stream, len.0 as usize)
+            let stream = FixedLengthBody::new(stream, len, end.clone());
+            Body::from_reader(stream, len as usize).with_buffer_size(config.body_buffer_size)
         }
     } else {
+        end.reach();
         Body::empty()
     };
 
+    let request = request.extension(end);
+
+    let request = match discarder {
+        Some(discarder) => request.extension(discarder),
+        None => request,
+    };
+
+    let request = match raw {
+        Some(raw) => request.extension(raw),
+        None => request,
+    };
+
     request.body(body).map_err(|_| ParseError::Unknown)
 }
 
-#[cfg(feature = "client")]
+/// Validates the `Transfer-Encoding` codings of a request, returning whether its body is chunked.
+///
+/// Only `chunked` is supported, which must be the last coding, and `identity`, which is ignored.
+#[cfg(feature = "server")]
+fn is_chunked(headers: &http::HeaderMap) -> Result<bool, ParseError> {
+    let mut chunked = false;
+
+    for value in headers.get_all(http::header::TRANSFER_ENCODING) {
+        let value = value.to_str().map_err(|_| ProtocolError::InvalidHeader)?;
+
+        for coding in value.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            if chunked {
+                return Err(ProtocolError::InvalidHeader.into());
+            } else if coding.eq_ignore_ascii_case("chunked") {
+                chunked = true;
+            } else if !coding.eq_ignore_ascii_case("identity") {
+                // https://datatracker.ietf.org/doc/html/rfc7230#section-3.3.1
+                return Err(ParseError::InvalidTransferEncoding);
+            }
+        }
+    }
+
+    Ok(chunked)
+}
+
+/// Parses the `Content-Length` of a request. Repeated values (either on multiple headers or as a
+/// list) are accepted only if they are all the same, as picking one of them could make us frame
+/// the request differently than intermediaries.
+/// https://datatracker.ietf.org/doc/html/rfc7230#section-3.3.2
+#[cfg(feature = "server")]
+fn content_length(headers: &http::HeaderMap) -> Result<Option<u64>, ParseError> {
+    let mut content_length = None;
+
+    for value in headers.get_all(http::header::CONTENT_LENGTH) {
+        let value = value.to_str().map_err(|_| ProtocolError::InvalidHeader)?;
+
+        for len in value.split(',').map(str::trim) {
+            if len.is_empty() || !len.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(ProtocolError::InvalidHeader.into());
+            }
+
+            let len = len
+                .parse::<u64>()
+                .map_err(|_| ProtocolError::InvalidHeader)?;
+
+            match content_length {
+                Some(previous) if previous != len => {
+                    return Err(ProtocolError::AmbiguousLength.into());
+                }
+                _ => content_length = Some(len),
+            }
+        }
+    }
+
+    Ok(content_length)
+}
+
+/// Tells whether the body of a request was read up to its end, leaving the connection right at the
+/// start of the next request. Bodies left unread are drained when dropped, but draining fails on
+/// broken framing or when the client goes away, and the connection can't be reused then.
+#[cfg(feature = "server")]
+#[derive(Clone, Default)]
+pub(crate) struct BodyEnd(Arc<AtomicBool>);
+
+#[cfg(feature = "server")]
+impl BodyEnd {
+    fn reach(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_reached(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A request body delimited by `Content-Length`, which reads whatever is left of it when dropped.
+#[cfg(feature = "server")]
+struct FixedLengthBody<R: Read> {
+    stream: R,
+    remaining: u64,
+    end: BodyEnd,
+}
+
+#[cfg(feature = "server")]
+impl<R: Read> FixedLengthBody<R> {
+    fn new(stream: R, len: u64, end: BodyEnd) -> Self {
+        if len == 0 {
+            end.reach();
+        }
+        Self {
+            stream,
+            remaining: len,
+            end,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl<R: Read> Read for FixedLengthBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let read = self.stream.read(&mut buf[..len])?;
+        self.remaining -= read as u64;
+        if self.remaining == 0 {
+            self.end.reach();
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "server")]
+impl<R: Read> Drop for FixedLengthBody<R> {
+    fn drop(&mut self) {
+        if self.remaining > 0 {
+            io::copy(self, &mut io::sink()).ok();
+        }
+    }
+}
+
+/// Cuts short the body of a request expecting `100 Continue`, so it is not drained when the
+/// server rejects the request and the client never sends it.
+#[cfg(feature = "server")]
+#[derive(Clone, Default)]
+pub(crate) struct BodyDiscarder(Arc<AtomicBool>);
+
+#[cfg(feature = "server")]
+impl BodyDiscarder {
+    pub(crate) fn discard(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "server")]
+struct Discardable<R> {
+    inner: R,
+    discarded: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "server")]
+impl<R: Read> Read for Discardable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.discarded.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(feature = "server")]
+impl<R: BufRead> BufRead for Discardable<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.discarded.load(Ordering::Relaxed) {
+            return Ok(&[]);
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+/// The raw bytes of a request, as they arrived on the wire. Only available as a request extension
+/// when the server is configured with
+/// [`capture_raw_requests`](crate::server::ServerBuilder::capture_raw_requests).
+///
+/// # Example
+/// ```no_run
+/// # use touche::{Body, HttpBody, RawRequest, Request, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::builder()
+///     .capture_raw_requests(16 * 1024)
+///     .bind("0.0.0.0:4444")
+///     .serve(|req: Request<Body>| {
+///         let raw = req.extensions().get::<RawRequest>().cloned();
+///         let body = req.into_body().into_bytes()?;
+///         if let Some(raw) = raw {
+///             eprintln!("{}", String::from_utf8_lossy(&raw.bytes()));
+///         }
+///
+///         Response::builder()
+///             .status(StatusCode::OK)
+///             .body(body)
+///             .map_err(std::io::Error::other)
+///     })
+/// # }
+/// ```
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub struct RawRequest {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    limit: usize,
+}
+
+#[cfg(feature = "server")]
+impl RawRequest {
+    fn new(head: &[u8], limit: usize) -> Self {
+        let raw = Self {
+            bytes: Default::default(),
+            limit,
+        };
+        raw.record(head);
+        raw
+    }
+
+    /// Returns the bytes captured so far: the request head, followed by the body bytes already
+    /// read. The capture stops once the configured limit is reached.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+
+    fn record(&self, data: &[u8]) {
+        let mut bytes = self.bytes.lock().unwrap();
+        let len = self.limit.saturating_sub(bytes.len()).min(data.len());
+        bytes.extend_from_slice(&data[..len]);
+    }
+}
+
+/// Records every byte consumed from the inner reader into a [`RawRequest`].
+#[cfg(feature = "server")]
+struct Capturing<R> {
+    inner: R,
+    raw: RawRequest,
+}
+
+#[cfg(feature = "server")]
+impl<R: BufRead> Read for Capturing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "server")]
+impl<R: BufRead> BufRead for Capturing<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Ok(available) = self.inner.fill_buf() {
+            self.raw.record(&available[..amt.min(available.len())]);
+        }
+        self.inner.consume(amt)
+    }
+}
+
+#[cfg(feature = "server")]
+fn deadline_remaining(deadline: Instant) -> Result<Duration, ParseError> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .filter(|remaining| !remaining.is_zero())
+        .ok_or(ParseError::Protocol(ProtocolError::RequestTimeout))
+}
+
+/// Replaces the obsolete line folding of header values (continuation lines starting with spaces or
+/// tabs) with a single space, failing unless `allow` is set. Folded lines are rejected by default,
+/// as parsers disagreeing on them can be abused to smuggle headers.
+///
+/// A continuation right after the request line has no header to continue, so it is always
+/// rejected.
+#[cfg(feature = "server")]
+fn unfold(buf: Vec<u8>, allow: bool) -> Result<Vec<u8>, ProtocolError> {
+    let is_fold = |pos: usize| buf[pos] == b'\n' && matches!(buf.get(pos + 1), Some(b' ' | b'\t'));
+    if !(0..buf.len()).any(is_fold) {
+        return Ok(buf);
+    }
+
+    let request_line_end = buf
+        .iter()
+        .enumerate()
+        .position(|(pos, byte)| {
+            *byte == b'\n' && buf[..pos].iter().any(|b| *b != b'\r' && *b != b'\n')
+        })
+        .unwrap_or(buf.len());
+    if !allow || is_fold(request_line_end) {
+        return Err(ProtocolError::ObsoleteLineFolding);
+    }
+
+    let mut unfolded = Vec::with_capacity(buf.len());
+    let mut pos = 0;
+    while pos < buf.len() {
+        if is_fold(pos) {
+            if unfolded.last() == Some(&b'\r') {
+                unfolded.pop();
+            }
+            unfolded.push(b' ');
+            pos += 1;
+            while matches!(buf.get(pos), Some(b' ' | b'\t')) {
+                pos += 1;
+            }
+        } else {
+            unfolded.push(buf[pos]);
+            pos += 1;
+        }
+    }
+    Ok(unfolded)
+}
+
+/// Length of the request line read so far, ignoring the empty lines that may precede it and its
+/// line terminator once complete.
+#[cfg(feature = "server")]
+fn request_line_len(buf: &[u8], complete: bool) -> usize {
+    let start = buf
+        .iter()
+        .position(|byte| !matches!(byte, b'\r' | b'\n'))
+        .unwrap_or(buf.len());
+    let line = &buf[start..];
+    if !complete {
+        return line.len();
+    }
+    match line {
+        [line @ .., b'\r', b'\n'] | [line @ .., b'\n'] => line.len(),
+        line => line.len(),
+    }
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
 pub(crate) fn write_request<B: HttpBody>(
     req: http::Request<B>,
     stream: &mut impl Write,
@@ -140,10 +874,7 @@ pub(crate) fn write_request<B: HttpBody>(
         match (content_length, body.len()) {
             (Some(len), Some(body_len)) => {
                 if len.0 != body_len {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "content-length doesn't match body length",
-                    ));
+                    return Err(io::Error::other("content-length doesn't match body length"));
                 }
                 Encoding::FixedLength(len.0)
             }
@@ -162,10 +893,7 @@ pub(crate) fn write_request<B: HttpBody>(
         headers.typed_insert::<headers::TransferEncoding>(headers::TransferEncoding::chunked());
         Encoding::Chunked
     } else {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "could not determine the size of the body",
-        ));
+        return Err(io::Error::other("could not determine the size of the body"));
     };
 
     let version = if version == Version::HTTP_11 {
@@ -173,10 +901,7 @@ pub(crate) fn write_request<B: HttpBody>(
     } else if version == Version::HTTP_10 {
         "HTTP/1.0"
     } else {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "unsupported http version",
-        ));
+        return Err(io::Error::other("unsupported http version"));
     };
 
     stream.write_all(format!("{method} {uri} {version}\r\n").as_bytes())?;
@@ -225,32 +950,179 @@ pub(crate) fn write_request<B: HttpBody>(
     Ok(())
 }
 
-pub(crate) struct ChunkedReader(pub(crate) Box<dyn BufRead + Send>);
+/// Maximum length of a chunk size line, including its extensions.
+const MAX_CHUNK_LINE_LENGTH: u64 = 4 * 1024;
 
-impl Iterator for ChunkedReader {
-    type Item = Vec<u8>;
+/// Maximum number of fields in the trailer section of a chunked body.
+const MAX_TRAILERS: usize = 64;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut buf = Vec::new();
+pub(crate) struct ChunkedReader {
+    stream: Box<dyn BufRead + Send>,
+    max_chunk_size: Option<u64>,
+    drain_on_drop: bool,
+    #[cfg(feature = "server")]
+    end: Option<BodyEnd>,
+    done: bool,
+}
+
+impl ChunkedReader {
+    pub(crate) fn new(stream: impl BufRead + Send + 'static) -> Self {
+        Self {
+            stream: Box::new(stream),
+            max_chunk_size: None,
+            drain_on_drop: false,
+            #[cfg(feature = "server")]
+            end: None,
+            done: false,
+        }
+    }
+
+    /// Rejects chunks whose declared size is bigger than `max_chunk_size`.
+    #[cfg(feature = "server")]
+    pub(crate) fn max_chunk_size(mut self, max_chunk_size: Option<u64>) -> Self {
+        self.max_chunk_size = max_chunk_size;
+        self
+    }
+
+    /// Reads the remaining chunks when dropped, so a body left unread by the handler does not
+    /// leak into the next request of the connection.
+    #[cfg(feature = "server")]
+    pub(crate) fn drain_on_drop(mut self) -> Self {
+        self.drain_on_drop = true;
+        self
+    }
+
+    /// Reaches `end` once the last chunk and the trailers are read.
+    #[cfg(feature = "server")]
+    pub(crate) fn track_end(mut self, end: BodyEnd) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    fn read_chunk(&mut self) -> io::Result<Option<Chunk>> {
+        let line = self.read_line()?;
+
+        // Chunk extensions are skipped by httparse, their values are not needed
+        let size = match httparse::parse_chunk_size(&line) {
+            Ok(httparse::Status::Complete((_pos, size))) => size,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid chunk size",
+                ))
+            }
+        };
+
+        if size == 0 {
+            let trailers = self.read_trailers()?;
+            return Ok(Some(trailers)
+                .filter(|trailers| !trailers.is_empty())
+                .map(Chunk::Trailers));
+        }
+
+        if self.max_chunk_size.filter(|max| size > *max).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk size exceeds the limit",
+            ));
+        }
+
+        // The chunk is read as its bytes arrive, so a crafted chunk size can't make us allocate
+        // more memory than what was actually sent.
+        let mut chunk = Vec::with_capacity(size.min(8 * 1024) as usize);
+        self.stream.by_ref().take(size).read_to_end(&mut chunk)?;
+        if chunk.len() as u64 != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        if !matches!(self.read_line()?.as_slice(), b"\r\n" | b"\n") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing line break after chunk data",
+            ));
+        }
+
+        Ok(Some(Chunk::Data(chunk)))
+    }
+
+    /// Reads the trailer section that follows the last chunk, up to the empty line ending it.
+    fn read_trailers(&mut self) -> io::Result<HeaderMap> {
+        let mut trailers = HeaderMap::new();
 
         loop {
-            if self.0.read_until(b'\n', &mut buf).ok()? == 0 {
-                return None;
+            let line = self.read_line()?;
+            if matches!(line.as_slice(), b"\r\n" | b"\n") {
+                return Ok(trailers);
             }
 
-            match httparse::parse_chunk_size(&buf) {
-                Ok(httparse::Status::Complete((_pos, 0))) => {
-                    return None;
-                }
-                Ok(httparse::Status::Complete((_pos, size))) => {
-                    let mut chunk = vec![0_u8; size as usize];
-                    self.0.read_exact(&mut chunk).ok()?;
-                    self.0.read_until(b'\n', &mut buf).ok()?;
-                    return Some(chunk);
-                }
-                Ok(httparse::Status::Partial) => continue,
-                Err(_) => return None,
+            if trailers.len() >= MAX_TRAILERS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "too many trailers",
+                ));
             }
+
+            let (name, value) = line
+                .iter()
+                .position(|byte| *byte == b':')
+                .map(|colon| line.split_at(colon))
+                .and_then(|(name, value)| {
+                    let name = HeaderName::from_bytes(name).ok()?;
+                    let value = HeaderValue::from_bytes(value[1..].trim_ascii()).ok()?;
+                    Some((name, value))
+                })
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid trailer"))?;
+
+            trailers.append(name, value);
+        }
+    }
+
+    /// Reads a line of the chunked framing. Lines are bounded, so clients can't make us buffer
+    /// endless chunk extensions.
+    fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        let mut line = Vec::new();
+        let read = self
+            .stream
+            .by_ref()
+            .take(MAX_CHUNK_LINE_LENGTH)
+            .read_until(b'\n', &mut line)?;
+
+        if line.ends_with(b"\n") {
+            Ok(line)
+        } else if read as u64 == MAX_CHUNK_LINE_LENGTH {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk line too long",
+            ))
+        } else {
+            Err(io::ErrorKind::UnexpectedEof.into())
+        }
+    }
+}
+
+impl Iterator for ChunkedReader {
+    type Item = io::Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let chunk = self.read_chunk().transpose();
+        // The trailers, if any, are the last thing sent
+        self.done = !matches!(chunk, Some(Ok(Chunk::Data(_))));
+        #[cfg(feature = "server")]
+        if let (None | Some(Ok(_)), true, Some(end)) = (&chunk, self.done, &self.end) {
+            end.reach();
+        }
+        chunk
+    }
+}
+
+impl Drop for ChunkedReader {
+    fn drop(&mut self) {
+        if self.drain_on_drop {
+            while let Some(Ok(_)) = self.next() {}
         }
     }
 }
@@ -263,12 +1135,116 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn decodes_typed_headers() {
+        let req = Request::builder()
+            .header("content-type", "application/json")
+            .header("authorization", "Bearer lolwut")
+            .header("host", "lol.com:8080")
+            .header("user-agent", "touche")
+            .body(())
+            .unwrap();
+
+        let headers = req.typed_headers();
+        assert_eq!(headers.content_type, Some(headers::ContentType::json()));
+        assert_eq!(headers.bearer_auth.unwrap().token(), "lolwut");
+        assert!(headers.basic_auth.is_none());
+        assert_eq!(headers.host.unwrap().port(), Some(8080));
+        assert_eq!(headers.user_agent.unwrap().as_str(), "touche");
+        assert!(headers.content_length.is_none());
+    }
+
+    #[test]
+    fn resolves_the_effective_authority() {
+        let authority = |req: http::request::Builder| req.body(()).unwrap().effective_authority();
+
+        let req = Request::get("/").header("host", "Lol.com");
+        assert_eq!(authority(req), Ok(("lol.com".to_string(), 80)));
+
+        let req = Request::get("/").header("host", "[::1]:8080");
+        assert_eq!(authority(req), Ok(("[::1]".to_string(), 8080)));
+
+        let req = Request::get("https://wut.com/lol").header("host", "lol.com");
+        assert_eq!(authority(req), Ok(("wut.com".to_string(), 443)));
+
+        let req = Request::get("/");
+        assert_eq!(authority(req), Err(StatusCode::BAD_REQUEST));
+
+        let req = Request::get("/")
+            .header("host", "lol.com")
+            .header("host", "wut.com");
+        assert_eq!(authority(req), Err(StatusCode::BAD_REQUEST));
+
+        let req = Request::get("/").header("host", "user@lol.com");
+        assert_eq!(authority(req), Err(StatusCode::BAD_REQUEST));
+
+        // Without a connection there is no local address to fall back to
+        let req = Request::get("/").version(http::Version::HTTP_10);
+        assert_eq!(authority(req), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn only_fails_on_malformed_typed_headers_when_strict() {
+        let req = Request::builder()
+            .header("content-length", "lol")
+            .header("authorization", "Lol wut")
+            .body(())
+            .unwrap();
+
+        let headers = req.typed_headers();
+        assert!(headers.content_length.is_none());
+        assert!(headers.bearer_auth.is_none());
+        assert!(req.try_typed_headers().is_err());
+
+        let req = Request::builder()
+            .header("authorization", "Basic bG9sOnd1dA==")
+            .body(())
+            .unwrap();
+
+        let headers = req.try_typed_headers().unwrap();
+        assert_eq!(headers.basic_auth.unwrap().username(), "lol");
+    }
+
+    #[test]
+    fn parse_origin_form_targets() {
+        let req = "GET /lol?wut=1 HTTP/1.1\r\nHost: lol.com\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+
+        assert_eq!(req.uri().scheme(), None);
+        assert_eq!(req.uri().authority(), None);
+        assert_eq!(req.uri().path(), "/lol");
+        assert_eq!(req.uri().query(), Some("wut=1"));
+        assert_eq!(req.headers()["host"], "lol.com");
+    }
+
+    #[test]
+    fn parse_absolute_form_targets() {
+        let req = "GET http://wut.com:8080/lol?wut=1 HTTP/1.1\r\nHost: lol.com\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+
+        assert_eq!(req.uri(), "http://wut.com:8080/lol?wut=1");
+        assert_eq!(req.uri().scheme_str(), Some("http"));
+        assert_eq!(req.uri().path(), "/lol");
+        assert_eq!(req.headers()["host"], "wut.com:8080");
+        assert_eq!(req.headers().get_all("host").iter().count(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_request_targets() {
+        let req = "GET http://[::1/lol HTTP/1.1\r\n\r\n";
+        let err = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap_err();
+        assert_eq!(
+            err.protocol_error(),
+            Some(ProtocolError::InvalidRequestLine)
+        );
+    }
+
     #[test]
     fn parse_request_without_body() {
         let req = "GET /lolwut HTTP/1.1\r\nHost: lol.com\r\n\r\n";
         let req = std::io::Cursor::new(req);
 
-        let req = parse_request(req).unwrap();
+        let req = parse_request(req, &Default::default(), None).unwrap();
 
         assert_eq!(Version::HTTP_11, req.version());
         assert_eq!("/lolwut", req.uri().path());
@@ -280,12 +1256,27 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_connect_request() {
+        let req = "CONNECT lol.com:443 HTTP/1.1\r\nHost: lol.com:443\r\n\r\n";
+        let req = std::io::Cursor::new(req);
+
+        let req = parse_request(req, &Default::default(), None).unwrap();
+
+        assert_eq!(http::Method::CONNECT, req.method());
+        assert_eq!(
+            Some("lol.com:443"),
+            req.uri().authority().map(|a| a.as_str())
+        );
+        assert_eq!(req.into_body().len(), Some(0));
+    }
+
     #[test]
     fn parse_request_with_content_length_body() {
         let req = "POST /lol HTTP/1.1\r\nHost: lol.com\r\nContent-Length: 6\r\n\r\nlolwut ignored";
         let req = std::io::Cursor::new(req);
 
-        let req = parse_request(req).unwrap();
+        let req = parse_request(req, &Default::default(), None).unwrap();
 
         assert_eq!(req.into_body().into_bytes().unwrap(), b"lolwut");
     }
@@ -295,7 +1286,7 @@ mod test {
         let req = "POST /lol HTTP/1.1\r\nHost: lol.com\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nlol\r\n3\r\nwut\r\n0\r\n\r\n";
         let req = std::io::Cursor::new(req);
 
-        let req = parse_request(req).unwrap();
+        let req = parse_request(req, &Default::default(), None).unwrap();
 
         assert_eq!(req.into_body().into_bytes().unwrap(), b"lolwut");
     }
@@ -305,18 +1296,97 @@ mod test {
         let req = "POST /lol HTTP/1.1\r\nHost: lol.com\r\nTransfer-Encoding: chunked\r\n\r\n3;extension\r\nlol\r\n3\r\nwut\r\n0\r\n\r\n";
         let req = std::io::Cursor::new(req);
 
-        let req = parse_request(req).unwrap();
+        let req = parse_request(req, &Default::default(), None).unwrap();
 
         assert_eq!(req.into_body().into_bytes().unwrap(), b"lolwut");
     }
 
+    #[test]
+    fn parse_request_with_chunked_body_and_trailers() {
+        let req = "POST /lol HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTrailer: content-md5\r\n\r\n3\r\nlol\r\n0\r\nContent-MD5: 9cdfb439c7876e703e307864c9167a15\r\nX-Lol:wut\r\n\r\n";
+        let req = std::io::Cursor::new(req);
+
+        let req = parse_request(req, &Default::default(), None).unwrap();
+
+        let (bytes, trailers) = req.into_body().into_bytes_with_trailers().unwrap();
+        assert_eq!(bytes, b"lol");
+        assert_eq!(trailers["content-md5"], "9cdfb439c7876e703e307864c9167a15");
+        assert_eq!(trailers["x-lol"], "wut");
+
+        let req = "POST /lol HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nlol\r\n0\r\nlolwut\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+        assert!(req.into_body().into_bytes_with_trailers().is_err());
+    }
+
+    #[test]
+    fn rejects_chunks_bigger_than_the_limit() {
+        let req = "POST /lol HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nlol\r\nffffffffff\r\nwut\r\n0\r\n\r\n";
+        let req = std::io::Cursor::new(req);
+
+        let config = ParseConfig {
+            max_chunk_size: Some(1024),
+            ..Default::default()
+        };
+        let req = parse_request(req, &config, None).unwrap();
+
+        let err = req.into_body().into_bytes().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_chunk_extensions_with_values() {
+        let req = "POST /lol HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3;lol=wut;name=\"quoted value\"\r\nlol\r\n0;lol\r\n\r\n";
+        let req = std::io::Cursor::new(req);
+
+        let req = parse_request(req, &Default::default(), None).unwrap();
+
+        assert_eq!(req.into_body().into_bytes().unwrap(), b"lol");
+    }
+
+    #[test]
+    fn rejects_oversized_chunk_extensions() {
+        let req = format!(
+            "POST /lol HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3;lol={}\r\nlol\r\n0\r\n\r\n",
+            "a".repeat(64 * 1024)
+        );
+        let req = std::io::Cursor::new(req);
+
+        let req = parse_request(req, &Default::default(), None).unwrap();
+
+        let err = req.into_body().into_bytes().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_chunks_without_line_break_after_data() {
+        let req =
+            "POST /lol HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nlolwut\r\n0\r\n\r\n";
+        let req = std::io::Cursor::new(req);
+
+        let req = parse_request(req, &Default::default(), None).unwrap();
+
+        let err = req.into_body().into_bytes().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn fails_on_chunks_smaller_than_informed() {
+        let req = "POST /lol HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nffffffffff\r\nlolwut";
+        let req = std::io::Cursor::new(req);
+
+        let req = parse_request(req, &Default::default(), None).unwrap();
+
+        let err = req.into_body().into_bytes().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
     #[test]
     fn parse_request_with_streaming_body() {
         let req = b"POST /lol HTTP/1.1\r\nHost: lol.com\r\nContent-Length: 2048\r\n\r\n";
         let body = [65_u8; 2048];
         let req = std::io::Cursor::new([req.as_ref(), body.as_ref()].concat());
 
-        let req = parse_request(req).unwrap();
+        let req = parse_request(req, &Default::default(), None).unwrap();
 
         assert_eq!(req.into_body().into_bytes().unwrap(), body);
     }
@@ -326,8 +1396,274 @@ mod test {
         let req = std::io::Cursor::new("POST /lol");
 
         assert!(matches!(
-            parse_request(req),
+            parse_request(req, &Default::default(), None),
             Err(ParseError::IncompleteRequest)
         ));
     }
+
+    #[test]
+    fn maps_parse_errors_to_protocol_errors() {
+        let req = std::io::Cursor::new("GET / HTTP/1.1\r\nHost lol.com\r\n\r\n");
+        let err = parse_request(req, &Default::default(), None).unwrap_err();
+        assert_eq!(err.protocol_error(), Some(ProtocolError::InvalidHeader));
+
+        let req = std::io::Cursor::new("GET / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n");
+        let err = parse_request(req, &Default::default(), None).unwrap_err();
+        assert_eq!(
+            err.protocol_error().map(|err| err.status()),
+            Some(StatusCode::NOT_IMPLEMENTED)
+        );
+
+        let req = std::io::Cursor::new("");
+        let err = parse_request(req, &Default::default(), None).unwrap_err();
+        assert_eq!(err.protocol_error(), None);
+    }
+
+    #[test]
+    fn limits_the_number_of_headers() {
+        let config = ParseConfig {
+            max_headers: 2,
+            ..Default::default()
+        };
+
+        let req = "GET / HTTP/1.1\r\nx-lol: 1\r\nx-wut: 2\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &config, None).unwrap();
+        assert_eq!(req.headers().len(), 2);
+
+        // The rest of the head is never read
+        let req = "GET / HTTP/1.1\r\nx-lol: 1\r\nx-wut: 2\r\nx-lolwut: 3\r\n";
+        let err = parse_request(std::io::Cursor::new(req), &config, None).unwrap_err();
+        assert_eq!(err.protocol_error(), Some(ProtocolError::TooManyHeaders));
+        assert_eq!(
+            ProtocolError::TooManyHeaders.status(),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+    }
+
+    #[test]
+    fn limits_the_request_line_length() {
+        let config = ParseConfig {
+            max_request_line: 32,
+            ..Default::default()
+        };
+
+        let req = "GET /0123456789012345 HTTP/1.1\r\n\r\n";
+        assert!(parse_request(std::io::Cursor::new(req), &config, None).is_ok());
+
+        // Rejected before the line is complete, so it is never parsed
+        let req = format!("GET /{} HTTP/1.1", "a".repeat(64));
+        let err = parse_request(std::io::Cursor::new(req), &config, None).unwrap_err();
+        assert_eq!(
+            err.protocol_error(),
+            Some(ProtocolError::RequestLineTooLong)
+        );
+        assert_eq!(
+            ProtocolError::RequestLineTooLong.status(),
+            StatusCode::URI_TOO_LONG
+        );
+    }
+
+    #[test]
+    fn rejects_bare_line_feeds() {
+        let req = "GET /\nHost: x\n\n";
+        let err = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap_err();
+        assert_eq!(err.protocol_error(), Some(ProtocolError::BareLineFeed));
+        assert_eq!(
+            ProtocolError::BareLineFeed.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let req = "GET / HTTP/1.1\r\nHost: x\n\r\n";
+        let err = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap_err();
+        assert_eq!(err.protocol_error(), Some(ProtocolError::BareLineFeed));
+
+        let config = ParseConfig {
+            allow_bare_lf: true,
+            ..Default::default()
+        };
+        let req = "GET / HTTP/1.1\nHost: x\n\n";
+        let req = parse_request(std::io::Cursor::new(req), &config, None).unwrap();
+        assert_eq!(req.headers()["host"], "x");
+    }
+
+    #[test]
+    fn handles_obsolete_line_folding() {
+        let req = "GET / HTTP/1.1\r\nx-lol: lol\r\n  wut\r\nhost: x\r\n\r\n";
+        let err = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap_err();
+        assert_eq!(
+            err.protocol_error(),
+            Some(ProtocolError::ObsoleteLineFolding)
+        );
+        assert_eq!(
+            ProtocolError::ObsoleteLineFolding.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let config = ParseConfig {
+            allow_obs_fold: true,
+            ..Default::default()
+        };
+        let req = parse_request(std::io::Cursor::new(req), &config, None).unwrap();
+        assert_eq!(req.headers()["x-lol"], "lol wut");
+        assert_eq!(req.headers()["host"], "x");
+
+        let req = "GET / HTTP/1.1\r\n\tx-lol: lol\r\n\r\n";
+        let err = parse_request(std::io::Cursor::new(req), &config, None).unwrap_err();
+        assert_eq!(
+            err.protocol_error(),
+            Some(ProtocolError::ObsoleteLineFolding)
+        );
+    }
+
+    fn parse_error(req: &str) -> Option<ProtocolError> {
+        let req = std::io::Cursor::new(req.to_string());
+        parse_request(req, &Default::default(), None)
+            .unwrap_err()
+            .protocol_error()
+    }
+
+    #[test]
+    fn accepts_chunked_and_identity_transfer_codings() {
+        let req =
+            "POST / HTTP/1.1\r\nTransfer-Encoding: identity, chunked\r\n\r\n3\r\nlol\r\n0\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+        assert_eq!(req.into_body().into_bytes().unwrap(), b"lol");
+
+        let req = "POST / HTTP/1.1\r\nTransfer-Encoding: identity\r\nTransfer-Encoding: CHUNKED\r\n\r\n3\r\nlol\r\n0\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+        assert_eq!(req.into_body().into_bytes().unwrap(), b"lol");
+
+        let req = "POST / HTTP/1.1\r\nTransfer-Encoding: identity\r\n\r\nlol";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+        assert_eq!(req.into_body().into_bytes().unwrap(), b"");
+    }
+
+    #[test]
+    fn rejects_unsupported_transfer_codings() {
+        assert_eq!(
+            parse_error("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"),
+            Some(ProtocolError::UnsupportedTransferEncoding)
+        );
+        assert_eq!(
+            parse_error("POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n"),
+            Some(ProtocolError::InvalidHeader)
+        );
+        assert_eq!(
+            parse_error("POST / HTTP/1.1\r\nTransfer-Encoding: chunked, chunked\r\n\r\n"),
+            Some(ProtocolError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn rejects_transfer_encoding_with_content_length() {
+        let err = parse_error(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n3\r\nlol\r\n0\r\n\r\n",
+        );
+        assert_eq!(err, Some(ProtocolError::AmbiguousLength));
+        assert_eq!(err.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn accepts_repeated_identical_content_lengths() {
+        let req = "POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3\r\n\r\nlol";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+        assert_eq!(req.into_body().into_bytes().unwrap(), b"lol");
+
+        let req = "POST / HTTP/1.1\r\nContent-Length: 3, 3\r\n\r\nlol";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+        assert_eq!(req.into_body().into_bytes().unwrap(), b"lol");
+    }
+
+    #[test]
+    fn rejects_conflicting_content_lengths() {
+        assert_eq!(
+            parse_error("POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 6\r\n\r\nlolwut"),
+            Some(ProtocolError::AmbiguousLength)
+        );
+        assert_eq!(
+            parse_error("POST / HTTP/1.1\r\nContent-Length: 3, 6\r\n\r\nlolwut"),
+            Some(ProtocolError::AmbiguousLength)
+        );
+        assert_eq!(
+            parse_error(
+                "POST / HTTP/1.1\r\nTransfer-Encoding: identity\r\nContent-Length: 3\r\n\r\nlol"
+            ),
+            Some(ProtocolError::AmbiguousLength)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_content_lengths() {
+        for len in ["+3", "-3", "3a", "0x3", "", "3,", "99999999999999999999"] {
+            let req = format!("POST / HTTP/1.1\r\nContent-Length: {len}\r\n\r\nlol");
+            assert_eq!(
+                parse_error(&req),
+                Some(ProtocolError::InvalidHeader),
+                "{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn captures_raw_requests() {
+        let config = ParseConfig {
+            capture_limit: Some(1024),
+            ..Default::default()
+        };
+        let req = "POST / HTTP/1.1\r\ncontent-length: 6\r\n\r\nlolwut";
+        let req = parse_request(std::io::Cursor::new(req), &config, None).unwrap();
+        let raw = req.extensions().get::<RawRequest>().unwrap().clone();
+        assert_eq!(
+            raw.bytes(),
+            b"POST / HTTP/1.1\r\ncontent-length: 6\r\n\r\nlolwut"
+        );
+
+        let config = ParseConfig {
+            capture_limit: Some(20),
+            ..Default::default()
+        };
+        let req = "POST / HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n3\r\nlol\r\n0\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &config, None).unwrap();
+        let raw = req.extensions().get::<RawRequest>().unwrap().clone();
+        assert_eq!(req.into_body().into_bytes().unwrap(), b"lol");
+        assert_eq!(raw.bytes(), b"POST / HTTP/1.1\r\ntra");
+
+        let req = "GET / HTTP/1.1\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &Default::default(), None).unwrap();
+        assert!(req.extensions().get::<RawRequest>().is_none());
+    }
+
+    #[test]
+    fn captures_folded_requests_as_they_arrived() {
+        let config = ParseConfig {
+            capture_limit: Some(1024),
+            allow_obs_fold: true,
+            ..Default::default()
+        };
+        let req = "GET / HTTP/1.1\r\nx-lol: lol\r\n\twut\r\n\r\n";
+        let req = parse_request(std::io::Cursor::new(req), &config, None).unwrap();
+        assert_eq!(req.headers()["x-lol"], "lol wut");
+
+        let raw = req.extensions().get::<RawRequest>().unwrap().clone();
+        assert_eq!(
+            raw.bytes(),
+            b"GET / HTTP/1.1\r\nx-lol: lol\r\n\twut\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn captures_streamed_bodies_as_they_are_read() {
+        let config = ParseConfig {
+            capture_limit: Some(usize::MAX),
+            ..Default::default()
+        };
+        let body = "lol".repeat(1000);
+        let req = format!("POST / HTTP/1.1\r\ncontent-length: 3000\r\n\r\n{body}");
+        let req = parse_request(std::io::Cursor::new(req), &config, None).unwrap();
+        let raw = req.extensions().get::<RawRequest>().unwrap().clone();
+        assert!(raw.bytes().ends_with(b"\r\n\r\n"));
+
+        assert_eq!(req.into_body().into_bytes().unwrap(), body.as_bytes());
+        assert!(raw.bytes().ends_with(body.as_bytes()));
+    }
 }
@@ -1,33 +1,232 @@
-#[cfg(any(feature = "client", test))]
+#[cfg(any(feature = "client", feature = "server", test))]
 use std::io::BufRead;
 use std::io::{self, Write};
+#[cfg(feature = "server")]
+use std::{
+    borrow::BorrowMut,
+    fs::File,
+    io::{BufWriter, Read},
+};
 
 use headers::{HeaderMap, HeaderMapExt};
-#[cfg(any(feature = "client", test))]
-use http::StatusCode;
-use http::{response::Parts, Version};
-
-use crate::{body::Chunk, upgrade::UpgradeExtension, HttpBody};
-#[cfg(any(feature = "client", test))]
-use crate::{
-    request::{ChunkedReader, ParseError},
-    Body,
+use http::{
+    header::{self, HeaderName},
+    response::Parts,
+    HeaderValue, StatusCode, Version,
 };
 
-#[derive(PartialEq, Eq)]
-pub(crate) enum Encoding {
+#[cfg(any(feature = "client", feature = "server", test))]
+use crate::request::{ChunkedReader, ParseError};
+#[cfg(feature = "server")]
+use crate::Connection;
+use crate::{body::Chunk, upgrade::UpgradeExtension, Body, HttpBody};
+
+/// How the body of a message is delimited on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Sent with a `Content-Length` of the given size.
     FixedLength(u64),
+    /// Sent with chunked `Transfer-Encoding`.
     Chunked,
+    /// Sent until the connection is closed.
     CloseDelimited,
 }
 
+/// How a response is written, see [`server::framing`](crate::server::framing).
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Framing {
+    /// How the body is delimited.
+    pub encoding: Encoding,
+    /// Whether the connection is kept open for further requests after the response.
+    pub keep_alive: bool,
+}
+
+/// Response extension that flushes the status line and headers to the client as soon as they are
+/// written, before the body starts being produced.
+///
+/// Useful for streaming responses where the time to the first byte matters, as the head would
+/// otherwise wait in the write buffer until the first body chunk is ready.
+///
+/// # Example
+/// ```no_run
+/// # use touche::{server::FlushHead, Body, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(|_req| {
+///     let (sender, body) = Body::channel();
+///
+///     std::thread::spawn(move || {
+///         // Some expensive computation...
+///         sender.send("done")
+///     });
+///
+///     Response::builder()
+///         .status(StatusCode::OK)
+///         .extension(FlushHead)
+///         .body(body)
+/// })
+/// # }
+/// ```
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlushHead;
+
+/// Response extension that decides whether the connection is kept alive after the response,
+/// instead of leaving it to the `Connection` header.
+///
+/// [`ConnectionControl::Close`] adds the `Connection: close` header, while
+/// [`ConnectionControl::KeepAlive`] drops the `close` option from it. The connection is still
+/// closed when the client asked for it, when the body can only be delimited by closing it, or when
+/// the server must close it (e.g. after the last request allowed on the connection).
+///
+/// # Example
+/// ```no_run
+/// # use touche::{server::ConnectionControl, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(|_req| {
+///     Response::builder()
+///         .status(StatusCode::BAD_REQUEST)
+///         .extension(ConnectionControl::Close)
+///         .body("The connection is in an unknown state")
+/// })
+/// # }
+/// ```
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionControl {
+    /// Closes the connection after the response.
+    Close,
+    /// Keeps the connection alive after the response, when possible.
+    KeepAlive,
+}
+
+#[cfg(feature = "server")]
+impl ConnectionControl {
+    /// Rewrites the `Connection` header of a response according to this option.
+    pub(crate) fn apply(self, headers: &mut HeaderMap) {
+        let options = headers
+            .get_all(header::CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|option| !option.is_empty() && !option.eq_ignore_ascii_case("close"))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        let options = match self {
+            ConnectionControl::Close => options
+                .into_iter()
+                .chain(Some("close".to_string()))
+                .collect(),
+            ConnectionControl::KeepAlive => options,
+        };
+
+        headers.remove(header::CONNECTION);
+        if !options.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(&options.join(", ")) {
+                headers.insert(header::CONNECTION, value);
+            }
+        }
+    }
+}
+
+/// Options on how responses are written.
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WriteConfig {
+    pub(crate) sort_headers: bool,
+    pub(crate) force_content_length: bool,
+    pub(crate) coalesce_chunks: Option<usize>,
+}
+
+/// Streams that may send files without copying them through userspace.
+#[cfg(feature = "server")]
+pub(crate) trait SendFile: Write {
+    fn send_file(&mut self, file: &mut io::Take<File>) -> io::Result<u64> {
+        io::copy(file, self)
+    }
+}
+
+#[cfg(feature = "server")]
+impl<C: Write + BorrowMut<Connection>> SendFile for BufWriter<C> {
+    fn send_file(&mut self, file: &mut io::Take<File>) -> io::Result<u64> {
+        self.flush()?;
+        self.get_mut().borrow_mut().send_file(file)
+    }
+}
+
+#[cfg(test)]
+impl SendFile for io::Cursor<Vec<u8>> {}
+
+/// Amount of bytes written for a response, see [`server::write_response`](crate::server::write_response).
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BytesWritten {
+    /// The status line and headers.
+    pub head: u64,
+    /// The body data, without any framing.
+    pub body: u64,
+    /// Everything written after the head: the body data plus chunk framing and trailers.
+    pub body_framed: u64,
+}
+
+/// Adapts any writer to [`SendFile`], copying files through userspace.
+#[cfg(feature = "server")]
+pub(crate) struct Plain<'a, W>(pub(crate) &'a mut W);
+
+#[cfg(feature = "server")]
+impl<W: Write> Write for Plain<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(feature = "server")]
+impl<W: Write> SendFile for Plain<'_, W> {}
+
+/// Counts the bytes written through it.
+#[cfg(feature = "server")]
+struct Counter<'a, W> {
+    inner: &'a mut W,
+    written: u64,
+}
+
+#[cfg(feature = "server")]
+impl<W: Write> Write for Counter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "server")]
+impl<W: SendFile> SendFile for Counter<'_, W> {
+    fn send_file(&mut self, file: &mut io::Take<File>) -> io::Result<u64> {
+        let written = self.inner.send_file(file)?;
+        self.written += written;
+        Ok(written)
+    }
+}
+
 pub(crate) enum Outcome {
     Close,
     KeepAlive,
     Upgrade(UpgradeExtension),
 }
 
-#[cfg(any(feature = "client", test))]
+#[cfg(any(feature = "client", feature = "server", test))]
 pub(crate) fn parse_response(
     mut stream: impl BufRead + Send + 'static,
 ) -> Result<http::Response<Body>, ParseError> {
@@ -79,7 +278,7 @@ pub(crate) fn parse_response(
             // https://datatracker.ietf.org/doc/html/rfc2616#section-3.6
             return Err(ParseError::InvalidTransferEncoding);
         }
-        Body::from_iter(ChunkedReader(Box::new(stream)))
+        Body::from_results(ChunkedReader::new(stream))
     } else if let Some(len) = headers.typed_try_get::<headers::ContentLength>()? {
         // Let's automatically buffer small bodies
         if len.0 < 1024 {
@@ -105,9 +304,27 @@ pub(crate) fn parse_response(
 #[cfg(feature = "server")]
 pub(crate) fn write_response<B: HttpBody>(
     res: http::Response<B>,
-    stream: &mut impl Write,
+    stream: &mut impl SendFile,
     write_body: bool,
-) -> io::Result<Outcome> {
+    config: &WriteConfig,
+) -> io::Result<(Outcome, BytesWritten)> {
+    if config.force_content_length
+        && res.body().len().is_none()
+        && !is_bodyless(res.status())
+        && !res.headers().contains_key(header::CONTENT_LENGTH)
+        && res.extensions().get::<UpgradeExtension>().is_none()
+    {
+        let (mut parts, body) = res.into_parts();
+        parts.headers.remove(header::TRANSFER_ENCODING);
+        let body = body.into_bytes()?;
+        return write_response(
+            http::Response::from_parts(parts, body),
+            stream,
+            write_body,
+            config,
+        );
+    }
+
     let (
         Parts {
             status,
@@ -119,6 +336,142 @@ pub(crate) fn write_response<B: HttpBody>(
         body,
     ) = res.into_parts();
 
+    let stream = &mut Counter {
+        inner: stream,
+        written: 0,
+    };
+
+    // Upgraded connections (including CONNECT tunnels) take over the stream right after the blank
+    // line that ends the head, so no body framing must be sent.
+    // https://datatracker.ietf.org/doc/html/rfc7231#section-4.3.6
+    if let Some(upgrade) = extensions.remove::<UpgradeExtension>() {
+        headers.remove(http::header::CONTENT_LENGTH);
+        headers.remove(http::header::TRANSFER_ENCODING);
+        write_head(stream, version, status, &headers, config)?;
+        let written = BytesWritten {
+            head: stream.written,
+            ..Default::default()
+        };
+        return Ok((Outcome::Upgrade(upgrade), written));
+    }
+
+    if let Some(control) = extensions.remove::<ConnectionControl>() {
+        control.apply(&mut headers);
+    }
+
+    let Framing {
+        encoding,
+        keep_alive,
+    } = match extensions.remove::<ExplicitEncoding>() {
+        Some(ExplicitEncoding(encoding)) => {
+            apply_encoding(encoding, version, &mut headers, body.len())?
+        }
+        None => decide_encoding(status, version, &mut headers, body.len())?,
+    };
+    let has_no_body = is_bodyless(status);
+
+    write_head(stream, version, status, &headers, config)?;
+    let head = stream.written;
+    let mut body_len = 0;
+
+    if extensions.remove::<FlushHead>().is_some() {
+        stream.flush()?;
+    }
+
+    if write_body && !has_no_body {
+        match encoding {
+            // Just buffer small bodies
+            Encoding::FixedLength(len) if len < 1024 => {
+                let mut bytes = body.into_bytes()?;
+                bytes.truncate(len as usize);
+                stream.write_all(&bytes)?;
+                body_len = bytes.len() as u64;
+            }
+            Encoding::FixedLength(_) | Encoding::CloseDelimited => {
+                match body.into_file(crate::body::sealed::Token) {
+                    Ok(mut file) => {
+                        body_len = stream.send_file(&mut file)?;
+                    }
+                    Err(body) => {
+                        // Bodies of unknown length never go past an explicit fixed length
+                        let limit = match encoding {
+                            Encoding::FixedLength(len) => len,
+                            _ => u64::MAX,
+                        };
+                        body_len = io::copy(&mut body.into_reader().take(limit), stream)?;
+                    }
+                }
+            }
+            Encoding::Chunked => {
+                let mut trailers = HeaderMap::new();
+                let threshold = config.coalesce_chunks.unwrap_or(0);
+                let mut pending = Vec::new();
+
+                for chunk in body.into_chunks() {
+                    match chunk? {
+                        Chunk::Data(chunk) if chunk.is_empty() => {}
+                        Chunk::Data(chunk) if pending.is_empty() && chunk.len() >= threshold => {
+                            write_chunk(stream, &chunk)?;
+                            body_len += chunk.len() as u64;
+                        }
+                        Chunk::Data(chunk) => {
+                            pending.extend_from_slice(&chunk);
+                            if pending.len() >= threshold {
+                                write_chunk(stream, &pending)?;
+                                body_len += pending.len() as u64;
+                                pending.clear();
+                            }
+                        }
+                        Chunk::Trailers(te) => {
+                            trailers.extend(te);
+                        }
+                    }
+                }
+
+                if !pending.is_empty() {
+                    write_chunk(stream, &pending)?;
+                    body_len += pending.len() as u64;
+                }
+
+                stream.write_all(b"0\r\n")?;
+                write_headers(stream, &trailers, config)?;
+                stream.write_all(b"\r\n")?;
+            }
+        };
+    }
+
+    let outcome = if keep_alive {
+        Outcome::KeepAlive
+    } else {
+        Outcome::Close
+    };
+
+    let written = BytesWritten {
+        head,
+        body: body_len,
+        body_framed: stream.written - head,
+    };
+
+    Ok((outcome, written))
+}
+
+/// Decides how a response with the given head and body length is framed, adding the headers
+/// required by the framing (such as `Content-Length` or `Transfer-Encoding`) and removing the ones
+/// it forbids.
+#[cfg(feature = "server")]
+pub(crate) fn decide_encoding(
+    status: StatusCode,
+    version: Version,
+    headers: &mut HeaderMap,
+    body_len: Option<u64>,
+) -> io::Result<Framing> {
+    let has_no_body = is_bodyless(status);
+
+    if has_no_body {
+        headers.remove(http::header::CONTENT_LENGTH);
+        headers.remove(http::header::TRANSFER_ENCODING);
+    }
+
     let has_chunked_encoding = headers
         .typed_get::<headers::TransferEncoding>()
         .filter(|te| te.is_chunked())
@@ -131,16 +484,15 @@ pub(crate) fn write_response<B: HttpBody>(
 
     let content_length = headers.typed_get::<headers::ContentLength>();
 
-    let encoding = if has_chunked_encoding && version == Version::HTTP_11 {
+    let encoding = if has_no_body {
+        Encoding::FixedLength(0)
+    } else if has_chunked_encoding && version == Version::HTTP_11 {
         Encoding::Chunked
-    } else if content_length.is_some() || body.len().is_some() {
-        match (content_length, body.len()) {
+    } else if content_length.is_some() || body_len.is_some() {
+        match (content_length, body_len) {
             (Some(len), Some(body_len)) => {
                 if len.0 != body_len {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "content-length doesn't match body length",
-                    ));
+                    return Err(io::Error::other("content-length doesn't match body length"));
                 }
                 Encoding::FixedLength(len.0)
             }
@@ -151,7 +503,7 @@ pub(crate) fn write_response<B: HttpBody>(
             }
             (None, None) => unreachable!(),
         }
-    } else if body.len().is_none() && !has_connection_close && version == Version::HTTP_11 {
+    } else if body_len.is_none() && !has_connection_close && version == Version::HTTP_11 {
         headers.typed_insert::<headers::TransferEncoding>(headers::TransferEncoding::chunked());
         Encoding::Chunked
     } else {
@@ -165,64 +517,297 @@ pub(crate) fn write_response<B: HttpBody>(
         headers.remove(http::header::TRANSFER_ENCODING);
     };
 
-    stream.write_all(format!("{version:?} {status}\r\n").as_bytes())?;
+    // HTTP/1.0 connections are only persistent when explicitly told so
+    if version == Version::HTTP_10
+        && matches!(encoding, Encoding::FixedLength(_))
+        && !headers.contains_key(header::CONNECTION)
+    {
+        headers.insert(header::CONNECTION, HeaderValue::from_static("keep-alive"));
+    }
+
+    let keep_alive = encoding != Encoding::CloseDelimited
+        && headers
+            .typed_get::<headers::Connection>()
+            .filter(|conn| conn.contains("close"))
+            .is_none();
 
-    for (name, val) in headers.iter() {
-        stream.write_all(&[format!("{name}: ").as_bytes(), val.as_bytes(), b"\r\n"].concat())?;
+    Ok(Framing {
+        encoding,
+        keep_alive,
+    })
+}
+
+/// Response extension set by [`server::write_response_with`](crate::server::write_response_with)
+/// to skip the framing inference.
+#[cfg(feature = "server")]
+pub(crate) struct ExplicitEncoding(pub(crate) Encoding);
+
+/// Frames a response with the given `encoding`, replacing the headers that describe the framing.
+///
+/// Only checks what would make the response impossible to write: chunked encoding on HTTP/1.0,
+/// and fixed lengths that don't match the length of the body.
+#[cfg(feature = "server")]
+fn apply_encoding(
+    encoding: Encoding,
+    version: Version,
+    headers: &mut HeaderMap,
+    body_len: Option<u64>,
+) -> io::Result<Framing> {
+    headers.remove(header::CONTENT_LENGTH);
+    headers.remove(header::TRANSFER_ENCODING);
+
+    match encoding {
+        Encoding::FixedLength(len) => {
+            if body_len.is_some_and(|body_len| body_len != len) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "fixed length doesn't match body length",
+                ));
+            }
+            headers.typed_insert(headers::ContentLength(len));
+        }
+        Encoding::Chunked if version < Version::HTTP_11 => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "chunked encoding requires HTTP/1.1",
+            ));
+        }
+        Encoding::Chunked => headers.typed_insert(headers::TransferEncoding::chunked()),
+        Encoding::CloseDelimited => {
+            ConnectionControl::Close.apply(headers);
+        }
     }
 
+    let keep_alive = encoding != Encoding::CloseDelimited
+        && headers
+            .typed_get::<headers::Connection>()
+            .filter(|conn| conn.contains("close"))
+            .is_none();
+
+    Ok(Framing {
+        encoding,
+        keep_alive,
+    })
+}
+
+/// Informational, 204 and 304 responses never have a body, so no framing is sent either.
+/// https://datatracker.ietf.org/doc/html/rfc9110#section-6.4.1
+#[cfg(feature = "server")]
+fn is_bodyless(status: StatusCode) -> bool {
+    status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+}
+
+#[cfg(feature = "server")]
+fn write_head(
+    stream: &mut impl Write,
+    version: Version,
+    status: StatusCode,
+    headers: &HeaderMap,
+    config: &WriteConfig,
+) -> io::Result<()> {
+    // Statuses without a known reason phrase get an empty one, the space before it is still
+    // required by the status line grammar
+    let reason = reason_phrase(status).unwrap_or_default();
+    stream.write_all(format!("{version:?} {} {reason}\r\n", status.as_str()).as_bytes())?;
+    write_headers(stream, headers, config)?;
+    stream.write_all(b"\r\n")
+}
+
+/// The reason phrase of a status, including the ones registered after the `http` crate was
+/// released.
+#[cfg(feature = "server")]
+fn reason_phrase(status: StatusCode) -> Option<&'static str> {
+    match status.as_u16() {
+        103 => Some("Early Hints"),
+        _ => status.canonical_reason(),
+    }
+}
+
+/// Writes `data` as a single chunk, flushing it to the client.
+#[cfg(feature = "server")]
+fn write_chunk(stream: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    stream.write_all(format!("{:x}\r\n", data.len()).as_bytes())?;
+    stream.write_all(data)?;
     stream.write_all(b"\r\n")?;
+    stream.flush()
+}
 
-    if write_body {
-        match encoding {
-            // Just buffer small bodies
-            Encoding::FixedLength(len) if len < 1024 => {
-                stream.write_all(&body.into_bytes()?)?;
-            }
-            Encoding::FixedLength(_) | Encoding::CloseDelimited => {
-                io::copy(&mut body.into_reader(), stream)?;
-            }
-            Encoding::Chunked => {
-                let mut trailers = HeaderMap::new();
+/// Writes the headers grouped by name. When `sort_headers` is enabled, names are written in
+/// ascending byte order of their lowercase form, otherwise in the [`HeaderMap`] iteration order.
+/// Multiple values of the same header are always written in the order they were inserted.
+#[cfg(feature = "server")]
+fn write_headers(
+    stream: &mut impl Write,
+    headers: &HeaderMap,
+    config: &WriteConfig,
+) -> io::Result<()> {
+    let mut names = headers.keys().collect::<Vec<_>>();
 
-                for chunk in body.into_chunks() {
-                    match chunk? {
-                        Chunk::Data(chunk) => {
-                            stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())?;
-                            stream.write_all(&chunk)?;
-                            stream.write_all(b"\r\n")?;
-                            stream.flush()?;
-                        }
-                        Chunk::Trailers(te) => {
-                            trailers.extend(te);
-                        }
-                    }
-                }
+    if config.sort_headers {
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    }
 
-                stream.write_all(b"0\r\n")?;
-                for (name, val) in trailers.iter() {
-                    stream.write_all(
-                        &[format!("{name}: ").as_bytes(), val.as_bytes(), b"\r\n"].concat(),
-                    )?;
-                }
-                stream.write_all(b"\r\n")?;
-            }
-        };
+    for name in names {
+        for val in headers.get_all(name) {
+            stream
+                .write_all(&[format!("{name}: ").as_bytes(), val.as_bytes(), b"\r\n"].concat())?;
+        }
     }
 
-    let connection = headers.typed_get::<headers::Connection>();
+    Ok(())
+}
+
+/// Extension methods for [`http::response::Builder`].
+pub trait ResponseExt {
+    /// Sets the `Content-Type` header to `default`, unless the response already has one.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, ResponseExt, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .ensure_content_type("application/octet-stream")
+    ///         .nosniff()
+    ///         .body(vec![0xde, 0xad, 0xbe, 0xef])
+    /// })
+    /// # }
+    /// ```
+    fn ensure_content_type<V>(self, default: V) -> Self
+    where
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>;
+
+    /// Sets the `X-Content-Type-Options: nosniff` header, which prevents browsers from guessing
+    /// the content type of the response.
+    fn nosniff(self) -> Self;
+
+    /// Builds a redirect to `location`, with the given redirect `status` and an empty body.
+    ///
+    /// Use `307 Temporary Redirect` or `308 Permanent Redirect` to keep the method and body of the
+    /// request, and `302 Found`, `301 Moved Permanently` or `303 See Other` to have clients follow
+    /// up with a `GET`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` is not one of the redirect statuses above.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, ResponseExt, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     Response::builder().redirect(StatusCode::PERMANENT_REDIRECT, "https://example.com")
+    /// })
+    /// # }
+    /// ```
+    fn redirect<V>(self, status: StatusCode, location: V) -> http::Result<http::Response<Body>>
+    where
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>;
+
+    /// Serializes `value` as the JSON body of the response, setting the `Content-Type` and
+    /// `Content-Length` headers.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use serde::Serialize;
+    /// # use touche::{Response, ResponseExt, Server, StatusCode};
+    /// #[derive(Serialize)]
+    /// struct User {
+    ///     name: &'static str,
+    /// }
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::bind("0.0.0.0:4444").serve(|_req| {
+    ///     Response::builder()
+    ///         .status(StatusCode::CREATED)
+    ///         .json(&User { name: "touche" })
+    /// })
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    fn json<T: serde::Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<http::Response<Body>, JsonError>;
+}
+
+/// Error building a JSON response with [`ResponseExt::json`].
+#[cfg(feature = "json")]
+#[derive(Debug, thiserror::Error)]
+pub enum JsonError {
+    #[error("failed to serialize json: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("invalid response: {0}")]
+    Http(#[from] http::Error),
+}
 
-    let outcome = if let Some(upgrade) = extensions.remove::<UpgradeExtension>() {
-        Outcome::Upgrade(upgrade)
-    } else if encoding == Encoding::CloseDelimited
-        || connection.filter(|conn| conn.contains("close")).is_some()
+impl ResponseExt for http::response::Builder {
+    fn ensure_content_type<V>(self, default: V) -> Self
+    where
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
     {
-        Outcome::Close
-    } else {
-        Outcome::KeepAlive
-    };
+        if has_header(&self, header::CONTENT_TYPE) {
+            self
+        } else {
+            self.header(header::CONTENT_TYPE, default)
+        }
+    }
+
+    fn nosniff(self) -> Self {
+        if has_header(&self, header::X_CONTENT_TYPE_OPTIONS) {
+            self
+        } else {
+            self.header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        }
+    }
+
+    fn redirect<V>(self, status: StatusCode, location: V) -> http::Result<http::Response<Body>>
+    where
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        assert!(
+            matches!(
+                status,
+                StatusCode::MOVED_PERMANENTLY
+                    | StatusCode::FOUND
+                    | StatusCode::SEE_OTHER
+                    | StatusCode::TEMPORARY_REDIRECT
+                    | StatusCode::PERMANENT_REDIRECT
+            ),
+            "{status} is not a redirect status"
+        );
+
+        self.status(status)
+            .header(header::LOCATION, location)
+            .body(Body::empty())
+    }
 
-    Ok(outcome)
+    #[cfg(feature = "json")]
+    fn json<T: serde::Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<http::Response<Body>, JsonError> {
+        let body = serde_json::to_vec(value)?;
+        let res = self
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))?;
+        Ok(res)
+    }
+}
+
+fn has_header(builder: &http::response::Builder, name: HeaderName) -> bool {
+    builder
+        .headers_ref()
+        .filter(|headers| headers.contains_key(name))
+        .is_some()
 }
 
 #[cfg(test)]
@@ -234,6 +819,46 @@ mod tests {
     use super::*;
     use http::{Response, StatusCode};
 
+    #[test]
+    fn builds_redirects() {
+        let res = Response::builder()
+            .redirect(StatusCode::SEE_OTHER, "/login")
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(res.headers()["location"], "/login");
+        assert_eq!(res.into_body().len(), Some(0));
+
+        assert!(Response::builder()
+            .redirect(StatusCode::FOUND, "/lol\nwut")
+            .is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a redirect status")]
+    fn rejects_non_redirect_statuses() {
+        Response::builder()
+            .redirect(StatusCode::NOT_MODIFIED, "/")
+            .ok();
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn builds_json_responses() {
+        let res = Response::builder()
+            .status(StatusCode::CREATED)
+            .json(&serde_json::json!({ "name": "touche" }))
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers()["content-type"], "application/json");
+        assert_eq!(res.headers()["content-length"], "17");
+        assert_eq!(
+            res.into_body().into_bytes().unwrap(),
+            br#"{"name":"touche"}"#
+        );
+    }
+
     #[test]
     fn writes_responses_without_bodies() {
         let res = Response::builder()
@@ -242,7 +867,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -251,6 +876,87 @@ mod tests {
         assert!(matches!(outcome, Outcome::KeepAlive));
     }
 
+    #[test]
+    fn writes_no_body_framing_on_responses_that_cant_have_bodies() {
+        for status in [StatusCode::NO_CONTENT, StatusCode::NOT_MODIFIED] {
+            let res = Response::builder()
+                .status(status)
+                .header("content-length", "3")
+                .body("lol")
+                .unwrap();
+
+            let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+            let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
+
+            assert_eq!(
+                output.get_ref(),
+                format!("HTTP/1.1 {status}\r\n\r\n").as_bytes()
+            );
+            assert!(matches!(outcome, Outcome::KeepAlive));
+        }
+
+        let res = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        write_response(res, &mut output, true, &Default::default()).unwrap();
+
+        assert_eq!(output.get_ref(), b"HTTP/1.1 204 No Content\r\n\r\n");
+    }
+
+    #[test]
+    fn writes_statuses_without_reason_phrases() {
+        let res = Response::builder().status(599).body(Body::empty()).unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        write_response(res, &mut output, true, &Default::default()).unwrap();
+
+        assert_eq!(
+            output.get_ref(),
+            b"HTTP/1.1 599 \r\ncontent-length: 0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn honors_the_connection_control_extension() {
+        let res = Response::builder()
+            .header("connection", "x-lol")
+            .extension(ConnectionControl::Close)
+            .body("lol")
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
+
+        assert!(matches!(outcome, Outcome::Close));
+        assert!(String::from_utf8_lossy(output.get_ref()).contains("connection: x-lol, close\r\n"));
+
+        let res = Response::builder()
+            .header("connection", "close")
+            .extension(ConnectionControl::KeepAlive)
+            .body("lol")
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
+
+        assert!(matches!(outcome, Outcome::KeepAlive));
+        assert!(!String::from_utf8_lossy(output.get_ref()).contains("connection"));
+
+        // Close delimited bodies can't keep the connection alive
+        let res = Response::builder()
+            .version(Version::HTTP_10)
+            .extension(ConnectionControl::KeepAlive)
+            .body(Body::from_iter(["lol"]))
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
+        assert!(matches!(outcome, Outcome::Close));
+    }
+
     #[test]
     fn writes_responses_with_bodies() {
         let res = Response::builder()
@@ -259,7 +965,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -276,7 +982,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, false).unwrap();
+        let (outcome, _) = write_response(res, &mut output, false, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -294,7 +1000,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        assert!(write_response(res, &mut output, true).is_err());
+        assert!(write_response(res, &mut output, true, &Default::default()).is_err());
     }
 
     #[test]
@@ -309,7 +1015,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -335,7 +1041,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         send_thread.join().unwrap();
 
@@ -346,6 +1052,161 @@ mod tests {
         assert!(matches!(outcome, Outcome::KeepAlive));
     }
 
+    #[test]
+    fn flushes_the_head_before_producing_the_body() {
+        #[derive(Default)]
+        struct Recorder {
+            written: Vec<u8>,
+            flushed: Vec<Vec<u8>>,
+        }
+
+        impl Write for Recorder {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.written.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushed.push(self.written.clone());
+                Ok(())
+            }
+        }
+
+        impl SendFile for Recorder {}
+
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .extension(FlushHead)
+            .body(Body::from_iter(Some(b"lol".to_vec())))
+            .unwrap();
+
+        let mut output = Recorder::default();
+        write_response(res, &mut output, true, &Default::default()).unwrap();
+
+        assert_eq!(
+            output.flushed[0],
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n"
+        );
+
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .body("lol")
+            .unwrap();
+
+        let mut output = Recorder::default();
+        write_response(res, &mut output, true, &Default::default()).unwrap();
+        assert!(output.flushed.is_empty());
+    }
+
+    #[test]
+    fn buffers_bodies_of_unknown_length_when_forcing_content_length() {
+        let config = WriteConfig {
+            force_content_length: true,
+            ..Default::default()
+        };
+
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .header("transfer-encoding", "chunked")
+            .body(Body::from_iter(vec![b"lol".to_vec(), b"wut".to_vec()]))
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (outcome, _) = write_response(res, &mut output, true, &config).unwrap();
+
+        assert_eq!(
+            output.get_ref(),
+            b"HTTP/1.1 200 OK\r\ncontent-length: 6\r\n\r\nlolwut"
+        );
+        assert!(matches!(outcome, Outcome::KeepAlive));
+
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .version(Version::HTTP_10)
+            .body(Body::from_reader(Cursor::new(b"lolwut"), None))
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (outcome, _) = write_response(res, &mut output, false, &config).unwrap();
+
+        assert_eq!(
+            output.get_ref(),
+            b"HTTP/1.0 200 OK\r\ncontent-length: 6\r\nconnection: keep-alive\r\n\r\n"
+        );
+        assert!(matches!(outcome, Outcome::KeepAlive));
+    }
+
+    #[test]
+    fn counts_the_bytes_written() {
+        let head = b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\n\r\n".len() as u64;
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .body("lol")
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (_, written) = write_response(res, &mut output, true, &Default::default()).unwrap();
+        assert_eq!(
+            written,
+            BytesWritten {
+                head,
+                body: 3,
+                body_framed: 3
+            }
+        );
+
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::from_reader(Cursor::new(vec![b'a'; 2000]), 2000))
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (_, written) = write_response(res, &mut output, true, &Default::default()).unwrap();
+        assert_eq!(written.body, 2000);
+        assert_eq!(written.body_framed, 2000);
+        assert_eq!(
+            written.head + written.body_framed,
+            output.get_ref().len() as u64
+        );
+
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .version(Version::HTTP_10)
+            .body(Body::from_reader(Cursor::new(b"lolwut"), None))
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (_, written) = write_response(res, &mut output, true, &Default::default()).unwrap();
+        assert_eq!(written.body, 6);
+        assert_eq!(written.body_framed, 6);
+
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::from_iter(vec![b"lol".to_vec(), b"wut".to_vec()]))
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (_, written) = write_response(res, &mut output, true, &Default::default()).unwrap();
+        let head = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n".len() as u64;
+        assert_eq!(
+            written,
+            BytesWritten {
+                head,
+                body: 6,
+                body_framed: b"3\r\nlol\r\n3\r\nwut\r\n0\r\n\r\n".len() as u64,
+            }
+        );
+
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .body("lol")
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (_, written) = write_response(res, &mut output, false, &Default::default()).unwrap();
+        assert_eq!(written.body, 0);
+        assert_eq!(written.body_framed, 0);
+    }
+
     #[test]
     fn writes_responses_from_reader_with_known_size() {
         let res = Response::builder()
@@ -354,7 +1215,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -371,7 +1232,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -388,7 +1249,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -406,7 +1267,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -431,7 +1292,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         send_thread.join().unwrap();
 
@@ -451,7 +1312,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert!(matches!(outcome, Outcome::Close));
     }
@@ -464,7 +1325,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert!(matches!(outcome, Outcome::KeepAlive));
     }
@@ -478,11 +1339,27 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert!(matches!(outcome, Outcome::Upgrade(_)));
     }
 
+    #[test]
+    fn writes_tunnel_responses_without_body_framing() {
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-length", "0")
+            .upgrade(|_| {})
+            .body(Body::empty())
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
+
+        assert_eq!(output.get_ref(), b"HTTP/1.1 200 OK\r\n\r\n");
+        assert!(matches!(outcome, Outcome::Upgrade(_)));
+    }
+
     #[test]
     fn writes_http_10_responses() {
         let res = Response::builder()
@@ -492,15 +1369,34 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
-            b"HTTP/1.0 200 OK\r\ncontent-length: 3\r\n\r\nlol"
+            b"HTTP/1.0 200 OK\r\ncontent-length: 3\r\nconnection: keep-alive\r\n\r\nlol"
         );
         assert!(matches!(outcome, Outcome::KeepAlive));
     }
 
+    #[test]
+    fn does_not_keep_alive_http_10_responses_with_close_connection() {
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .version(Version::HTTP_10)
+            .header("connection", "close")
+            .body("lol")
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
+
+        assert_eq!(
+            output.get_ref(),
+            b"HTTP/1.0 200 OK\r\nconnection: close\r\ncontent-length: 3\r\n\r\nlol"
+        );
+        assert!(matches!(outcome, Outcome::Close));
+    }
+
     #[test]
     fn removes_chunked_transfer_encoding_from_http_10_responses() {
         let res = Response::builder()
@@ -511,7 +1407,7 @@ mod tests {
             .unwrap();
 
         let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
-        let outcome = write_response(res, &mut output, true).unwrap();
+        let (outcome, _) = write_response(res, &mut output, true, &Default::default()).unwrap();
 
         assert_eq!(
             output.get_ref(),
@@ -520,6 +1416,98 @@ mod tests {
         assert!(matches!(outcome, Outcome::Close));
     }
 
+    #[test]
+    fn writes_sorted_headers() {
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .header("x-zzz", "1")
+            .header("set-cookie", "b=2")
+            .header("accept-ranges", "none")
+            .header("set-cookie", "a=1")
+            .body("lol")
+            .unwrap();
+
+        let config = WriteConfig {
+            sort_headers: true,
+            ..Default::default()
+        };
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        write_response(res, &mut output, true, &config).unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(output.get_ref()).unwrap(),
+            "HTTP/1.1 200 OK\r\naccept-ranges: none\r\ncontent-length: 3\r\nset-cookie: b=2\r\nset-cookie: a=1\r\nx-zzz: 1\r\n\r\nlol"
+        );
+    }
+
+    #[test]
+    fn coalesces_small_chunks() {
+        let chunks = ["l", "o", "l", "", "wut", "lolwut!", "l"];
+        let res = Response::builder().body(Body::from_iter(chunks)).unwrap();
+
+        let config = WriteConfig {
+            coalesce_chunks: Some(4),
+            ..Default::default()
+        };
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let (_, written) = write_response(res, &mut output, true, &config).unwrap();
+
+        let output = std::str::from_utf8(output.get_ref()).unwrap();
+        assert!(output.ends_with("\r\n\r\n6\r\nlolwut\r\n7\r\nlolwut!\r\n1\r\nl\r\n0\r\n\r\n"));
+        assert_eq!(written.body, 14);
+    }
+
+    #[test]
+    fn writes_each_set_cookie_on_its_own_line() {
+        let res = Response::builder()
+            .header("set-cookie", "a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT")
+            .header("set-cookie", "b=2; HttpOnly")
+            .body("lol")
+            .unwrap();
+
+        let mut output: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        write_response(res, &mut output, true, &Default::default()).unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(output.get_ref()).unwrap(),
+            "HTTP/1.1 200 OK\r\nset-cookie: a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT\r\nset-cookie: b=2; HttpOnly\r\ncontent-length: 3\r\n\r\nlol"
+        );
+    }
+
+    #[test]
+    fn ensures_content_type() {
+        let res = Response::builder()
+            .ensure_content_type("application/octet-stream")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            res.headers()["content-type"],
+            HeaderValue::from_static("application/octet-stream")
+        );
+
+        let res = Response::builder()
+            .header("content-type", "text/plain")
+            .ensure_content_type("application/octet-stream")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            res.headers()["content-type"],
+            HeaderValue::from_static("text/plain")
+        );
+    }
+
+    #[test]
+    fn sets_nosniff_only_once() {
+        let res = Response::builder().nosniff().nosniff().body(()).unwrap();
+        assert_eq!(
+            res.headers()
+                .get_all("x-content-type-options")
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![HeaderValue::from_static("nosniff")]
+        );
+    }
+
     #[test]
     fn parse_response_without_body() {
         let res = "HTTP/1.1 200 OK\r\ndate: Mon, 25 Jul 2022 21:34:35 GMT\r\n\r\n";
@@ -587,4 +1575,58 @@ mod tests {
 
         assert_eq!(res.into_body().into_bytes().unwrap(), b"lolwut");
     }
+
+    #[test]
+    fn decides_response_framing() {
+        let framing = |version, status, headers: &[(&str, &str)], body_len| {
+            let mut map = HeaderMap::new();
+            for (name, value) in headers {
+                map.append(
+                    HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                    value.parse().unwrap(),
+                );
+            }
+            let framing = decide_encoding(status, version, &mut map, body_len).unwrap();
+            (framing.encoding, framing.keep_alive, map)
+        };
+
+        let (encoding, keep_alive, headers) =
+            framing(Version::HTTP_11, StatusCode::OK, &[], Some(3));
+        assert_eq!((encoding, keep_alive), (Encoding::FixedLength(3), true));
+        assert_eq!(headers["content-length"], "3");
+
+        let (encoding, keep_alive, headers) = framing(Version::HTTP_11, StatusCode::OK, &[], None);
+        assert_eq!((encoding, keep_alive), (Encoding::Chunked, true));
+        assert_eq!(headers["transfer-encoding"], "chunked");
+
+        let (encoding, keep_alive, headers) = framing(Version::HTTP_10, StatusCode::OK, &[], None);
+        assert_eq!((encoding, keep_alive), (Encoding::CloseDelimited, false));
+        assert_eq!(headers["connection"], "close");
+
+        let (encoding, keep_alive, headers) =
+            framing(Version::HTTP_10, StatusCode::OK, &[], Some(3));
+        assert_eq!((encoding, keep_alive), (Encoding::FixedLength(3), true));
+        assert_eq!(headers["connection"], "keep-alive");
+
+        let (encoding, keep_alive, _) = framing(
+            Version::HTTP_11,
+            StatusCode::OK,
+            &[("connection", "close")],
+            Some(3),
+        );
+        assert_eq!((encoding, keep_alive), (Encoding::FixedLength(3), false));
+
+        let (encoding, keep_alive, headers) = framing(
+            Version::HTTP_11,
+            StatusCode::NO_CONTENT,
+            &[("content-length", "3")],
+            Some(3),
+        );
+        assert_eq!((encoding, keep_alive), (Encoding::FixedLength(0), true));
+        assert!(!headers.contains_key("content-length"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("content-length", "4".parse().unwrap());
+        assert!(decide_encoding(StatusCode::OK, Version::HTTP_11, &mut headers, Some(3)).is_err());
+    }
 }
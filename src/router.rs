@@ -0,0 +1,488 @@
+//! Minimal request routing based on the method and path.
+//!
+//! A [`Router`] maps methods and path patterns to [`Services`](Service), answering
+//! `404 Not Found` when no pattern matches the request path, and `405 Method Not Allowed` when
+//! the path matches but not the method.
+//!
+//! Patterns are made of `/` separated segments, which may be:
+//!
+//! - A literal, like `users`, matched exactly.
+//! - A named parameter, like `:id`, matching any non empty segment.
+//! - A named wildcard, like `*path`, as the last segment, matching the rest of the path.
+//!
+//! The matched parameters are available to the handler through the [`Params`] request extension.
+//!
+//! # Example
+//! ```no_run
+//! use touche::{router::Params, Body, Request, Response, Router, Server, StatusCode};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let router = Router::new()
+//!         .get("/", |_req: Request<Body>| {
+//!             Response::builder().status(StatusCode::OK).body("Home")
+//!         })
+//!         .get("/users/:id", |req: Request<Body>| {
+//!             let params = req.extensions().get::<Params>().unwrap();
+//!             let id = params.get("id").unwrap();
+//!             Response::builder()
+//!                 .status(StatusCode::OK)
+//!                 .body(format!("User {id}"))
+//!         });
+//!
+//!     Server::bind("0.0.0.0:4444").serve(router)
+//! }
+//! ```
+use std::{error::Error, fmt, sync::Arc};
+
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+
+use crate::{
+    body::{BoxBody, HttpBody},
+    server::Service,
+    Body,
+};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+type BoxService = Arc<dyn Service<Body = BoxBody, Error = BoxError> + Send + Sync>;
+
+/// Routes requests to services by their method and path. See the [module docs](self).
+#[derive(Clone, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+#[derive(Clone)]
+struct Route {
+    method: Method,
+    pattern: Vec<Segment>,
+    service: BoxService,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+/// The parameters matched on the request path, available as a request extension.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    /// Returns the value of the parameter `name`. Values are not percent-decoded.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterates over the parameters names and values, in the order they appear on the pattern.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl Router {
+    /// Creates a router without routes, which answers every request with `404 Not Found`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes requests with the given `method` and path matching `pattern` to `service`.
+    ///
+    /// Routes are tried in the order they are added. `HEAD` requests are routed to `GET` routes,
+    /// unless a `HEAD` route matches first. Unless routed, `OPTIONS` requests to known paths are
+    /// answered with `204 No Content` and an `Allow` header listing the methods of the path.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern doesn't start with `/`, or has a wildcard that is not the last
+    /// segment.
+    pub fn route<S>(mut self, method: Method, pattern: &str, service: S) -> Self
+    where
+        S: Service + Send + Sync + 'static,
+        S::Body: 'static,
+    {
+        self.routes.push(Route {
+            method,
+            pattern: parse_pattern(pattern),
+            service: Arc::new(Boxed(service)),
+        });
+        self
+    }
+
+    /// Routes `GET` (and `HEAD`) requests, see [`Router::route`].
+    pub fn get<S>(self, pattern: &str, service: S) -> Self
+    where
+        S: Service + Send + Sync + 'static,
+        S::Body: 'static,
+    {
+        self.route(Method::GET, pattern, service)
+    }
+
+    /// Routes `POST` requests, see [`Router::route`].
+    pub fn post<S>(self, pattern: &str, service: S) -> Self
+    where
+        S: Service + Send + Sync + 'static,
+        S::Body: 'static,
+    {
+        self.route(Method::POST, pattern, service)
+    }
+
+    /// Routes `PUT` requests, see [`Router::route`].
+    pub fn put<S>(self, pattern: &str, service: S) -> Self
+    where
+        S: Service + Send + Sync + 'static,
+        S::Body: 'static,
+    {
+        self.route(Method::PUT, pattern, service)
+    }
+
+    /// Routes `DELETE` requests, see [`Router::route`].
+    pub fn delete<S>(self, pattern: &str, service: S) -> Self
+    where
+        S: Service + Send + Sync + 'static,
+        S::Body: 'static,
+    {
+        self.route(Method::DELETE, pattern, service)
+    }
+
+    /// Finds the route for a request, along with its path parameters. When no route matches,
+    /// returns the methods allowed on the path, which are empty if the path matches no route.
+    fn find(&self, method: &Method, path: &str) -> Result<(&Route, Params), Vec<Method>> {
+        let mut allowed = Vec::new();
+        let mut get = None;
+
+        for route in &self.routes {
+            let Some(params) = match_path(&route.pattern, path) else {
+                continue;
+            };
+
+            if route.method == method {
+                return Ok((route, params));
+            } else if method == Method::HEAD && route.method == Method::GET && get.is_none() {
+                get = Some((route, params));
+            } else if !allowed.contains(&route.method) {
+                allowed.push(route.method.clone());
+            }
+        }
+
+        get.ok_or(allowed)
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                self.routes
+                    .iter()
+                    .map(|route| (&route.method, &route.pattern)),
+            )
+            .finish()
+    }
+}
+
+impl Service for Router {
+    type Body = BoxBody;
+    type Error = BoxError;
+
+    fn call(&self, mut req: Request<Body>) -> Result<Response<Self::Body>, Self::Error> {
+        match self.find(req.method(), req.uri().path()) {
+            Ok((route, params)) => {
+                req.extensions_mut().insert(params);
+                route.service.call(req)
+            }
+            Err(allowed) if allowed.is_empty() => Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(().boxed())?),
+            Err(allowed) => {
+                let status = if req.method() == Method::OPTIONS {
+                    StatusCode::NO_CONTENT
+                } else {
+                    StatusCode::METHOD_NOT_ALLOWED
+                };
+                Ok(Response::builder()
+                    .status(status)
+                    .header(header::ALLOW, allow(allowed)?)
+                    .body(().boxed())?)
+            }
+        }
+    }
+
+    fn should_continue(&self, req: &Request<Body>) -> StatusCode {
+        match self.find(req.method(), req.uri().path()) {
+            Ok((route, _)) => route.service.should_continue(req),
+            Err(allowed) if allowed.is_empty() => StatusCode::NOT_FOUND,
+            Err(_) if req.method() == Method::OPTIONS => StatusCode::CONTINUE,
+            Err(_) => StatusCode::METHOD_NOT_ALLOWED,
+        }
+    }
+}
+
+/// Builds the `Allow` header for the methods routed on a path, which also accepts `HEAD` requests
+/// when routing `GET` ones, and `OPTIONS` requests.
+fn allow(mut methods: Vec<Method>) -> Result<HeaderValue, http::header::InvalidHeaderValue> {
+    if let Some(get) = methods.iter().position(|method| method == Method::GET) {
+        if !methods.contains(&Method::HEAD) {
+            methods.insert(get + 1, Method::HEAD);
+        }
+    }
+    if !methods.contains(&Method::OPTIONS) {
+        methods.push(Method::OPTIONS);
+    }
+
+    let methods = methods
+        .iter()
+        .map(|method| method.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    HeaderValue::try_from(methods)
+}
+
+/// Adapts any [`Service`] to the boxed types used by the [`Router`].
+struct Boxed<S>(S);
+
+impl<S> Service for Boxed<S>
+where
+    S: Service,
+    S::Body: 'static,
+{
+    type Body = BoxBody;
+    type Error = BoxError;
+
+    fn call(&self, req: Request<Body>) -> Result<Response<Self::Body>, Self::Error> {
+        match self.0.call(req) {
+            Ok(res) => Ok(res.map(HttpBody::boxed)),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn should_continue(&self, req: &Request<Body>) -> StatusCode {
+        self.0.should_continue(req)
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    assert!(pattern.starts_with('/'), "patterns must start with /");
+
+    let segments = pattern
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                Segment::Param(name.to_string())
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Literal(segment.to_string())
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let wildcard_in_middle = segments
+        .iter()
+        .rev()
+        .skip(1)
+        .any(|segment| matches!(segment, Segment::Wildcard(_)));
+    assert!(
+        !wildcard_in_middle,
+        "wildcards must be the last segment of patterns"
+    );
+
+    segments
+}
+
+fn match_path(pattern: &[Segment], path: &str) -> Option<Params> {
+    let mut params = Vec::new();
+    let mut rest = path.trim_start_matches('/');
+
+    for segment in pattern {
+        if let Segment::Wildcard(name) = segment {
+            params.push((name.clone(), rest.to_string()));
+            return Some(Params(params));
+        }
+
+        let (current, remaining) = rest.split_once('/').unwrap_or((rest, ""));
+        match segment {
+            Segment::Literal(literal) if literal == current => {}
+            Segment::Param(name) if !current.is_empty() => {
+                params.push((name.clone(), current.to_string()));
+            }
+            _ => return None,
+        }
+        rest = remaining;
+    }
+
+    // Allows a trailing slash
+    rest.is_empty().then_some(Params(params))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    fn named(name: &'static str) -> impl Service<Body = String, Error = Infallible> + Clone {
+        move |req: Request<Body>| {
+            let params = req.extensions().get::<Params>().unwrap();
+            let params = params
+                .iter()
+                .map(|(name, value)| format!(" {name}={value}"))
+                .collect::<String>();
+            Ok(Response::new(format!("{name}{params}")))
+        }
+    }
+
+    fn call(router: &Router, method: Method, path: &str) -> Response<BoxBody> {
+        let req = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        router.call(req).unwrap()
+    }
+
+    fn body(res: Response<BoxBody>) -> String {
+        String::from_utf8(res.into_body().into_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn routes_by_method_and_path() {
+        let router = Router::new()
+            .get("/", named("root"))
+            .get("/users", named("list"))
+            .post("/users", named("create"))
+            .get("/users/:id", named("show"))
+            .delete("/users/:id/posts/:post", named("delete post"));
+
+        assert_eq!(body(call(&router, Method::GET, "/")), "root");
+        assert_eq!(body(call(&router, Method::GET, "/users")), "list");
+        assert_eq!(body(call(&router, Method::GET, "/users/")), "list");
+        assert_eq!(body(call(&router, Method::POST, "/users")), "create");
+        assert_eq!(body(call(&router, Method::GET, "/users/42")), "show id=42");
+        assert_eq!(
+            body(call(&router, Method::GET, "/users/42?lol=wut")),
+            "show id=42"
+        );
+        assert_eq!(
+            body(call(&router, Method::DELETE, "/users/42/posts/lol")),
+            "delete post id=42 post=lol"
+        );
+    }
+
+    #[test]
+    fn routes_wildcards() {
+        let router = Router::new().get("/static/*path", named("static"));
+
+        assert_eq!(
+            body(call(&router, Method::GET, "/static/css/app.css")),
+            "static path=css/app.css"
+        );
+        assert_eq!(body(call(&router, Method::GET, "/static")), "static path=");
+        assert_eq!(
+            call(&router, Method::GET, "/other").status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn routes_head_requests_to_get_routes() {
+        let router = Router::new()
+            .get("/lol", named("get lol"))
+            .get("/wut", named("get wut"))
+            .route(Method::HEAD, "/wut", named("head wut"));
+
+        assert_eq!(body(call(&router, Method::HEAD, "/lol")), "get lol");
+        assert_eq!(body(call(&router, Method::HEAD, "/wut")), "head wut");
+    }
+
+    #[test]
+    fn answers_not_found_and_method_not_allowed() {
+        let router = Router::new()
+            .get("/users/:id", named("show"))
+            .put("/users/:id", named("update"))
+            .post("/users", named("create"));
+
+        let res = call(&router, Method::GET, "/posts");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(res.body().is_empty());
+
+        assert_eq!(
+            call(&router, Method::GET, "/users/42/lol").status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            call(&router, Method::GET, "/users//").status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let res = call(&router, Method::DELETE, "/users/42");
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()["allow"], "GET, HEAD, PUT, OPTIONS");
+
+        let req = Request::delete("/users/42").body(Body::empty()).unwrap();
+        assert_eq!(router.should_continue(&req), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[test]
+    fn answers_options_requests_with_the_allowed_methods() {
+        let router = Router::new()
+            .get("/users/:id", named("show"))
+            .delete("/users/:id", named("delete"))
+            .route(Method::OPTIONS, "/posts", named("posts options"))
+            .post("/posts", named("create"));
+
+        let res = call(&router, Method::OPTIONS, "/users/42");
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()["allow"], "GET, HEAD, DELETE, OPTIONS");
+        assert!(res.body().is_empty());
+
+        let req = Request::options("/users/42").body(Body::empty()).unwrap();
+        assert_eq!(router.should_continue(&req), StatusCode::CONTINUE);
+
+        assert_eq!(
+            body(call(&router, Method::OPTIONS, "/posts")),
+            "posts options"
+        );
+        assert_eq!(
+            call(&router, Method::OPTIONS, "/lol").status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcards must be the last segment of patterns")]
+    fn rejects_wildcards_in_the_middle_of_patterns() {
+        Router::new().get("/static/*path/lol", named("static"));
+    }
+
+    #[test]
+    fn serves_routed_requests() {
+        let router = Router::new().get("/hello/:name", |req: Request<Body>| {
+            let name = req
+                .extensions()
+                .get::<Params>()
+                .unwrap()
+                .get("name")
+                .unwrap();
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(format!("Hello {name}"))
+        });
+
+        let res = crate::test::oneshot(
+            router,
+            Request::get("/hello/touche").body(Body::empty()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.into_body().into_bytes().unwrap(), b"Hello touche");
+    }
+}
@@ -22,25 +22,43 @@
 //! ```
 use std::{
     error::Error,
-    io::{self, BufReader, BufWriter, Write},
-    net::{TcpListener, ToSocketAddrs},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::{
+        IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs,
+    },
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
     time::{Duration, SystemTime},
 };
 
 use headers::{HeaderMapExt, HeaderValue};
-use http::{Method, Request, Response, StatusCode, Version};
+use http::{header, HeaderMap, Method, Request, Response, StatusCode, Version};
+use socket2::{Domain, Protocol, Socket, Type};
 use threadpool::ThreadPool;
 
+#[cfg(feature = "compression")]
+use crate::compress::{self, CompressionConfig};
 use crate::{
-    body::HttpBody,
+    body::{self, HttpBody},
+    proxy,
     read_queue::ReadQueue,
-    request::{self, ParseError},
-    response::{self, Outcome},
-    Body, Connection,
+    request::{self, BodyDiscarder, BodyEnd, ParseConfig, ParseError},
+    response::{self, ExplicitEncoding, Outcome, Plain, WriteConfig},
+    upgrade::{self, UpgradeExtension},
+    Body, Connection, ProtocolError,
 };
 
+pub use crate::response::{BytesWritten, ConnectionControl, Encoding, FlushHead, Framing};
+
 type IncomingRequest = Request<Body>;
 
+/// How long to wait for body bytes after rejecting an `Expect: 100-continue` request before
+/// assuming the client won't send them.
+const UNSENT_BODY_GRACE: Duration = Duration::from_millis(10);
+
 /// Maps [`Requests`](http::Request) to [`Responses`](http::Response).
 ///
 /// Usually you don't need to manually implement this trait, as its `Fn` implementation might suffice
@@ -101,7 +119,9 @@ pub trait Service {
         StatusCode::CONTINUE
     }
 
-    fn wants_stop(&self) -> bool { false }
+    fn wants_stop(&self) -> bool {
+        false
+    }
 }
 
 impl<F, Body, Err> Service for F
@@ -118,15 +138,222 @@ where
     }
 }
 
+/// Wraps a [`Service`] so `HEAD` requests are answered with its response to the same request as a
+/// `GET`, see [`head_as_get`].
+#[derive(Debug, Clone)]
+pub struct HeadAsGet<S>(S);
+
+/// Wraps a [`Service`] so it only has to handle `GET` requests to also answer `HEAD` ones.
+///
+/// `HEAD` requests reach the service as `GET` requests, so it can't answer them differently. The
+/// server then writes the head of the response without its body, with the same headers and
+/// framing (such as the `Content-Length`) it would use for the `GET` request.
+///
+/// # Example
+/// ```no_run
+/// # use touche::{server, Body, Request, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(server::head_as_get(|req: Request<Body>| {
+///     assert_ne!(req.method(), "HEAD");
+///     Response::builder()
+///         .status(StatusCode::OK)
+///         .body("Hello world")
+/// }))
+/// # }
+/// ```
+pub fn head_as_get<S: Service>(service: S) -> HeadAsGet<S> {
+    HeadAsGet(service)
+}
+
+impl<S: Service> Service for HeadAsGet<S> {
+    type Body = S::Body;
+    type Error = S::Error;
+
+    fn call(&self, mut request: IncomingRequest) -> Result<Response<Self::Body>, Self::Error> {
+        if request.method() == Method::HEAD {
+            *request.method_mut() = Method::GET;
+        }
+        self.0.call(request)
+    }
+
+    fn should_continue(&self, request: &IncomingRequest) -> StatusCode {
+        self.0.should_continue(request)
+    }
+
+    fn wants_stop(&self) -> bool {
+        self.0.wants_stop()
+    }
+}
+
 /// A listening HTTP server that accepts HTTP 1 connections.
 pub struct Server<'a> {
     thread_pool: ThreadPool,
     incoming: Box<dyn Iterator<Item = Connection> + 'a>,
+    config: Arc<Config>,
+    shutdown: ShutdownHandle,
+}
+
+/// A handle to stop a [`Server`] from accepting connections, obtained with
+/// [`Server::shutdown_handle`].
+///
+/// Shutting down unblocks the threads waiting on new connections right away, so the serve methods
+/// return promptly. Connections already being served are not interrupted.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<ShutdownState>,
+}
+
+#[derive(Default)]
+struct ShutdownState {
+    requested: AtomicBool,
+    // Address the listener can be reached at, to wake up threads blocked accepting connections
+    wake_addr: Mutex<Option<SocketAddr>>,
+}
+
+impl ShutdownHandle {
+    /// Stops the server from accepting new connections.
+    pub fn shutdown(&self) {
+        self.inner.requested.store(true, Ordering::SeqCst);
+        self.wake();
+    }
+
+    /// Returns whether [`ShutdownHandle::shutdown`] was called.
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    fn listen_on(&self, addr: SocketAddr) {
+        let ip = match addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        *self.inner.wake_addr.lock().unwrap() = Some(SocketAddr::new(ip, addr.port()));
+    }
+
+    /// Connects to the listener, so one of the threads blocked on `accept` returns. That thread
+    /// wakes up the next one before leaving, until every accept loop is done.
+    fn wake(&self) {
+        if let Some(addr) = *self.inner.wake_addr.lock().unwrap() {
+            TcpStream::connect_timeout(&addr, Duration::from_secs(1)).ok();
+        }
+    }
+}
+
+impl std::fmt::Debug for ShutdownHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownHandle")
+            .field("is_shutdown", &self.is_shutdown())
+            .finish()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Config {
+    parse: ParseConfig,
+    write: WriteConfig,
+    on_protocol_error: Option<Box<ProtocolErrorHandler>>,
+    #[cfg(feature = "rustls")]
+    on_tls_error: Option<Box<TlsErrorHandler>>,
+    server_name: Option<HeaderValue>,
+    response_hooks: Vec<Box<ResponseHook>>,
+    trusted_proxies: Vec<IpAddr>,
+    #[cfg(feature = "compression")]
+    compression: Option<CompressionConfig>,
+    keep_alive_timeout: Option<Duration>,
+    max_requests_per_connection: Option<usize>,
+    stats: ServerStats,
+}
+
+/// Counters of the connections and requests handled by a [`Server`], obtained with
+/// [`Server::stats`].
+///
+/// The counters are live: every call to the accessors returns their current value.
+#[derive(Clone, Default)]
+pub struct ServerStats {
+    inner: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    connections: AtomicU64,
+    active_connections: AtomicU64,
+    requests: AtomicU64,
+    reused_connections: AtomicU64,
+}
+
+impl ServerStats {
+    /// Total number of connections accepted.
+    pub fn connections_accepted(&self) -> u64 {
+        self.inner.connections.load(Ordering::Relaxed)
+    }
+
+    /// Number of connections currently being served.
+    pub fn active_connections(&self) -> u64 {
+        self.inner.active_connections.load(Ordering::Relaxed)
+    }
+
+    /// Total number of requests served, counting the ones answered with protocol errors.
+    pub fn requests_served(&self) -> u64 {
+        self.inner.requests.load(Ordering::Relaxed)
+    }
+
+    /// Total number of requests served on a connection kept alive from a previous request.
+    pub fn keep_alive_reuses(&self) -> u64 {
+        self.inner.reused_connections.load(Ordering::Relaxed)
+    }
+
+    fn connection_opened(&self) -> ActiveConnection<'_> {
+        self.inner.connections.fetch_add(1, Ordering::Relaxed);
+        self.inner
+            .active_connections
+            .fetch_add(1, Ordering::Relaxed);
+        ActiveConnection(self)
+    }
+
+    fn request_served(&self, reused: bool) {
+        self.inner.requests.fetch_add(1, Ordering::Relaxed);
+        if reused {
+            self.inner
+                .reused_connections
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl std::fmt::Debug for ServerStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerStats")
+            .field("connections_accepted", &self.connections_accepted())
+            .field("active_connections", &self.active_connections())
+            .field("requests_served", &self.requests_served())
+            .field("keep_alive_reuses", &self.keep_alive_reuses())
+            .finish()
+    }
+}
+
+/// Counts a connection as active until dropped.
+struct ActiveConnection<'a>(&'a ServerStats);
+
+impl Drop for ActiveConnection<'_> {
+    fn drop(&mut self) {
+        self.0
+            .inner
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
+type ProtocolErrorHandler = dyn Fn(&ProtocolError) + Send + Sync;
+
+#[cfg(feature = "rustls")]
+type TlsErrorHandler = dyn Fn(&io::Error, Option<SocketAddr>) + Send + Sync;
+
+type ResponseHook = dyn Fn(&mut http::response::Parts) + Send + Sync;
+
 impl From<TcpListener> for Server<'static> {
     fn from(listener: TcpListener) -> Self {
-        Self::builder().from_connections(TcpAcceptor { listener })
+        Self::from_listener(listener)
     }
 }
 
@@ -146,6 +373,75 @@ impl<'a> Server<'a> {
         Self::builder().bind(addr)
     }
 
+    /// Binds the [`Server`] to the given `addr`, accepting TLS connections with the given rustls
+    /// `config`.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if binding to the address fails. For a non panic method to bind the
+    /// server, see [`ServerBuilder::try_bind_tls`].
+    #[cfg(feature = "rustls")]
+    pub fn bind_tls<A: ToSocketAddrs>(
+        addr: A,
+        config: Arc<rustls::ServerConfig>,
+    ) -> Server<'static> {
+        Self::builder().bind_tls(addr, config)
+    }
+
+    /// Creates a [`Server`] that accepts connections from an already bound `listener`, such as one
+    /// inherited through socket activation. See [`ServerBuilder::from_listener`].
+    pub fn from_listener(listener: TcpListener) -> Server<'static> {
+        Self::builder().from_listener(listener)
+    }
+
+    /// Returns a [`ShutdownHandle`] to stop this server from accepting connections, making the
+    /// serve methods return.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::{thread, time::Duration};
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// let server = Server::bind("0.0.0.0:4444");
+    /// let handle = server.shutdown_handle();
+    ///
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_secs(60));
+    ///     handle.shutdown();
+    /// });
+    ///
+    /// server.serve(|_req| {
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(())
+    /// })
+    /// # }
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Returns the [`ServerStats`] of this server, which keep being updated while it serves
+    /// connections.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// let server = Server::bind("0.0.0.0:4444");
+    /// let stats = server.stats();
+    ///
+    /// server.serve(move |_req| {
+    ///     Response::builder()
+    ///         .status(StatusCode::OK)
+    ///         .body(format!("Requests served: {}", stats.requests_served()))
+    /// })
+    /// # }
+    /// ```
+    pub fn stats(&self) -> ServerStats {
+        self.config.stats.clone()
+    }
+
     /// Serves an [`Service`] on a thread per connection model, backed by a thread pool.
     ///
     /// # Example
@@ -165,9 +461,14 @@ impl<'a> Server<'a> {
         S: Send + Clone + 'static,
     {
         for conn in self.incoming {
+            if self.shutdown.is_shutdown() {
+                break;
+            }
+
             let app = service.clone();
+            let config = self.config.clone();
             self.thread_pool.execute(move || {
-                serve(conn, app).ok();
+                serve(conn, app, &config).ok();
             });
         }
 
@@ -194,8 +495,12 @@ impl<'a> Server<'a> {
         S: Service + Clone,
     {
         for conn in self.incoming {
+            if self.shutdown.is_shutdown() {
+                break;
+            }
+
             let app = service.clone();
-            serve(conn, app).ok();
+            serve(conn, app, &self.config).ok();
 
             if service.wants_stop() {
                 break;
@@ -232,10 +537,15 @@ impl<'a> Server<'a> {
         <M as MakeService>::Service: Send,
     {
         for conn in self.incoming {
+            if self.shutdown.is_shutdown() {
+                break;
+            }
+
             let app = make_service.clone();
             if let Ok(handler) = app.call(&conn) {
+                let config = self.config.clone();
                 self.thread_pool.execute(move || {
-                    serve(conn, handler).ok();
+                    serve(conn, handler, &config).ok();
                 });
             }
         }
@@ -247,6 +557,34 @@ impl<'a> Server<'a> {
 pub struct ServerBuilder {
     max_threads: usize,
     read_timeout: Option<Duration>,
+    header_read_timeout: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    max_requests_per_connection: Option<usize>,
+    write_timeout: Option<Duration>,
+    body_read_buffer_size: usize,
+    max_chunk_size: Option<u64>,
+    sort_headers: bool,
+    force_content_length: bool,
+    coalesce_chunks: Option<usize>,
+    on_protocol_error: Option<Box<ProtocolErrorHandler>>,
+    #[cfg(feature = "rustls")]
+    on_tls_error: Option<Box<TlsErrorHandler>>,
+    server_name: Option<HeaderValue>,
+    response_hooks: Vec<Box<ResponseHook>>,
+    trusted_proxies: Vec<IpAddr>,
+    #[cfg(feature = "compression")]
+    compression: Option<CompressionConfig>,
+    capture_limit: Option<usize>,
+    max_headers: usize,
+    max_request_line_bytes: usize,
+    allow_bare_lf: bool,
+    allow_obs_fold: bool,
+    tcp_nodelay: bool,
+    workers: usize,
+    reuse_address: bool,
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    reuse_port: bool,
+    backlog: u32,
 }
 
 impl Default for ServerBuilder {
@@ -254,6 +592,35 @@ impl Default for ServerBuilder {
         Self {
             max_threads: 512,
             read_timeout: None,
+            header_read_timeout: None,
+            keep_alive_timeout: None,
+            max_requests_per_connection: None,
+            write_timeout: None,
+            body_read_buffer_size: 8 * 1024,
+            max_chunk_size: None,
+            sort_headers: false,
+            force_content_length: false,
+            coalesce_chunks: None,
+            on_protocol_error: None,
+            #[cfg(feature = "rustls")]
+            on_tls_error: None,
+            server_name: None,
+            response_hooks: Vec::new(),
+            trusted_proxies: Vec::new(),
+            #[cfg(feature = "compression")]
+            compression: None,
+            capture_limit: None,
+            max_headers: 64,
+            max_request_line_bytes: 8 * 1024,
+            allow_bare_lf: false,
+            allow_obs_fold: false,
+            tcp_nodelay: false,
+            workers: 1,
+            // Mimics the std TcpListener behavior
+            reuse_address: !cfg!(windows),
+            #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+            reuse_port: false,
+            backlog: 128,
         }
     }
 }
@@ -346,163 +713,2686 @@ impl ServerBuilder {
         }
     }
 
-    /// Binds the [`Server`] to the given `addr`.
+    /// Sets the write timeout of every connection. Clients that stop reading the response for
+    /// longer than the timeout are disconnected, so they can't hold a thread forever. Defaults to
+    /// no timeout.
     ///
-    /// # Panics
+    /// As with [`ServerBuilder::read_timeout`], upgraded connections keep this timeout, which can
+    /// be changed with [`Connection::set_write_timeout`].
     ///
-    /// This method will panic if binding to the address fails. For a non panic way to bind a
-    /// server, see [`ServerBuilder::try_bind`].
-    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> Server<'static> {
-        self.try_bind(addr).unwrap()
+    /// # Example
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .write_timeout(Duration::from_secs(30))
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn write_timeout<T: Into<Option<Duration>>>(self, timeout: T) -> Self {
+        Self {
+            write_timeout: timeout.into(),
+            ..self
+        }
     }
 
-    /// Tries to bind the server to the informed `addr`.
-    pub fn try_bind<A: ToSocketAddrs>(self, addr: A) -> io::Result<Server<'static>> {
-        let listener = TcpListener::bind(addr)?;
-        Ok(self.from_connections(TcpAcceptor { listener }))
+    /// Sets a deadline for receiving a whole request head, counted from its first byte. Clients
+    /// exceeding it are answered with `408 Request Timeout` and disconnected. Defaults to no
+    /// deadline.
+    ///
+    /// Unlike [`ServerBuilder::read_timeout`], which bounds each read, this bounds the total time,
+    /// so a client can't hold a connection by slowly sending its headers one byte at a time.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .read_timeout(Duration::from_secs(30))
+    ///     .header_read_timeout(Duration::from_secs(5))
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn header_read_timeout<T: Into<Option<Duration>>>(self, timeout: T) -> Self {
+        Self {
+            header_read_timeout: timeout.into(),
+            ..self
+        }
     }
 
-    /// Accepts connections from some [`Iterator`].
-    pub fn from_connections<'a, T: IntoIterator<Item = Connection> + 'a>(
-        self,
-        conns: T,
-    ) -> Server<'a> {
-        Server {
-            thread_pool: ThreadPool::new(self.max_threads),
-            incoming: Box::new(conns.into_iter().filter_map(move |conn| {
-                conn.set_read_timeout(self.read_timeout).ok()?;
-                Some(conn)
-            })),
+    /// Sets how long kept alive connections may stay idle waiting for their next request, after
+    /// which they are closed. Unlike [`ServerBuilder::read_timeout`], it doesn't apply to the
+    /// first request of a connection. Disabled by default.
+    ///
+    /// Clients are told about it with a `Keep-Alive: timeout=<secs>` header on responses that
+    /// keep the connection open, so they can stop reusing it before the server closes it. The
+    /// advertised timeout is rounded up to whole seconds.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .keep_alive_timeout(Duration::from_secs(5))
+    ///     .max_requests_per_connection(100)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn keep_alive_timeout<T: Into<Option<Duration>>>(self, timeout: T) -> Self {
+        Self {
+            keep_alive_timeout: timeout.into(),
+            ..self
         }
     }
-}
-
-struct TcpAcceptor {
-    listener: TcpListener,
-}
-
-impl Iterator for TcpAcceptor {
-    type Item = Connection;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        Some(self.listener.accept().ok()?.into())
+    /// Sets the maximum number of requests served on a single connection. The response to the
+    /// last one has a `Connection: close` header, and the connection is closed after it. Not
+    /// limited by default.
+    ///
+    /// Responses that keep the connection open have a `Keep-Alive: max=<n>` header with the
+    /// number of requests the client can still send on it.
+    pub fn max_requests_per_connection(self, max: usize) -> Self {
+        Self {
+            max_requests_per_connection: Some(max),
+            ..self
+        }
     }
-}
-
-pub trait MakeService {
-    type Service: Service;
-    type Error: Into<Box<dyn Error + Send + Sync>>;
-
-    fn call(&self, conn: &Connection) -> Result<Self::Service, Self::Error>;
-}
-
-impl<F, S, Err> MakeService for F
-where
-    F: Fn(&Connection) -> Result<S, Err>,
-    Err: Into<Box<dyn Error + Send + Sync>>,
-    S: Service + Send,
-{
-    type Service = S;
-    type Error = Err;
 
-    fn call(&self, conn: &Connection) -> Result<Self::Service, Self::Error> {
-        self(conn)
+    /// Sets the size of the buffers used to read streaming request bodies. Defaults to `8 KiB`.
+    ///
+    /// Larger buffers reduce the number of reads when handling big uploads, at the cost of more
+    /// memory per connection.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `body_read_buffer_size` is zero.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Request, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .body_read_buffer_size(64 * 1024)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|req: Request<_>| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(req.into_body())
+    ///     })
+    /// # }
+    /// ```
+    pub fn body_read_buffer_size(self, body_read_buffer_size: usize) -> Self {
+        assert!(
+            body_read_buffer_size > 0,
+            "body read buffer size must be greater than zero"
+        );
+        Self {
+            body_read_buffer_size,
+            ..self
+        }
     }
-}
 
-fn serve<C: Into<Connection>, A: Service>(stream: C, app: A) -> io::Result<()> {
-    let conn = stream.into();
-    let mut read_queue = ReadQueue::new(BufReader::new(conn.clone()));
+    /// Sets the maximum size of each chunk of chunked encoded request bodies. Requests declaring
+    /// bigger chunks fail to be read with an [`io::ErrorKind::InvalidData`] error. Defaults to no
+    /// limit.
+    ///
+    /// Note that chunks are always read as their bytes arrive, so a declared size alone doesn't
+    /// make the server allocate memory.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Request, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .max_chunk_size(64 * 1024)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|req: Request<_>| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(req.into_body())
+    ///     })
+    /// # }
+    /// ```
+    pub fn max_chunk_size(self, max_chunk_size: u64) -> Self {
+        Self {
+            max_chunk_size: Some(max_chunk_size),
+            ..self
+        }
+    }
+
+    /// Writes response headers (and trailers) sorted by name, so the output is deterministic.
+    /// Defaults to `false`.
+    ///
+    /// Names are compared by the bytes of their lowercase form, in ascending order. When a header
+    /// has multiple values, such as `Set-Cookie`, each value is written on its own line in the
+    /// order they were inserted into the response.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .sort_headers(true)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .header("x-b", "b")
+    ///             .header("x-a", "a")
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn sort_headers(self, sort_headers: bool) -> Self {
+        Self {
+            sort_headers,
+            ..self
+        }
+    }
+
+    /// Buffers every response body of unknown length to send it with a `Content-Length`, instead
+    /// of using chunked encoding or closing the connection to delimit it. Useful for legacy
+    /// clients that can't handle chunked encoding, at the cost of holding whole bodies in memory.
+    /// Defaults to `false`.
+    ///
+    /// Trailers can't be sent without chunked encoding, so they are discarded. Upgrade responses
+    /// are never buffered.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .force_content_length(true)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(Body::from_iter(["lol", "wut"]))
+    ///     })
+    /// # }
+    /// ```
+    pub fn force_content_length(self, force_content_length: bool) -> Self {
+        Self {
+            force_content_length,
+            ..self
+        }
+    }
+
+    /// Combines the data of chunked response bodies into chunks of at least `threshold` bytes,
+    /// reducing the framing overhead and writes of bodies made of many tiny chunks. Disabled by
+    /// default, so every chunk is sent as soon as it is produced.
+    ///
+    /// This trades latency for throughput: data is held until enough of it is produced or the
+    /// body ends, so it is not suited to streams that must reach the client right away, such as
+    /// server-sent events.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Body, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .coalesce_chunks(16 * 1024)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         let lines = (0..10_000).map(|i| format!("line {i}\n"));
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(Body::from_iter(lines))
+    ///     })
+    /// # }
+    /// ```
+    pub fn coalesce_chunks(self, threshold: usize) -> Self {
+        Self {
+            coalesce_chunks: Some(threshold),
+            ..self
+        }
+    }
+
+    /// Sets a callback that is called whenever a client sends a request that violates the HTTP
+    /// protocol. The server still answers the client with the appropriate status code (see
+    /// [`ProtocolError::status`]) and closes the connection.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .on_protocol_error(|err| eprintln!("Bad request: {err}"))
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn on_protocol_error<F>(self, handler: F) -> Self
+    where
+        F: Fn(&ProtocolError) + Send + Sync + 'static,
+    {
+        Self {
+            on_protocol_error: Some(Box::new(handler)),
+            ..self
+        }
+    }
+
+    /// Sets a callback that is called whenever the TLS handshake of a connection fails, with the
+    /// error and the address of the client. The connection is closed afterwards.
+    ///
+    /// Handshakes fail either because of a TLS error, such as the client not trusting the
+    /// certificate or not supporting any of the offered protocol versions, or because of an I/O
+    /// error, such as the client going away. TLS errors are wrapped in the [`io::Error`], and can
+    /// be recovered with [`io::Error::get_ref`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::sync::Arc;
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// # let config: Arc<rustls::ServerConfig> = unimplemented!();
+    /// Server::builder()
+    ///     .on_tls_error(|err, peer_addr| {
+    ///         match err.get_ref().and_then(|err| err.downcast_ref::<rustls::Error>()) {
+    ///             Some(err) => eprintln!("TLS handshake with {peer_addr:?} failed: {err}"),
+    ///             None => eprintln!("Connection with {peer_addr:?} failed: {err}"),
+    ///         }
+    ///     })
+    ///     .bind_tls("0.0.0.0:4444", config)
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body("Hello from TLS")
+    ///     })
+    /// # }
+    /// ```
+    #[cfg(feature = "rustls")]
+    pub fn on_tls_error<F>(self, handler: F) -> Self
+    where
+        F: Fn(&io::Error, Option<SocketAddr>) + Send + Sync + 'static,
+    {
+        Self {
+            on_tls_error: Some(Box::new(handler)),
+            ..self
+        }
+    }
+
+    /// Adds a `Server` header with the given `name` to every response that doesn't already have
+    /// one. By default no `Server` header is sent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not a valid header value.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .server_name("touche")
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn server_name(self, name: &str) -> Self {
+        Self {
+            server_name: Some(HeaderValue::from_str(name).expect("invalid server name")),
+            ..self
+        }
+    }
+
+    /// Sets the addresses of the reverse proxies trusted to report the client address with the
+    /// `Forwarded` or `X-Forwarded-For` headers. Requests get the resulting [`ClientIp`] on their
+    /// extensions.
+    ///
+    /// Hops are only taken into account when added by a trusted proxy, so clients can't spoof
+    /// their address. By default no proxy is trusted, and the client address is always the peer.
+    ///
+    /// [`ClientIp`]: crate::proxy::ClientIp
+    pub fn trusted_proxies(self, proxies: impl IntoIterator<Item = IpAddr>) -> Self {
+        Self {
+            trusted_proxies: proxies.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Compresses the responses of clients accepting `gzip`, according to `config`. See the
+    /// [`compress`](crate::compress) module for details. Disabled by default.
+    ///
+    /// Compression happens after the response hooks run, so they still see the original headers.
+    #[cfg(feature = "compression")]
+    pub fn compression(self, config: CompressionConfig) -> Self {
+        Self {
+            compression: Some(config),
+            ..self
+        }
+    }
+
+    /// Adds a hook that can rewrite the head of every response produced by the service, right
+    /// before it is written. Useful for cross cutting concerns such as security headers or
+    /// logging. Hooks run in the order they were added, after the default `Date` and `Server`
+    /// headers are set.
+    ///
+    /// Interim responses and the ones sent on protocol errors are not passed to the hooks.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{header, Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .map_response(|res| {
+    ///         res.headers
+    ///             .insert(header::X_CONTENT_TYPE_OPTIONS, "nosniff".parse().unwrap());
+    ///     })
+    ///     .map_response(|res| eprintln!("Responded with {}", res.status))
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn map_response<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut http::response::Parts) + Send + Sync + 'static,
+    {
+        self.response_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Sets the maximum number of headers a request may have. Requests with more headers are
+    /// answered with `431 Request Header Fields Too Large`. Defaults to `64`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .max_headers(32)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn max_headers(self, max_headers: usize) -> Self {
+        Self {
+            max_headers,
+            ..self
+        }
+    }
+
+    /// Sets the maximum length in bytes of the request line, not counting its line terminator.
+    /// Requests with longer lines are answered with `414 URI Too Long`. Defaults to `8192`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .max_request_line_bytes(2048)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn max_request_line_bytes(self, max_request_line_bytes: usize) -> Self {
+        Self {
+            max_request_line_bytes,
+            ..self
+        }
+    }
+
+    /// Accepts request heads with lines terminated by a bare `\n` instead of `\r\n`, for
+    /// compatibility with broken clients. By default such requests are answered with
+    /// `400 Bad Request`, as servers and proxies disagreeing on line terminators can be abused to
+    /// smuggle requests.
+    pub fn allow_bare_lf(self, allow_bare_lf: bool) -> Self {
+        Self {
+            allow_bare_lf,
+            ..self
+        }
+    }
+
+    /// Accepts header values folded over several lines, with continuation lines starting with
+    /// spaces or tabs, which is deprecated. Folded values are joined with a single space. By
+    /// default such requests are answered with `400 Bad Request`, as they can be abused to
+    /// smuggle headers past proxies that read them differently.
+    pub fn allow_obs_fold(self, allow_obs_fold: bool) -> Self {
+        Self {
+            allow_obs_fold,
+            ..self
+        }
+    }
+
+    /// Records the raw bytes of every request, up to `limit` bytes each, making them available to
+    /// services through the [`RawRequest`](crate::RawRequest) request extension. Meant for
+    /// debugging, as it copies everything read from the connection. Disabled by default.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .capture_raw_requests(16 * 1024)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn capture_raw_requests(self, limit: usize) -> Self {
+        Self {
+            capture_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Sets the `TCP_NODELAY` option on every accepted connection, disabling Nagle's algorithm.
+    /// This reduces the latency of small writes, such as streamed chunks, at the cost of more
+    /// packets. Defaults to `false`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .tcp_nodelay(true)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn tcp_nodelay(self, tcp_nodelay: bool) -> Self {
+        Self {
+            tcp_nodelay,
+            ..self
+        }
+    }
+
+    /// Sets the number of threads accepting connections from the listener, so accepting (and
+    /// setting up) new connections is spread among them. Defaults to `1`.
+    ///
+    /// Requests are still served by the thread pool, see [`ServerBuilder::max_threads`]. To spread
+    /// the load among multiple processes instead, bind each of them to the same address with
+    /// [`ServerBuilder::reuse_port`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` is zero.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .workers(4)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn workers(self, workers: usize) -> Self {
+        assert!(workers > 0, "workers must be greater than zero");
+        Self { workers, ..self }
+    }
+
+    /// Sets the `SO_REUSEADDR` option on the listener socket before binding it. Defaults to
+    /// `true` on every platform except Windows, which is the same behavior of
+    /// [`TcpListener::bind`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .reuse_address(true)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn reuse_address(self, reuse_address: bool) -> Self {
+        Self {
+            reuse_address,
+            ..self
+        }
+    }
+
+    /// Sets the `SO_REUSEPORT` option on the listener socket before binding it, allowing several
+    /// processes (or servers) to bind to the same address. Defaults to `false`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .reuse_port(true)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    pub fn reuse_port(self, reuse_port: bool) -> Self {
+        Self { reuse_port, ..self }
+    }
+
+    /// Sets the size of the queue of connections waiting to be accepted by the listener. Bursts
+    /// of connections beyond it may be refused or dropped by the OS, which may also cap it (e.g.
+    /// to `net.core.somaxconn` on Linux). Defaults to `128`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// Server::builder()
+    ///     .backlog(4096)
+    ///     .bind("0.0.0.0:4444")
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn backlog(self, backlog: u32) -> Self {
+        Self { backlog, ..self }
+    }
+
+    /// Binds the [`Server`] to the given `addr`.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if binding to the address fails. For a non panic way to bind a
+    /// server, see [`ServerBuilder::try_bind`].
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> Server<'static> {
+        self.try_bind(addr).unwrap()
+    }
+
+    /// Tries to bind the server to the informed `addr`.
+    pub fn try_bind<A: ToSocketAddrs>(self, addr: A) -> io::Result<Server<'static>> {
+        let mut last_err = None;
+
+        for addr in addr.to_socket_addrs()? {
+            match self.bind_listener(addr) {
+                Ok(listener) => return Ok(self.accept_from(TcpAcceptor::new(listener))),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    /// Binds the [`Server`] to the given `addr`, accepting TLS connections with the given rustls
+    /// `config`.
+    ///
+    /// The handshake of each connection is performed on the thread serving it, so the config may
+    /// carry a certificate resolver to pick a certificate based on the SNI hostname, allowing
+    /// multiple domains to be hosted on the same port.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if binding to the address fails. For a non panic way to bind a
+    /// server, see [`ServerBuilder::try_bind_tls`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::sync::Arc;
+    /// # use rustls::{Certificate, PrivateKey, ServerConfig};
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// # let (certs, key) = (Vec::<Certificate>::new(), PrivateKey(Vec::new()));
+    /// let config = ServerConfig::builder()
+    ///     .with_safe_defaults()
+    ///     .with_no_client_auth()
+    ///     .with_single_cert(certs, key)
+    ///     .map_err(std::io::Error::other)?;
+    ///
+    /// Server::builder()
+    ///     .bind_tls("0.0.0.0:4444", Arc::new(config))
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body("Hello from TLS")
+    ///     })
+    /// # }
+    /// ```
+    #[cfg(feature = "rustls")]
+    pub fn bind_tls<A: ToSocketAddrs>(
+        self,
+        addr: A,
+        config: Arc<rustls::ServerConfig>,
+    ) -> Server<'static> {
+        self.try_bind_tls(addr, config).unwrap()
+    }
+
+    /// Tries to bind the server to the informed `addr`, accepting TLS connections with the given
+    /// rustls `config`. See [`ServerBuilder::bind_tls`].
+    #[cfg(feature = "rustls")]
+    pub fn try_bind_tls<A: ToSocketAddrs>(
+        self,
+        addr: A,
+        config: Arc<rustls::ServerConfig>,
+    ) -> io::Result<Server<'static>> {
+        let mut last_err = None;
+
+        for addr in addr.to_socket_addrs()? {
+            match self.bind_listener(addr) {
+                Ok(listener) => {
+                    return Ok(self.accept_from(TlsAcceptor {
+                        acceptor: TcpAcceptor::new(listener),
+                        config,
+                    }))
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    fn bind_listener(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(self.reuse_address)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(self.reuse_port)?;
+        socket.bind(&addr.into())?;
+        socket.listen(i32::try_from(self.backlog).unwrap_or(i32::MAX))?;
+        Ok(socket.into())
+    }
+
+    /// Accepts connections from an already bound `listener`, instead of binding one. Useful for
+    /// socket activation, or to find out the port assigned by the OS when binding to port `0`.
+    ///
+    /// Note that socket options such as [`ServerBuilder::reuse_address`] only apply to listeners
+    /// bound by the server.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::net::TcpListener;
+    /// # use touche::{Response, Server, StatusCode};
+    /// # fn main() -> std::io::Result<()> {
+    /// let listener = TcpListener::bind("127.0.0.1:0")?;
+    /// println!("Listening on {}", listener.local_addr()?);
+    ///
+    /// Server::builder()
+    ///     .from_listener(listener)
+    ///     .serve(|_req| {
+    ///         Response::builder()
+    ///             .status(StatusCode::OK)
+    ///             .body(())
+    ///     })
+    /// # }
+    /// ```
+    pub fn from_listener(self, listener: TcpListener) -> Server<'static> {
+        self.accept_from(TcpAcceptor::new(listener))
+    }
+
+    /// Accepts connections with the configured number of [`ServerBuilder::workers`], each running
+    /// its own accept loop on a clone of the listener.
+    fn accept_from<A: Acceptor>(self, acceptor: A) -> Server<'static> {
+        let shutdown = acceptor.shutdown_handle();
+
+        if self.workers == 1 {
+            return Server {
+                shutdown,
+                ..self.from_connections(acceptor)
+            };
+        }
+
+        // Runs with fewer workers if the listener can't be cloned
+        let clones = (1..self.workers)
+            .map_while(|_| acceptor.try_clone().ok())
+            .collect::<Vec<_>>();
+
+        let (sender, receiver) = mpsc::sync_channel(self.workers);
+        for acceptor in clones.into_iter().chain(Some(acceptor)) {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for conn in acceptor {
+                    if sender.send(conn).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Server {
+            shutdown,
+            ..self.from_connections(receiver)
+        }
+    }
+
+    /// Accepts connections from some [`Iterator`].
+    pub fn from_connections<'a, T: IntoIterator<Item = Connection> + 'a>(
+        self,
+        conns: T,
+    ) -> Server<'a> {
+        let config = Config {
+            parse: ParseConfig {
+                body_buffer_size: self.body_read_buffer_size,
+                max_chunk_size: self.max_chunk_size,
+                read_timeout: self.read_timeout,
+                header_read_timeout: self.header_read_timeout,
+                capture_limit: self.capture_limit,
+                max_headers: self.max_headers,
+                max_request_line: self.max_request_line_bytes,
+                allow_bare_lf: self.allow_bare_lf,
+                allow_obs_fold: self.allow_obs_fold,
+            },
+            write: WriteConfig {
+                sort_headers: self.sort_headers,
+                force_content_length: self.force_content_length,
+                coalesce_chunks: self.coalesce_chunks,
+            },
+            on_protocol_error: self.on_protocol_error,
+            #[cfg(feature = "rustls")]
+            on_tls_error: self.on_tls_error,
+            server_name: self.server_name,
+            response_hooks: self.response_hooks,
+            trusted_proxies: self.trusted_proxies,
+            #[cfg(feature = "compression")]
+            compression: self.compression,
+            keep_alive_timeout: self.keep_alive_timeout,
+            max_requests_per_connection: self.max_requests_per_connection,
+            stats: ServerStats::default(),
+        };
+
+        Server {
+            thread_pool: ThreadPool::new(self.max_threads),
+            incoming: Box::new(conns.into_iter().filter_map(move |conn| {
+                conn.set_read_timeout(self.read_timeout).ok()?;
+                conn.set_write_timeout(self.write_timeout).ok()?;
+                if self.tcp_nodelay {
+                    conn.set_nodelay(true).ok()?;
+                }
+                Some(conn)
+            })),
+            config: Arc::new(config),
+            shutdown: ShutdownHandle::default(),
+        }
+    }
+}
+
+/// Iterators of connections accepted from a listener.
+trait Acceptor: Iterator<Item = Connection> + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+
+    fn shutdown_handle(&self) -> ShutdownHandle;
+}
+
+struct TcpAcceptor {
+    listener: TcpListener,
+    shutdown: ShutdownHandle,
+}
+
+impl TcpAcceptor {
+    fn new(listener: TcpListener) -> Self {
+        let shutdown = ShutdownHandle::default();
+        if let Ok(addr) = listener.local_addr() {
+            shutdown.listen_on(addr);
+        }
+        Self { listener, shutdown }
+    }
+
+    /// Blocks until a connection arrives, or the server is shut down.
+    fn accept(&self) -> Option<TcpStream> {
+        let accepted = self.listener.accept();
+
+        if self.shutdown.is_shutdown() {
+            // Lets the next thread blocked on this listener know about the shutdown
+            self.shutdown.wake();
+            return None;
+        }
+
+        Some(accepted.ok()?.0)
+    }
+}
+
+impl Acceptor for TcpAcceptor {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            listener: self.listener.try_clone()?,
+            shutdown: self.shutdown.clone(),
+        })
+    }
+
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+}
+
+impl Iterator for TcpAcceptor {
+    type Item = Connection;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.accept()?.into())
+    }
+}
+
+#[cfg(feature = "rustls")]
+struct TlsAcceptor {
+    acceptor: TcpAcceptor,
+    config: Arc<rustls::ServerConfig>,
+}
+
+#[cfg(feature = "rustls")]
+impl Acceptor for TlsAcceptor {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            acceptor: self.acceptor.try_clone()?,
+            config: self.config.clone(),
+        })
+    }
+
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.acceptor.shutdown_handle()
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl Iterator for TlsAcceptor {
+    type Item = Connection;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tcp = self.acceptor.accept()?;
+        // The handshake itself only happens on the thread serving the connection
+        let tls = rustls::ServerConnection::new(self.config.clone()).ok()?;
+        Some(rustls::StreamOwned::new(tls, tcp).into())
+    }
+}
+
+/// Returns how the server will frame `res`: how its body is delimited and whether the connection
+/// is kept alive afterwards. Useful to inspect a response before returning it, e.g. in middlewares.
+///
+/// Only the response is taken into account, so the connection is still closed if the client asked
+/// for it. Upgrade responses hand the connection over, so they are never kept alive.
+///
+/// Fails if the `Content-Length` header doesn't match the length of the body.
+///
+/// # Example
+/// ```
+/// # use touche::{server::{self, Encoding}, Response};
+/// let res = Response::builder().body("lolwut").unwrap();
+///
+/// let framing = server::framing(&res).unwrap();
+/// assert_eq!(framing.encoding, Encoding::FixedLength(6));
+/// assert!(framing.keep_alive);
+/// ```
+pub fn framing<B: HttpBody>(res: &Response<B>) -> io::Result<Framing> {
+    let mut headers = res.headers().clone();
+    if let Some(control) = res.extensions().get::<ConnectionControl>() {
+        control.apply(&mut headers);
+    }
+    let framing =
+        response::decide_encoding(res.status(), res.version(), &mut headers, res.body().len())?;
+
+    if res.extensions().get::<UpgradeExtension>().is_some() {
+        return Ok(Framing {
+            encoding: Encoding::CloseDelimited,
+            keep_alive: false,
+        });
+    }
+
+    Ok(framing)
+}
+
+/// Serializes `res` to `writer` exactly as the server would send it on a connection, returning how
+/// many bytes were written.
+///
+/// Nothing is assumed about the writer, so it can be a buffer, a file, or a bridge to async I/O
+/// driven from another thread. Streaming bodies are written as they are produced, with chunked
+/// encoding when their length is unknown. Upgrade responses only have their head written, their
+/// handler is never called as there is no connection to hand over.
+///
+/// # Example
+/// ```
+/// # use touche::{server, Body, Response};
+/// # fn main() -> std::io::Result<()> {
+/// let res = Response::builder()
+///     .body(Body::from_iter(vec!["lol", "wut"]))
+///     .unwrap();
+///
+/// let mut buf = Vec::new();
+/// let written = server::write_response(res, &mut buf)?;
+///
+/// assert!(buf.ends_with(b"3\r\nlol\r\n3\r\nwut\r\n0\r\n\r\n"));
+/// assert_eq!(written.body, 6);
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_response<B: HttpBody>(
+    res: Response<B>,
+    writer: &mut impl Write,
+) -> io::Result<BytesWritten> {
+    let config = WriteConfig::default();
+    let (_, written) = response::write_response(res, &mut Plain(writer), true, &config)?;
+    Ok(written)
+}
+
+/// Same as [`write_response`], but framing the body with the given `encoding` instead of
+/// inferring it from the response. Meant for tools that test how clients handle specific framing.
+///
+/// The `Content-Length` and `Transfer-Encoding` headers are replaced according to `encoding`, and
+/// `Connection: close` is added to close delimited responses. Chunked bodies are written with one
+/// chunk per [`Chunk`](crate::body::Chunk) produced by the body, so their layout can be chosen
+/// with [`Body::from_iter`]. Bodies of unknown length are cut at the given fixed length.
+///
+/// Fails if chunked encoding is used with HTTP/1.0, or if a fixed length doesn't match the known
+/// length of the body.
+///
+/// # Example
+/// ```
+/// # use touche::{server::{self, Encoding}, Body, Response};
+/// # fn main() -> std::io::Result<()> {
+/// let res = Response::builder()
+///     .body(Body::from_iter(vec!["l", "ol", "wut"]))
+///     .unwrap();
+///
+/// let mut buf = Vec::new();
+/// server::write_response_with(res, Encoding::Chunked, &mut buf)?;
+///
+/// assert!(buf.ends_with(b"1\r\nl\r\n2\r\nol\r\n3\r\nwut\r\n0\r\n\r\n"));
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_response_with<B: HttpBody>(
+    mut res: Response<B>,
+    encoding: Encoding,
+    writer: &mut impl Write,
+) -> io::Result<BytesWritten> {
+    res.extensions_mut().insert(ExplicitEncoding(encoding));
+    write_response(res, writer)
+}
+
+/// Sends informational `1xx` responses, such as `103 Early Hints`, before the final response.
+///
+/// Available to services as a request extension. It only works while the service is being called,
+/// sending after the final response was returned fails. Interim responses are not sent to
+/// HTTP/1.0 clients, as they don't support them.
+///
+/// # Example
+/// ```no_run
+/// # use touche::{header, server::EarlyHints, Body, HeaderMap, Request, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+///     if let Some(hints) = req.extensions().get::<EarlyHints>() {
+///         let mut headers = HeaderMap::new();
+///         headers.insert(header::LINK, "</style.css>; rel=preload; as=style".parse().unwrap());
+///         hints.send(headers).ok();
+///     }
+///
+///     Response::builder()
+///         .status(StatusCode::OK)
+///         .body("<link rel=\"stylesheet\" href=\"/style.css\">")
+/// })
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct EarlyHints {
+    conn: Arc<Mutex<Option<Connection>>>,
+    version: Version,
+    config: WriteConfig,
+}
+
+impl EarlyHints {
+    fn new(conn: Connection, version: Version, config: WriteConfig) -> Self {
+        Self {
+            conn: Arc::new(Mutex::new(Some(conn))),
+            version,
+            config,
+        }
+    }
+
+    /// Sends a `103 Early Hints` response with the given `headers`, usually `Link` ones.
+    pub fn send(&self, headers: HeaderMap) -> io::Result<()> {
+        self.send_informational(StatusCode::from_u16(103).unwrap(), headers)
+    }
+
+    /// Sends an informational response with the given `status` and `headers`.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the status is not a `1xx` one, or if it is
+    /// `101 Switching Protocols`, which must be sent as the final response instead.
+    pub fn send_informational(&self, status: StatusCode, headers: HeaderMap) -> io::Result<()> {
+        if !status.is_informational() || status == StatusCode::SWITCHING_PROTOCOLS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not an interim status",
+            ));
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let conn = conn
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the final response was already sent"))?;
+
+        if self.version < Version::HTTP_11 {
+            return Ok(());
+        }
+
+        let mut res = Response::new(());
+        *res.status_mut() = status;
+        *res.version_mut() = self.version;
+        *res.headers_mut() = headers;
+
+        let mut writer = BufWriter::new(conn);
+        response::write_response(res, &mut writer, true, &self.config)?;
+        writer.flush()
+    }
+
+    fn finish(&self) {
+        self.conn.lock().unwrap().take();
+    }
+}
+
+impl std::fmt::Debug for EarlyHints {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EarlyHints")
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+pub trait MakeService {
+    type Service: Service;
+    type Error: Into<Box<dyn Error + Send + Sync>>;
+
+    fn call(&self, conn: &Connection) -> Result<Self::Service, Self::Error>;
+}
+
+impl<F, S, Err> MakeService for F
+where
+    F: Fn(&Connection) -> Result<S, Err>,
+    Err: Into<Box<dyn Error + Send + Sync>>,
+    S: Service + Send,
+{
+    type Service = S;
+    type Error = Err;
+
+    fn call(&self, conn: &Connection) -> Result<Self::Service, Self::Error> {
+        self(conn)
+    }
+}
+
+pub(crate) fn serve<C: Into<Connection>, A: Service>(
+    stream: C,
+    app: A,
+    config: &Config,
+) -> io::Result<()> {
+    let _active = config.stats.connection_opened();
+    let conn = stream.into();
+    let teardown = conn.clone();
+    let result = match conn.complete_handshake() {
+        Ok(()) => serve_connection(conn, app, config),
+        Err(err) => {
+            #[cfg(feature = "rustls")]
+            if let Some(ref on_tls_error) = config.on_tls_error {
+                on_tls_error(&err, conn.peer_addr());
+            }
+            Err(err)
+        }
+    };
+
+    match result {
+        // The client going away in the middle of a response is not a server failure
+        Err(err) if is_disconnect(&err) => Ok(()),
+        // Resets the connection once closed, so the client can tell the response is incomplete
+        Err(err) if body::is_aborted(&err) => {
+            teardown.reset().ok();
+            Err(err)
+        }
+        // The client stopped reading, so give up on it without waiting for anything else
+        Err(err) if request::is_timeout(&err) => {
+            teardown.shutdown(Shutdown::Both).ok();
+            Err(err)
+        }
+        result => result,
+    }
+}
+
+/// Tells the client about the keep alive policy with a `Keep-Alive` header, when the response
+/// keeps the connection open and limits are configured.
+fn keep_alive_hint<B: HttpBody>(res: &mut Response<B>, config: &Config, requests: usize) {
+    let mut hints = Vec::new();
+    if let Some(timeout) = config.keep_alive_timeout {
+        // Rounded up, as `timeout=0` would tell clients not to reuse the connection at all
+        let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+        hints.push(format!("timeout={}", secs.max(1)));
+    }
+    if let Some(max) = config.max_requests_per_connection {
+        hints.push(format!("max={}", max.saturating_sub(requests)));
+    }
+
+    if hints.is_empty()
+        || res.headers().contains_key("keep-alive")
+        || !framing(res).is_ok_and(|framing| framing.keep_alive)
+    {
+        return;
+    }
+
+    if let Ok(value) = HeaderValue::from_str(&hints.join(", ")) {
+        res.headers_mut().insert("keep-alive", value);
+    }
+}
+
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+    )
+}
+
+fn serve_connection<A: Service>(conn: Connection, app: A, config: &Config) -> io::Result<()> {
+    let mut reused = false;
+    let mut requests = 0;
+
+    let info = conn.info();
+    let timeouts = conn.clone();
+    let mut read_queue = ReadQueue::new(BufReader::new(conn.clone()));
 
     let mut reader = read_queue.enqueue();
     let mut writer = BufWriter::new(conn);
 
-    loop {
-        match request::parse_request(reader) {
-            Ok(req) => {
-                reader = read_queue.enqueue();
+    loop {
+        if let (true, Some(timeout)) = (reused, config.keep_alive_timeout) {
+            timeouts.set_read_timeout(Some(timeout))?;
+            let idle = reader.fill_buf().map(|_| ());
+            timeouts.set_read_timeout(config.parse.read_timeout)?;
+            match idle {
+                Err(err) if request::is_timeout(&err) => break,
+                result => result?,
+            }
+        }
+
+        match request::parse_request(reader, &config.parse, Some(&timeouts)) {
+            Ok(mut req) => {
+                reader = read_queue.enqueue();
+                requests += 1;
+                let body_end = req.extensions_mut().remove::<BodyEnd>().unwrap_or_default();
+
+                req.extensions_mut().insert(info.clone());
+                if let Some(peer_addr) = info.peer_addr() {
+                    let client_ip =
+                        proxy::client_ip(peer_addr.ip(), req.headers(), &config.trusted_proxies);
+                    req.extensions_mut().insert(client_ip);
+                    req.extensions_mut().insert(peer_addr);
+                }
+
+                let asks_for_close = req
+                    .headers()
+                    .typed_get::<headers::Connection>()
+                    .filter(|conn| conn.contains("close"))
+                    .is_some();
+
+                let asks_for_keep_alive = req
+                    .headers()
+                    .typed_get::<headers::Connection>()
+                    .filter(|conn| conn.contains("keep-alive"))
+                    .is_some();
+
+                let version = req.version();
+                let method = req.method().clone();
+
+                let last_allowed = config
+                    .max_requests_per_connection
+                    .is_some_and(|max| requests >= max);
+
+                let demands_close = last_allowed
+                    || match version {
+                        Version::HTTP_09 => true,
+                        Version::HTTP_10 => !asks_for_keep_alive,
+                        _ => asks_for_close,
+                    };
+
+                let expects_continue = req
+                    .headers()
+                    .typed_get::<headers::Expect>()
+                    .filter(|expect| expect == &headers::Expect::CONTINUE)
+                    .is_some();
+
+                if expects_continue {
+                    match app.should_continue(&req) {
+                        status @ StatusCode::CONTINUE => {
+                            let res = Response::builder().status(status).body(()).unwrap();
+                            response::write_response(res, &mut writer, true, &config.write)?;
+                            writer.flush()?;
+                        }
+                        status => {
+                            if let Some(discarder) = req.extensions_mut().remove::<BodyDiscarder>()
+                            {
+                                discarder.discard();
+                            }
+                            drop(req);
+
+                            // Clients may send the body without waiting for the interim response,
+                            // so whatever follows can't be trusted to be the next request. The
+                            // connection is only kept when no body bytes arrived in the meantime.
+                            timeouts.set_read_timeout(Some(UNSENT_BODY_GRACE))?;
+                            let sent_body = match reader.fill_buf() {
+                                Ok(_) => true,
+                                Err(err) if request::is_timeout(&err) => false,
+                                Err(err) => return Err(err),
+                            };
+                            timeouts.set_read_timeout(config.parse.read_timeout)?;
+
+                            let close = sent_body || demands_close;
+                            let mut res = Response::builder().status(status);
+                            if close {
+                                res = res.header(header::CONNECTION, "close");
+                            }
+                            let res = res.body(()).unwrap();
+                            response::write_response(res, &mut writer, true, &config.write)?;
+                            config.stats.request_served(reused);
+                            writer.flush()?;
+
+                            if close {
+                                break;
+                            }
+                            reused = true;
+                            continue;
+                        }
+                    };
+                }
+
+                let requested_upgrades = upgrade::requested_protocols(&req);
+
+                #[cfg(feature = "compression")]
+                let accepts_gzip =
+                    config.compression.is_some() && compress::accepts_gzip(req.headers());
+
+                let hints =
+                    EarlyHints::new(writer.get_ref().clone(), version, config.write.clone());
+                req.extensions_mut().insert(hints.clone());
+
+                let res = app.call(req);
+                hints.finish();
+                let mut res = res.map_err(io::Error::other)?;
+
+                *res.version_mut() = version;
+
+                // Applied before the server's own decisions, which can't be overridden
+                if let Some(control) = res.extensions_mut().remove::<ConnectionControl>() {
+                    control.apply(res.headers_mut());
+                }
+
+                if version == Version::HTTP_10 && !asks_for_keep_alive {
+                    res.headers_mut()
+                        .insert("connection", HeaderValue::from_static("close"));
+                }
+
+                if res.headers().typed_get::<headers::Date>().is_none() {
+                    res.headers_mut()
+                        .typed_insert(headers::Date::from(SystemTime::now()));
+                }
+
+                if let Some(ref server_name) = config.server_name {
+                    if !res.headers().contains_key(header::SERVER) {
+                        res.headers_mut()
+                            .insert(header::SERVER, server_name.clone());
+                    }
+                }
+
+                if res.extensions().get::<UpgradeExtension>().is_none() {
+                    if last_allowed {
+                        ConnectionControl::Close.apply(res.headers_mut());
+                    } else if !demands_close {
+                        keep_alive_hint(&mut res, config, requests);
+                    }
+                }
+
+                if !config.response_hooks.is_empty() {
+                    let (mut parts, body) = res.into_parts();
+                    for hook in &config.response_hooks {
+                        hook(&mut parts);
+                    }
+                    res = Response::from_parts(parts, body);
+                }
+
+                if res.status() == StatusCode::SWITCHING_PROTOCOLS
+                    && res.extensions().get::<UpgradeExtension>().is_some()
+                {
+                    if let Err(err) = upgrade::validate(&requested_upgrades, res.headers_mut()) {
+                        // The upgrade handler is dropped along with the response
+                        drop(res);
+                        let res = Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .header(header::CONNECTION, "close")
+                            .body(())
+                            .unwrap();
+                        response::write_response(res, &mut writer, true, &config.write)?;
+                        config.stats.request_served(reused);
+                        writer.flush()?;
+                        return Err(err);
+                    }
+                }
+
+                let should_write_body = match method {
+                    Method::HEAD => false,
+                    Method::CONNECT => res.status().is_success(),
+                    _ => true,
+                };
+
+                #[cfg(feature = "compression")]
+                let (outcome, _) = match config.compression {
+                    Some(ref compression) if accepts_gzip && compression.applies(&res) => {
+                        let res = compression.compress(res);
+                        response::write_response(
+                            res,
+                            &mut writer,
+                            should_write_body,
+                            &config.write,
+                        )?
+                    }
+                    _ => response::write_response(
+                        res,
+                        &mut writer,
+                        should_write_body,
+                        &config.write,
+                    )?,
+                };
+                #[cfg(not(feature = "compression"))]
+                let (outcome, _) =
+                    response::write_response(res, &mut writer, should_write_body, &config.write)?;
+                config.stats.request_served(reused);
+                reused = true;
+
+                match outcome {
+                    Outcome::KeepAlive if demands_close => break,
+                    Outcome::KeepAlive => {
+                        writer.flush()?;
+                        // The request body is drained once dropped. If that failed, the rest of
+                        // it would be taken for the next request.
+                        reader.wait();
+                        if !body_end.is_reached() {
+                            break;
+                        }
+                    }
+                    Outcome::Close => break,
+                    Outcome::Upgrade(upgrade) => {
+                        drop(reader);
+                        drop(read_queue);
+                        upgrade.handler.handle(writer.into_inner()?);
+                        return Ok(());
+                    }
+                }
+            }
+            Err(ParseError::ConnectionClosed) => break,
+            Err(err) => {
+                if let Some(protocol_error) = err.protocol_error() {
+                    if let Some(ref on_protocol_error) = config.on_protocol_error {
+                        on_protocol_error(&protocol_error);
+                    }
+
+                    let res = Response::builder()
+                        .status(protocol_error.status())
+                        .header("connection", "close")
+                        .body(())
+                        .unwrap();
+                    response::write_response(res, &mut writer, true, &config.write)?;
+                    config.stats.request_served(reused);
+                    writer.flush()?;
+                }
+
+                return Err(io::Error::other(err));
+            }
+        }
+    }
+
+    // Make sure the whole response reaches the client before closing the connection, even when
+    // it already closed its writing side.
+    writer.flush()?;
+    writer.get_ref().shutdown(Shutdown::Write).ok();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        io::{BufRead, Read, Write},
+        net::TcpStream,
+        sync::mpsc,
+        thread,
+        time::Instant,
+    };
+
+    use crate::{proxy::ClientIp, ConnectionInfo};
+
+    use super::*;
+
+    fn send_raw_request(builder: ServerBuilder, req: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            builder
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(|_req| Response::builder().body("lolwut"))
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(req).unwrap();
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn answers_protocol_errors_with_the_proper_status() {
+        let res = send_raw_request(Server::builder(), b"GET / HTTP/1.1\r\nHost lol.com\r\n\r\n");
+        assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        let res = send_raw_request(
+            Server::builder(),
+            b"GET / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n",
+        );
+        assert!(res.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "body read buffer size must be greater than zero")]
+    fn rejects_empty_body_read_buffers() {
+        Server::builder().body_read_buffer_size(0);
+    }
+
+    #[test]
+    fn writes_the_full_response_to_half_closed_clients() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::from(listener)
+                .serve(|_req| {
+                    Response::builder()
+                        .header("connection", "close")
+                        .body(Body::from_reader(io::repeat(b'a').take(64 * 1024), None))
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        conn.shutdown(Shutdown::Write).unwrap();
+
+        let mut res = Vec::new();
+        conn.read_to_end(&mut res).unwrap();
+
+        assert!(res.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        assert!(res.ends_with(&[b'a'; 64 * 1024]));
+    }
+
+    #[derive(Clone)]
+    struct UploadService;
+
+    impl Service for UploadService {
+        type Body = &'static str;
+        type Error = Infallible;
+
+        fn call(&self, req: Request<Body>) -> Result<Response<Self::Body>, Self::Error> {
+            let body = req.into_body().into_bytes().unwrap();
+            let status = if body == b"lolwut" {
+                StatusCode::OK
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            Ok(Response::builder().status(status).body("ok").unwrap())
+        }
+
+        fn should_continue(&self, req: &Request<Body>) -> StatusCode {
+            match req.headers().typed_get::<headers::ContentLength>() {
+                Some(len) if len.0 <= 1024 => StatusCode::CONTINUE,
+                _ => StatusCode::PAYLOAD_TOO_LARGE,
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_connections_alive_after_rejecting_continue_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .max_threads(1)
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(UploadService)
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        // Rejected without the client ever sending the body
+        conn.write_all(b"POST / HTTP/1.1\r\nexpect: 100-continue\r\ncontent-length: 4096\r\n\r\n")
+            .unwrap();
+
+        let mut res = Vec::new();
+        let mut byte = [0_u8];
+        while !res.ends_with(b"\r\n\r\n") {
+            conn.read_exact(&mut byte).unwrap();
+            res.push(byte[0]);
+        }
+        let res = String::from_utf8(res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        assert!(!res.contains("connection: close"));
+
+        // The connection is reused for the next request
+        conn.write_all(b"POST / HTTP/1.1\r\ncontent-length: 6\r\nconnection: close\r\n\r\nlolwut")
+            .unwrap();
 
-                let asks_for_close = req
-                    .headers()
-                    .typed_get::<headers::Connection>()
-                    .filter(|conn| conn.contains("close"))
-                    .is_some();
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
 
-                let asks_for_keep_alive = req
-                    .headers()
-                    .typed_get::<headers::Connection>()
-                    .filter(|conn| conn.contains("keep-alive"))
-                    .is_some();
+    #[test]
+    fn closes_connections_after_rejecting_continue_requests_with_bodies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
 
-                let version = req.version();
-                let method = req.method().clone();
+        thread::spawn(move || {
+            Server::builder()
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(UploadService)
+                .ok()
+        });
 
-                let demands_close = match version {
-                    Version::HTTP_09 => true,
-                    Version::HTTP_10 => !asks_for_keep_alive,
-                    _ => asks_for_close,
-                };
+        // Clients may send the body anyway, which must not be read as another request
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let smuggled = b"GET /smuggled HTTP/1.1\r\nhost: lol.com\r\n\r\n";
+        let mut req = format!(
+            "POST / HTTP/1.1\r\nexpect: 100-continue\r\ncontent-length: {}\r\n\r\n",
+            2048 + smuggled.len()
+        )
+        .into_bytes();
+        req.extend_from_slice(smuggled);
+        req.extend_from_slice(&[b'x'; 2048]);
+        conn.write_all(&req).unwrap();
 
-                let expects_continue = req
-                    .headers()
-                    .typed_get::<headers::Expect>()
-                    .filter(|expect| expect == &headers::Expect::CONTINUE)
-                    .is_some();
+        let mut res = String::new();
+        conn.read_to_string(&mut res).ok();
+        assert!(res.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        assert_eq!(res.matches("HTTP/1.1").count(), 1);
+    }
 
-                if expects_continue {
-                    match app.should_continue(&req) {
-                        status @ StatusCode::CONTINUE => {
-                            let res = Response::builder().status(status).body(()).unwrap();
-                            response::write_response(res, &mut writer, true)?;
-                            writer.flush()?;
-                        }
-                        status => {
-                            let res = Response::builder().status(status).body(()).unwrap();
-                            response::write_response(res, &mut writer, true)?;
-                            writer.flush()?;
-                            continue;
-                        }
-                    };
-                }
+    #[test]
+    fn waits_for_continue_before_reading_small_bodies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
 
-                let mut res = app
-                    .call(req)
-                    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        thread::spawn(move || {
+            Server::builder()
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(UploadService)
+                .ok()
+        });
 
-                *res.version_mut() = version;
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
 
-                if version == Version::HTTP_10 && !asks_for_keep_alive {
-                    res.headers_mut()
-                        .insert("connection", HeaderValue::from_static("close"));
-                }
+        conn.write_all(b"POST / HTTP/1.1\r\nexpect: 100-continue\r\ncontent-length: 6\r\n\r\n")
+            .unwrap();
 
-                if res.headers().typed_get::<headers::Date>().is_none() {
-                    res.headers_mut()
-                        .typed_insert(headers::Date::from(SystemTime::now()));
-                }
+        let mut buf = [0_u8; 25];
+        conn.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"HTTP/1.1 100 Continue\r\n\r\n");
 
-                let should_write_body = match method {
-                    Method::HEAD => false,
-                    Method::CONNECT => res.status().is_success(),
-                    _ => true,
-                };
+        conn.write_all(b"lolwut").unwrap();
+        conn.shutdown(Shutdown::Write).unwrap();
 
-                match response::write_response(res, &mut writer, should_write_body)? {
-                    Outcome::KeepAlive if demands_close => break,
-                    Outcome::KeepAlive => writer.flush()?,
-                    Outcome::Close => break,
-                    Outcome::Upgrade(upgrade) => {
-                        drop(reader);
-                        drop(read_queue);
-                        upgrade.handler.handle(writer.into_inner()?);
-                        break;
-                    }
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn adds_the_server_header() {
+        let res = send_raw_request(
+            Server::builder().server_name("touche"),
+            b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n",
+        );
+        assert!(res.contains("server: touche\r\n"));
+
+        let res = send_raw_request(
+            Server::builder(),
+            b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n",
+        );
+        assert!(!res.contains("server:"));
+    }
+
+    #[test]
+    fn runs_response_hooks_in_order() {
+        let builder = Server::builder()
+            .map_response(|res| {
+                res.headers.insert("x-lol", HeaderValue::from_static("lol"));
+            })
+            .map_response(|res| {
+                let lol = res.headers.remove("x-lol").unwrap();
+                res.headers.insert("x-wut", lol);
+                res.status = StatusCode::ACCEPTED;
+            });
+
+        let res = send_raw_request(builder, b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n");
+        assert!(res.starts_with("HTTP/1.1 202 Accepted\r\n"));
+        assert!(res.contains("x-wut: lol\r\n"));
+        assert!(!res.contains("x-lol"));
+    }
+
+    #[test]
+    fn sends_early_hints_before_the_final_response() {
+        let app = |req: Request<Body>| {
+            let hints = req.extensions().get::<EarlyHints>().unwrap();
+
+            let mut headers = HeaderMap::new();
+            headers.insert(header::LINK, HeaderValue::from_static("</lol.css>"));
+            hints.send(headers).unwrap();
+            hints
+                .send_informational(StatusCode::PROCESSING, HeaderMap::new())
+                .unwrap();
+
+            let err = hints
+                .send_informational(StatusCode::OK, HeaderMap::new())
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+            Response::builder().body("lolwut")
+        };
+
+        let res = crate::test::raw(app, "GET / HTTP/1.1\r\n\r\n").unwrap();
+        let res = String::from_utf8(res).unwrap();
+        assert!(res.starts_with(
+            "HTTP/1.1 103 Early Hints\r\nlink: </lol.css>\r\n\r\nHTTP/1.1 102 Processing\r\n\r\nHTTP/1.1 200 OK\r\n"
+        ));
+        assert!(res.ends_with("lolwut"));
+
+        let res = crate::test::raw(app, "GET / HTTP/1.0\r\n\r\n").unwrap();
+        assert!(res.starts_with(b"HTTP/1.0 200 OK\r\n"));
+    }
+
+    #[test]
+    fn does_not_send_early_hints_after_the_final_response() {
+        let (tx, rx) = mpsc::channel();
+
+        let app = move |req: Request<Body>| {
+            let hints = req.extensions().get::<EarlyHints>().unwrap().clone();
+            tx.send(hints).unwrap();
+            Response::builder().body("lolwut")
+        };
+
+        let res = crate::test::raw(app, "GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(res.starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        let hints = rx.recv().unwrap();
+        assert!(hints.send(HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn serves_from_an_existing_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::from_listener(listener)
+                .serve(|_req| Response::builder().body("lolwut"))
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .unwrap();
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(res.ends_with("lolwut"));
+    }
+
+    #[test]
+    fn accepts_connections_on_multiple_workers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .workers(4)
+                .from_listener(listener)
+                .serve(|_req| Response::builder().body("lolwut"))
+                .ok()
+        });
+
+        let clients = (0..16)
+            .map(|_| {
+                thread::spawn(move || {
+                    let mut conn = TcpStream::connect(addr).unwrap();
+                    conn.write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+                        .unwrap();
+
+                    let mut res = String::new();
+                    conn.read_to_string(&mut res).unwrap();
+                    res
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for client in clients {
+            let res = client.join().unwrap();
+            assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(res.ends_with("lolwut"));
+        }
+    }
+
+    #[test]
+    fn closes_the_connection_when_the_body_fails() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .from_listener(listener)
+                .serve(|_req| {
+                    let chunks = vec![Ok("lol"), Err(io::Error::other("lolwut")), Ok("wut")];
+                    Response::builder().body(Body::from_results(chunks))
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n")
+            .unwrap();
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(res.ends_with("\r\n\r\n3\r\nlol\r\n"));
+        assert_eq!(res.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[test]
+    fn resets_the_connection_when_the_body_is_aborted() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .from_listener(listener)
+                .serve(|_req| {
+                    let (channel, body) = Body::channel();
+                    thread::spawn(move || {
+                        channel.send("lol").unwrap();
+                        thread::sleep(Duration::from_millis(50));
+                        channel.abort();
+                    });
+                    Response::builder().body(body)
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+        let mut res = Vec::new();
+        let err = conn.read_to_end(&mut res).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn counts_connections_and_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let server = Server::builder().from_listener(listener);
+            sender.send(server.stats()).unwrap();
+            server.serve(|_req| Response::builder().body("lolwut")).ok()
+        });
+        let stats = receiver.recv().unwrap();
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .unwrap();
+        conn.read_to_end(&mut Vec::new()).unwrap();
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .unwrap();
+        conn.read_to_end(&mut Vec::new()).unwrap();
+
+        // The connection may still be wrapping up after the client got the whole response
+        thread::sleep(Duration::from_millis(50));
+
+        assert_eq!(stats.connections_accepted(), 2);
+        assert_eq!(stats.active_connections(), 0);
+        assert_eq!(stats.requests_served(), 4);
+        assert_eq!(stats.keep_alive_reuses(), 2);
+    }
+
+    #[test]
+    fn shutdown_unblocks_the_accept_loops() {
+        for workers in [1, 4] {
+            let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+
+            let (sender, receiver) = mpsc::channel();
+            let server = thread::spawn(move || {
+                let server = Server::builder().workers(workers).from_listener(listener);
+                sender.send(server.shutdown_handle()).unwrap();
+                server.serve(|_req| Response::builder().body(())).is_ok()
+            });
+
+            let handle = receiver.recv().unwrap();
+            thread::sleep(Duration::from_millis(50));
+            assert!(!handle.is_shutdown());
+            handle.shutdown();
+
+            assert!(server.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn disconnects_clients_that_stop_reading() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let conn = Connection::from(listener.accept().unwrap());
+            conn.set_write_timeout(Some(Duration::from_millis(200)))
+                .unwrap();
+            let app = |_req| Response::builder().body(Body::from_reader(io::repeat(0), None));
+            tx.send(serve(conn, app, &Default::default())).unwrap();
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+        let err = rx
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap()
+            .unwrap_err();
+        assert!(request::is_timeout(&err));
+    }
+
+    #[test]
+    fn sends_files() {
+        let path = std::env::temp_dir().join(format!("touche-server-{}", std::process::id()));
+        let contents = "lolwut".repeat(1000);
+        std::fs::write(&path, &contents).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let file = path.clone();
+        thread::spawn(move || {
+            Server::from_listener(listener)
+                .serve(move |_req| {
+                    let file = std::fs::File::open(&file)?;
+                    Response::builder()
+                        .body(Body::try_from(file)?)
+                        .map_err(io::Error::other)
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .unwrap();
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.contains("content-length: 6000\r\n"));
+        assert!(res.ends_with(&format!("\r\n\r\n{contents}")));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn frames_pipelined_head_and_get_responses() {
+        let app = |req: Request<Body>| match req.uri().path() {
+            "/chunked" => Response::builder().body(Body::from_iter(["lol", "wut"])),
+            _ => Response::builder().body(Body::from("lolwut")),
+        };
+
+        let res = crate::test::raw(
+            app,
+            "HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\nHEAD /chunked HTTP/1.1\r\n\r\nGET /chunked HTTP/1.1\r\n\r\n",
+        )
+        .unwrap();
+        let res = String::from_utf8(res).unwrap();
+
+        let responses = res
+            .split("HTTP/1.1 ")
+            .skip(1)
+            .map(|res| {
+                let (head, body) = res.split_once("\r\n\r\n").unwrap();
+                let head = head
+                    .lines()
+                    .filter(|line| !line.starts_with("date:"))
+                    .collect::<Vec<_>>();
+                (head, body)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            responses,
+            vec![
+                (vec!["200 OK", "content-length: 6"], ""),
+                (vec!["200 OK", "content-length: 6"], "lolwut"),
+                (vec!["200 OK", "transfer-encoding: chunked"], ""),
+                (
+                    vec!["200 OK", "transfer-encoding: chunked"],
+                    "3\r\nlol\r\n3\r\nwut\r\n0\r\n\r\n"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn times_out_slow_request_heads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .read_timeout(Duration::from_secs(5))
+                .header_read_timeout(Duration::from_millis(200))
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(|_req| Response::builder().body("lolwut"))
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+
+        let mut writer = conn.try_clone().unwrap();
+        thread::spawn(move || {
+            for _ in 0..40 {
+                if writer.write_all(b"x").is_err() {
+                    break;
                 }
+                thread::sleep(Duration::from_millis(50));
             }
-            Err(ParseError::ConnectionClosed) => break,
-            Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err)),
+        });
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).ok();
+        assert!(res.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[test]
+    fn drains_unread_request_bodies_before_the_next_request() {
+        let chunk = "a".repeat(4096);
+        let req = format!(
+            "POST / HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n\
+             {len:x}\r\n{chunk}\r\n{len:x}\r\n{chunk}\r\n0\r\n\r\n\
+             POST / HTTP/1.1\r\ncontent-length: 4096\r\n\r\n{chunk}\
+             GET / HTTP/1.1\r\nconnection: close\r\n\r\n",
+            len = chunk.len(),
+        );
+
+        let res = send_raw_request(Server::builder(), req.as_bytes());
+
+        assert_eq!(res.matches("HTTP/1.1 200 OK\r\n").count(), 3);
+        assert!(!res.contains("400 Bad Request"));
+    }
+
+    #[test]
+    fn keeps_http_10_connections_alive_when_asked_to() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::from(listener)
+                .serve(|req: Request<_>| match req.uri().path() {
+                    "/streaming" => Response::builder().body(Body::once("lolwut")),
+                    _ => Response::builder().body(Body::from("lolwut")),
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+
+        conn.write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+            .unwrap();
+        let mut res = Vec::new();
+        while !res.ends_with(b"lolwut") {
+            let mut buf = [0_u8; 1024];
+            let read = conn.read(&mut buf).unwrap();
+            assert!(read > 0);
+            res.extend_from_slice(&buf[..read]);
         }
+        let res = String::from_utf8(res).unwrap();
+        assert!(res.contains("connection: keep-alive\r\n"));
+
+        conn.write_all(b"GET /streaming HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+            .unwrap();
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.contains("connection: close\r\n"));
+        assert!(res.ends_with("\r\n\r\nlolwut"));
     }
 
-    Ok(())
+    #[test]
+    fn exposes_the_connection_info_to_handlers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::from(listener)
+                .serve(|req: Request<_>| {
+                    let info = req.extensions().get::<ConnectionInfo>().unwrap();
+                    assert!(!info.is_tls());
+                    assert_eq!(
+                        req.extensions().get::<SocketAddr>(),
+                        info.peer_addr().as_ref()
+                    );
+                    Response::builder()
+                        .header("connection", "close")
+                        .body(format!("{}", info.peer_addr().unwrap()))
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.ends_with(&conn.local_addr().unwrap().to_string()));
+    }
+
+    #[test]
+    fn exposes_the_client_ip_from_trusted_proxies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .trusted_proxies(["127.0.0.1".parse().unwrap()])
+                .from_listener(listener)
+                .serve(|req: Request<_>| {
+                    let ClientIp(ip) = req.extensions().get::<ClientIp>().unwrap();
+                    Response::builder().body(ip.to_string())
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(
+            b"GET / HTTP/1.1\r\nx-forwarded-for: 203.0.113.1\r\nconnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.ends_with("\r\n\r\n203.0.113.1"));
+    }
+
+    #[test]
+    fn calls_the_protocol_error_callback() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let builder = Server::builder().on_protocol_error(move |err| {
+            tx.send(err.clone()).unwrap();
+        });
+
+        send_raw_request(builder, b"GET / HTTP/1.1\r\nHost lol.com\r\n\r\n");
+
+        assert_eq!(rx.recv().unwrap(), ProtocolError::InvalidHeader);
+    }
+
+    #[test]
+    #[cfg(feature = "rustls")]
+    fn calls_the_tls_error_callback() {
+        use rustls::{Certificate, PrivateKey, ServerConfig};
+
+        let read_pem = |path| {
+            let file = std::fs::File::open(path).unwrap();
+            rustls_pemfile::read_all(&mut io::BufReader::new(file)).unwrap()
+        };
+        let certs = read_pem("examples/tls/cert.pem")
+            .into_iter()
+            .filter_map(|item| match item {
+                rustls_pemfile::Item::X509Certificate(cert) => Some(Certificate(cert)),
+                _ => None,
+            })
+            .collect();
+        let key = read_pem("examples/tls/key.pem")
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) => {
+                    Some(PrivateKey(key))
+                }
+                _ => None,
+            })
+            .unwrap();
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        thread::spawn(move || {
+            Server::builder()
+                .on_tls_error(move |err, peer_addr| {
+                    let tls_error = err
+                        .get_ref()
+                        .and_then(|err| err.downcast_ref::<rustls::Error>())
+                        .cloned();
+                    tx.send((tls_error, peer_addr)).unwrap();
+                })
+                .from_connections(TlsAcceptor {
+                    acceptor: TcpAcceptor::new(listener),
+                    config: Arc::new(config),
+                })
+                .serve(|_req| Response::builder().body("lolwut"))
+                .ok()
+        });
+
+        // Speaking plain HTTP to a TLS server fails the handshake
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\nHost: lol.com\r\n\r\n")
+            .unwrap();
+
+        let (tls_error, peer_addr) = rx.recv().unwrap();
+        assert!(tls_error.is_some());
+        assert_eq!(peer_addr, Some(conn.local_addr().unwrap()));
+    }
+
+    #[test]
+    fn listens_with_the_configured_backlog() {
+        let listener = Server::builder()
+            .backlog(u32::MAX)
+            .bind_listener("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Connections are queued until accepted
+        let clients = (0..4)
+            .map(|_| TcpStream::connect(addr).unwrap())
+            .collect::<Vec<_>>();
+        for _ in &clients {
+            listener.accept().unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    fn binds_several_servers_to_the_same_port_with_reuse_port() {
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let listener = Server::builder()
+            .reuse_port(true)
+            .bind_listener(addr)
+            .unwrap();
+
+        let addr = listener.local_addr().unwrap();
+
+        assert!(Server::builder().reuse_port(true).try_bind(addr).is_ok());
+        assert!(Server::builder().try_bind(addr).is_err());
+    }
+
+    #[test]
+    fn writes_responses_to_any_writer() {
+        let res = Response::builder()
+            .header("x-lol", "wut")
+            .body(Body::from_reader(
+                std::io::Cursor::new(b"lolwut".to_vec()),
+                None,
+            ))
+            .unwrap();
+
+        let mut buf = Vec::new();
+        let written = write_response(res, &mut buf).unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "HTTP/1.1 200 OK\r\nx-lol: wut\r\ntransfer-encoding: chunked\r\n\r\n6\r\nlolwut\r\n0\r\n\r\n"
+        );
+        assert_eq!(
+            written.head as usize + written.body_framed as usize,
+            buf.len()
+        );
+        assert_eq!(written.body, 6);
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn compresses_responses_for_clients_accepting_gzip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .compression(CompressionConfig::new().min_size(0))
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(|_req| {
+                    Response::builder()
+                        .header("content-type", "text/plain")
+                        .body("lolwut")
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\naccept-encoding: gzip\r\n\r\n")
+            .unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .unwrap();
+
+        let mut res = Vec::new();
+        conn.read_to_end(&mut res).unwrap();
+        let res = String::from_utf8_lossy(&res);
+
+        let (compressed, plain) = res.split_at(res.rfind("HTTP/1.1 200 OK").unwrap());
+        assert!(compressed.contains("content-encoding: gzip\r\n"));
+        assert!(compressed.contains("vary: accept-encoding\r\n"));
+        assert!(compressed.contains("transfer-encoding: chunked\r\n"));
+        assert!(!plain.contains("content-encoding"));
+        assert!(plain.ends_with("\r\n\r\nlolwut"));
+    }
+
+    #[test]
+    fn echoes_chunked_uploads_as_they_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(|req: Request<Body>| Response::builder().body(req.into_body()))
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.write_all(b"POST / HTTP/1.1\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n3\r\nlol\r\n")
+            .unwrap();
+
+        // The first chunk comes back before the upload is over
+        let mut reader = BufReader::new(conn.try_clone().unwrap());
+        let mut head = String::new();
+        while !head.ends_with("\r\n\r\n") {
+            reader.read_line(&mut head).unwrap();
+        }
+        assert!(head.contains("transfer-encoding: chunked\r\n"));
+        let mut chunk = [0; 8];
+        reader.read_exact(&mut chunk).unwrap();
+        assert_eq!(&chunk, b"3\r\nlol\r\n");
+
+        conn.write_all(b"3\r\nwut\r\n0\r\n\r\n").unwrap();
+
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "3\r\nwut\r\n0\r\n\r\n");
+    }
+
+    /// Serves pipelined requests with a handler that does `consume` with each request body and
+    /// answers with the method and path of the request.
+    fn serve_pipelined(consume: fn(Body), req: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .max_threads(1)
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(move |req: Request<Body>| {
+                    let served = format!("served {} {}", req.method(), req.uri());
+                    consume(req.into_body());
+                    Response::builder().body(served)
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        conn.write_all(req).unwrap();
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).ok();
+        res
+    }
+
+    #[test]
+    fn drains_partially_read_bodies_before_the_next_request() {
+        let mut req = b"POST / HTTP/1.1\r\ncontent-length: 4096\r\n\r\n".to_vec();
+        req.extend_from_slice(&[b'a'; 4096]);
+        req.extend_from_slice(b"GET /next HTTP/1.1\r\nconnection: close\r\n\r\n");
+
+        let res = serve_pipelined(
+            |body| {
+                let mut buf = Vec::new();
+                body.into_reader().take(10).read_to_end(&mut buf).unwrap();
+            },
+            &req,
+        );
+        assert!(res.contains("served POST /"));
+        assert!(res.ends_with("served GET /next"));
+    }
+
+    #[test]
+    fn drains_limited_bodies_before_the_next_request() {
+        let mut req = b"POST / HTTP/1.1\r\ncontent-length: 4096\r\n\r\n".to_vec();
+        req.extend_from_slice(&[b'a'; 4096]);
+        req.extend_from_slice(b"GET /next HTTP/1.1\r\nconnection: close\r\n\r\n");
+
+        let consumers: [fn(Body); 2] = [
+            |body| drop(body.take(10)),
+            |body| {
+                body.take(10).into_bytes().unwrap();
+            },
+        ];
+        for consume in consumers {
+            let res = serve_pipelined(consume, &req);
+            assert!(res.contains("served POST /"));
+            assert!(res.ends_with("served GET /next"));
+        }
+    }
+
+    #[test]
+    fn drains_dropped_bodies_with_trailers_before_the_next_request() {
+        let mut req = b"POST / HTTP/1.1\r\ncontent-length: 4096\r\n\r\n".to_vec();
+        req.extend_from_slice(&[b'a'; 4096]);
+        req.extend_from_slice(b"GET /next HTTP/1.1\r\nconnection: close\r\n\r\n");
+
+        let res = serve_pipelined(|body| drop(body.with_trailers(HeaderMap::new())), &req);
+        assert!(res.contains("served POST /"));
+        assert!(res.ends_with("served GET /next"));
+    }
+
+    #[test]
+    fn does_not_throttle_dropped_bodies() {
+        let mut req = b"POST / HTTP/1.1\r\ncontent-length: 3000\r\n\r\n".to_vec();
+        req.extend_from_slice(&[b'a'; 3000]);
+        req.extend_from_slice(b"GET /next HTTP/1.1\r\nconnection: close\r\n\r\n");
+
+        let start = Instant::now();
+        let res = serve_pipelined(|body| drop(body.throttle(1000)), &req);
+        assert!(res.contains("served POST /"));
+        assert!(res.ends_with("served GET /next"));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .max_threads(1)
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(|_req| Response::builder().body(Body::from(vec![0; 3000]).throttle(1000)))
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let start = Instant::now();
+        conn.write_all(b"HEAD / HTTP/1.1\r\nconnection: close\r\n\r\n")
+            .unwrap();
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn answers_incoherent_upgrades_with_server_errors() {
+        use crate::upgrade::Upgrade;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (upgraded, upgrades) = mpsc::channel();
+
+        thread::spawn(move || {
+            Server::builder()
+                .max_threads(1)
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(move |_req| {
+                    let upgraded = upgraded.clone();
+                    Response::builder()
+                        .status(StatusCode::SWITCHING_PROTOCOLS)
+                        .header(header::UPGRADE, "lolwut")
+                        .upgrade(move |_conn: Connection| upgraded.send(()).unwrap())
+                        .body("")
+                })
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\nconnection: upgrade\r\nupgrade: websocket\r\n\r\n")
+            .unwrap();
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(res.contains("\r\nconnection: close\r\n"));
+        assert!(upgrades.try_recv().is_err());
+    }
+
+    #[test]
+    fn keeps_closing_connections_the_server_must_close() {
+        let serve = |builder: ServerBuilder, req: &'static [u8]| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            thread::spawn(move || {
+                builder
+                    .max_threads(1)
+                    .from_connections(TcpAcceptor::new(listener))
+                    .serve(|_req| {
+                        Response::builder()
+                            .extension(ConnectionControl::KeepAlive)
+                            .body("lolwut")
+                    })
+                    .ok()
+            });
+
+            let mut conn = TcpStream::connect(addr).unwrap();
+            conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            conn.write_all(req).unwrap();
+
+            let mut res = String::new();
+            conn.read_to_string(&mut res).unwrap();
+            res
+        };
+
+        // The last request allowed on the connection
+        let res = serve(
+            Server::builder().max_requests_per_connection(1),
+            b"GET / HTTP/1.1\r\n\r\n",
+        );
+        assert!(res.contains("\r\nconnection: close\r\n"));
+
+        // HTTP/1.0 clients that didn't ask for keep-alive
+        let res = serve(Server::builder(), b"GET / HTTP/1.0\r\n\r\n");
+        assert!(res.contains("\r\nconnection: close\r\n"));
+        assert!(!res.contains("keep-alive"));
+    }
+
+    #[test]
+    fn closes_connections_with_broken_chunked_framing() {
+        let req = b"POST / HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n\
+                    3\r\nabcXX\r\n0\r\n\r\n\
+                    GET /smuggled HTTP/1.1\r\n\r\n";
+
+        let consumers: [fn(Body); 2] = [drop, |body| assert!(body.into_bytes().is_err())];
+        for consume in consumers {
+            let res = serve_pipelined(consume, req);
+            assert!(res.contains("served POST /"));
+            assert!(!res.contains("smuggled"));
+        }
+    }
+
+    #[test]
+    fn writes_responses_with_explicit_framing() {
+        let write = |res: Response<Body>, encoding| {
+            let mut buf = Vec::new();
+            write_response_with(res, encoding, &mut buf).map(|_| String::from_utf8(buf).unwrap())
+        };
+
+        // Chunked even though the length is known
+        let res = Response::builder().body(Body::from("lolwut")).unwrap();
+        assert_eq!(
+            write(res, Encoding::Chunked).unwrap(),
+            "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n6\r\nlolwut\r\n0\r\n\r\n"
+        );
+
+        let res = Response::builder()
+            .header("content-length", "6")
+            .body(Body::from("lolwut"))
+            .unwrap();
+        assert_eq!(
+            write(res, Encoding::CloseDelimited).unwrap(),
+            "HTTP/1.1 200 OK\r\nconnection: close\r\n\r\nlolwut"
+        );
+
+        let res = Response::builder()
+            .body(Body::from_iter(["lol", "wut"]))
+            .unwrap();
+        assert!(write(res, Encoding::FixedLength(3))
+            .unwrap()
+            .ends_with("content-length: 3\r\n\r\nlol"));
+
+        let res = Response::builder().body(Body::from("lolwut")).unwrap();
+        assert!(write(res, Encoding::FixedLength(3)).is_err());
+
+        let res = Response::builder()
+            .version(Version::HTTP_10)
+            .body(Body::from("lolwut"))
+            .unwrap();
+        assert!(write(res, Encoding::Chunked).is_err());
+    }
+
+    #[test]
+    fn hints_and_enforces_keep_alive_limits() {
+        let builder = Server::builder()
+            .keep_alive_timeout(Duration::from_secs(5))
+            .max_requests_per_connection(2);
+
+        let res = send_raw_request(
+            builder,
+            b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n",
+        );
+
+        let responses = res.matches("HTTP/1.1 200 OK").count();
+        assert_eq!(responses, 2);
+        let (first, last) = res.split_at(res.rfind("HTTP/1.1 200 OK").unwrap());
+        assert!(first.contains("keep-alive: timeout=5, max=1\r\n"));
+        assert!(last.contains("connection: close\r\n"));
+        assert!(!last.contains("keep-alive"));
+    }
+
+    #[test]
+    fn closes_idle_kept_alive_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            Server::builder()
+                .keep_alive_timeout(Duration::from_millis(100))
+                .from_connections(TcpAcceptor::new(listener))
+                .serve(|_req| Response::builder().body("lolwut"))
+                .ok()
+        });
+
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        conn.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+        // The server closes the connection once idle, instead of the client timing out
+        let mut res = String::new();
+        conn.read_to_string(&mut res).unwrap();
+        assert!(res.contains("keep-alive: timeout=1\r\n"));
+        assert!(res.ends_with("lolwut"));
+    }
+
+    #[test]
+    fn answers_head_requests_as_get() {
+        let app = head_as_get(|req: Request<Body>| match req.method() {
+            &Method::GET => Response::builder()
+                .header("etag", "\"lol\"")
+                .body(Body::from("lolwut")),
+            _ => Response::builder().body(Body::empty()),
+        });
+
+        let res = crate::test::raw(app, "HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n").unwrap();
+        let res = String::from_utf8(res).unwrap();
+
+        let heads = res
+            .split("HTTP/1.1 ")
+            .skip(1)
+            .map(|res| {
+                let (head, _) = res.split_once("\r\n\r\n").unwrap();
+                head.lines()
+                    .filter(|line| !line.starts_with("date:"))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(heads[0], ["200 OK", "etag: \"lol\"", "content-length: 6"]);
+        assert_eq!(heads[0], heads[1]);
+        assert!(res.ends_with("\r\n\r\nlolwut"));
+        assert_eq!(res.matches("lolwut").count(), 1);
+    }
 }
@@ -0,0 +1,215 @@
+//! Helpers to test [`Service`]s without binding real sockets.
+//!
+//! Requests are written to an in-memory [`Connection`], which is then served on the current thread
+//! exactly as the [`Server`](crate::Server) would, so the whole request/response pipeline
+//! (parsing, chunked encoding, upgrades, etc.) is exercised.
+//!
+//! # Example
+//! ```
+//! use touche::{test, Body, HttpBody, Request, Response, StatusCode};
+//!
+//! let app = |req: Request<Body>| {
+//!     Response::builder()
+//!         .status(StatusCode::OK)
+//!         .body(format!("Hello {}", req.uri().path()))
+//! };
+//!
+//! let req = Request::get("/world").body(Body::empty()).unwrap();
+//! let res = test::oneshot(app, req).unwrap();
+//!
+//! assert_eq!(res.status(), StatusCode::OK);
+//! assert_eq!(res.into_body().into_bytes().unwrap(), b"Hello /world");
+//!
+//! let res = test::raw(app, "GET /raw HTTP/1.1\r\n\r\n").unwrap();
+//! assert!(res.starts_with(b"HTTP/1.1 200 OK\r\n"));
+//! assert!(res.ends_with(b"\r\n\r\nHello /raw"));
+//! ```
+use std::{
+    io::{self, Cursor, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+use http::{Request, Response, StatusCode};
+
+use crate::{request, response, server::Service, Body, HttpBody};
+
+/// Serves the raw `request` bytes with `service`, returning everything the server wrote back.
+///
+/// The connection is closed once all the bytes are read, so multiple pipelined requests can be
+/// sent at once. Note that upgrade handlers get the connection after the request was read, so
+/// bytes following an upgrade request are not available to them.
+pub fn raw<S: Service>(service: S, request: impl Into<Vec<u8>>) -> io::Result<Vec<u8>> {
+    let stream = MemoryStream::new(request.into());
+    crate::server::serve(stream.clone(), service, &Default::default())?;
+    Ok(stream.into_output())
+}
+
+/// Sends a single `req` to `service`, returning the parsed response.
+///
+/// Interim `1xx` responses are skipped. The body of upgraded responses (`101 Switching Protocols`
+/// or successful `CONNECT` requests) contains whatever the upgrade handler wrote to the
+/// connection.
+pub fn oneshot<S, B>(service: S, req: Request<B>) -> io::Result<Response<Body>>
+where
+    S: Service,
+    B: HttpBody,
+{
+    let is_connect = req.method() == http::Method::CONNECT;
+
+    let mut input = Vec::new();
+    request::write_request(req, &mut input)?;
+
+    let mut output = raw(service, input)?;
+
+    // Skip the interim responses, such as `100 Continue`
+    loop {
+        let head = head_len(&output).ok_or(io::ErrorKind::UnexpectedEof)?;
+        let status = status(&output[..head])?;
+
+        if status.is_informational() && status != StatusCode::SWITCHING_PROTOCOLS {
+            output.drain(..head);
+            continue;
+        }
+
+        let upgraded =
+            status == StatusCode::SWITCHING_PROTOCOLS || (is_connect && status.is_success());
+
+        // Upgraded responses have no framing, so everything after the head is returned as is
+        let body = upgraded.then(|| output.split_off(head));
+        let res = response::parse_response(Cursor::new(output)).map_err(io::Error::other)?;
+
+        return Ok(match body {
+            Some(body) => res.map(|_| Body::from(body)),
+            None => res,
+        });
+    }
+}
+
+fn status(head: &[u8]) -> io::Result<StatusCode> {
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut res = httparse::Response::new(&mut headers);
+    res.parse(head).map_err(io::Error::other)?;
+    res.code
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| io::Error::other("invalid status code"))
+}
+
+fn head_len(output: &[u8]) -> Option<usize> {
+    output
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+/// In-memory stream, reading from a fixed input and recording everything written to it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MemoryStream {
+    input: Arc<Mutex<Cursor<Vec<u8>>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MemoryStream {
+    pub(crate) fn new(input: Vec<u8>) -> Self {
+        Self {
+            input: Arc::new(Mutex::new(Cursor::new(input))),
+            output: Default::default(),
+        }
+    }
+
+    pub(crate) fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let input = self.input.lock().unwrap();
+        let remaining = &input.get_ref()[input.position() as usize..];
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        Ok(len)
+    }
+
+    fn into_output(self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.lock().unwrap().read(buf)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{upgrade::Upgrade, Connection};
+
+    use super::*;
+
+    #[test]
+    fn serves_raw_requests() {
+        let app = |_req: Request<Body>| Response::builder().body("lolwut");
+
+        let res = raw(app, "GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n").unwrap();
+        let res = String::from_utf8(res).unwrap();
+
+        assert_eq!(res.matches("HTTP/1.1 200 OK\r\n").count(), 2);
+        assert!(res.ends_with("\r\n\r\nlolwut"));
+    }
+
+    #[test]
+    fn sends_requests_with_streaming_bodies() {
+        let app = |req: Request<Body>| {
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::from_iter(req.into_body().into_bytes().ok()))
+        };
+
+        let req = Request::post("/")
+            .body(Body::from_iter(vec!["lol", "wut"]))
+            .unwrap();
+        let res = oneshot(app, req).unwrap();
+
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers()["transfer-encoding"], "chunked");
+        assert_eq!(res.into_body().into_bytes().unwrap(), b"lolwut");
+    }
+
+    #[test]
+    fn returns_what_upgrade_handlers_write() {
+        let app = |_req: Request<Body>| {
+            Response::builder()
+                .status(StatusCode::SWITCHING_PROTOCOLS)
+                .header("upgrade", "echo")
+                .upgrade(|mut conn: Connection| {
+                    conn.write_all(b"lolwut").unwrap();
+                })
+                .body(Body::empty())
+        };
+
+        let res = raw(
+            app,
+            "GET / HTTP/1.1\r\nconnection: upgrade\r\nupgrade: echo\r\n\r\n",
+        )
+        .unwrap();
+        assert!(res.ends_with(b"\r\n\r\nlolwut"));
+
+        let req = Request::get("/")
+            .header("connection", "upgrade")
+            .header("upgrade", "echo")
+            .body(Body::empty())
+            .unwrap();
+        let res = oneshot(app, req).unwrap();
+
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(res.headers()["upgrade"], "echo");
+        assert_eq!(res.into_body().into_bytes().unwrap(), b"lolwut");
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     io::{self, Read, Write},
-    net::{SocketAddr, TcpStream},
+    net::{Shutdown, SocketAddr, TcpStream},
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -17,6 +17,52 @@ impl RustlsConnection {
         Ok(())
     }
 
+    pub(crate) fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let stream = self.0.lock().unwrap();
+        stream.get_ref().set_write_timeout(timeout)
+    }
+
+    pub(crate) fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        let stream = self.0.lock().unwrap();
+        stream.get_ref().set_nodelay(nodelay)
+    }
+
+    pub(crate) fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let mut stream = self.0.lock().unwrap();
+        if how != Shutdown::Read {
+            stream.conn.send_close_notify();
+            stream.flush()?;
+        }
+        stream.sock.shutdown(how)
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn reset(&self) -> io::Result<()> {
+        let stream = self.0.lock().unwrap();
+        socket2::SockRef::from(stream.get_ref()).set_linger(Some(Duration::ZERO))
+    }
+
+    /// Drives the TLS handshake to completion, so the negotiated parameters (such as the SNI
+    /// hostname) are known before any request is read.
+    pub(crate) fn complete_handshake(&self) -> io::Result<()> {
+        let mut stream = self.0.lock().unwrap();
+        let StreamOwned { conn, sock } = &mut *stream;
+        while conn.is_handshaking() {
+            conn.complete_io(sock)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn server_name(&self) -> Option<String> {
+        let stream = self.0.lock().ok()?;
+        stream.conn.sni_hostname().map(|name| name.to_string())
+    }
+
+    pub(crate) fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        let stream = self.0.lock().ok()?;
+        stream.conn.alpn_protocol().map(|proto| proto.to_vec())
+    }
+
     pub(crate) fn into_inner(self) -> Result<StreamOwned<ServerConnection, TcpStream>, Self> {
         match Arc::try_unwrap(self.0) {
             Ok(conn) => Ok(conn.into_inner().unwrap()),
@@ -35,7 +81,7 @@ impl RustlsConnection {
     pub fn peer_addr(&self) -> io::Result<SocketAddr> {
         self.0
             .lock()
-            .map_err(|_err| io::Error::new(io::ErrorKind::Other, "Failed to aquire lock"))?
+            .map_err(|_err| io::Error::other("Failed to aquire lock"))?
             .sock
             .peer_addr()
     }
@@ -43,7 +89,7 @@ impl RustlsConnection {
     pub fn local_addr(&self) -> io::Result<SocketAddr> {
         self.0
             .lock()
-            .map_err(|_err| io::Error::new(io::ErrorKind::Other, "Failed to aquire lock"))?
+            .map_err(|_err| io::Error::other("Failed to aquire lock"))?
             .sock
             .local_addr()
     }
@@ -53,7 +99,7 @@ impl Read for RustlsConnection {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         self.0
             .lock()
-            .map_err(|_err| io::Error::new(io::ErrorKind::Other, "Failed to aquire lock"))?
+            .map_err(|_err| io::Error::other("Failed to aquire lock"))?
             .read(buf)
     }
 }
@@ -62,14 +108,14 @@ impl Write for RustlsConnection {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.0
             .lock()
-            .map_err(|_err| io::Error::new(io::ErrorKind::Other, "Failed to aquire lock"))?
+            .map_err(|_err| io::Error::other("Failed to aquire lock"))?
             .write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
         self.0
             .lock()
-            .map_err(|_err| io::Error::new(io::ErrorKind::Other, "Failed to aquire lock"))?
+            .map_err(|_err| io::Error::other("Failed to aquire lock"))?
             .flush()
     }
 }
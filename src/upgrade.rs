@@ -1,3 +1,4 @@
+use http::{header, HeaderMap, HeaderValue, Request, StatusCode, Version};
 use thiserror::Error;
 
 use crate::connection::Connection;
@@ -16,6 +17,41 @@ pub(crate) struct UpgradeExtension {
     pub(crate) handler: Box<dyn UpgradeHandler + 'static>,
 }
 
+/// Turns a response into an upgrade, handing the raw [`Connection`] to an [`UpgradeHandler`]
+/// after the response head is written.
+///
+/// Upgrade responses are never framed: no `Content-Length` or `Transfer-Encoding` headers and no
+/// body are written, so the new protocol starts right after the blank line that ends the head.
+/// This is also how `CONNECT` tunnels are implemented, by answering with a `2xx` response.
+///
+/// # Example
+/// ```no_run
+/// # use std::{io, net::TcpStream, thread};
+/// # use touche::{upgrade::Upgrade, Body, Connection, Method, Request, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(|req: Request<_>| {
+///     let address = match req.uri().authority() {
+///         Some(authority) if req.method() == Method::CONNECT => authority.to_string(),
+///         _ => {
+///             return Response::builder()
+///                 .status(StatusCode::BAD_REQUEST)
+///                 .body(Body::empty())
+///         }
+///     };
+///
+///     Response::builder()
+///         .status(StatusCode::OK)
+///         .upgrade(move |mut client: Connection| {
+///             let mut upstream = TcpStream::connect(&address).unwrap();
+///             let mut client_writer = client.clone();
+///             let mut upstream_reader = upstream.try_clone().unwrap();
+///             thread::spawn(move || io::copy(&mut upstream_reader, &mut client_writer));
+///             io::copy(&mut client, &mut upstream).ok();
+///         })
+///         .body(Body::empty())
+/// })
+/// # }
+/// ```
 pub trait Upgrade {
     fn upgrade(self, handle: impl UpgradeHandler + 'static) -> Self;
 }
@@ -37,6 +73,103 @@ impl<T> Upgrade for http::Response<T> {
     }
 }
 
+/// Returns the protocols a request asks to upgrade to, in the order they were listed on its
+/// `Upgrade` header.
+///
+/// The `Upgrade` header is only honored when `upgrade` is also listed on the `Connection` header,
+/// and never on HTTP/1.0 requests, so no protocols are returned in those cases.
+///
+/// # Example
+/// ```no_run
+/// # use touche::{upgrade::{self, Upgrade}, Body, Connection, Request, Response, Server, StatusCode};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+///     if !upgrade::requested_protocols(&req).iter().any(|proto| proto == "echo") {
+///         return upgrade::upgrade_required("echo").body(Body::empty());
+///     }
+///
+///     Response::builder()
+///         .status(StatusCode::SWITCHING_PROTOCOLS)
+///         .header("upgrade", "echo")
+///         .upgrade(|conn: Connection| {
+///             std::io::copy(&mut conn.clone(), &mut conn.clone()).ok();
+///         })
+///         .body(Body::empty())
+/// })
+/// # }
+/// ```
+pub fn requested_protocols<B>(req: &Request<B>) -> Vec<String> {
+    if req.version() < Version::HTTP_11 || !has_token(req.headers(), header::CONNECTION, "upgrade")
+    {
+        return Vec::new();
+    }
+
+    tokens(req.headers(), header::UPGRADE)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Starts a `426 Upgrade Required` response, telling the client it must upgrade to one of the
+/// comma separated `protocols` to access the resource.
+///
+/// # Panics
+///
+/// Panics if `protocols` is not a valid header value.
+pub fn upgrade_required(protocols: &str) -> http::response::Builder {
+    http::Response::builder()
+        .status(StatusCode::UPGRADE_REQUIRED)
+        .header(
+            header::UPGRADE,
+            HeaderValue::from_str(protocols).expect("invalid protocols"),
+        )
+        .header(header::CONNECTION, "upgrade")
+}
+
+/// Checks that a `101 Switching Protocols` response is coherent with the protocols requested,
+/// adding the `Connection: upgrade` header if missing.
+///
+/// The response must switch to protocols the client asked for, so the connection is not handed
+/// over to a protocol the client doesn't speak.
+#[cfg(feature = "server")]
+pub(crate) fn validate(requested: &[String], res_headers: &mut HeaderMap) -> std::io::Result<()> {
+    let protocols: Vec<&str> = tokens(res_headers, header::UPGRADE).collect();
+
+    if protocols.is_empty() {
+        return Err(std::io::Error::other(
+            "101 responses must have an upgrade header",
+        ));
+    }
+
+    if !protocols
+        .iter()
+        .all(|proto| requested.iter().any(|req| req.eq_ignore_ascii_case(proto)))
+    {
+        return Err(std::io::Error::other(
+            "upgrading to a protocol the client didn't ask for",
+        ));
+    }
+
+    if !has_token(res_headers, header::CONNECTION, "upgrade") {
+        res_headers.append(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    }
+
+    Ok(())
+}
+
+fn tokens(headers: &HeaderMap, name: header::HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(name)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+fn has_token(headers: &HeaderMap, name: header::HeaderName, token: &str) -> bool {
+    tokens(headers, name).any(|value| value.eq_ignore_ascii_case(token))
+}
+
 #[derive(Debug, Error)]
 pub enum ClientUpgradeError {
     #[error("connection not upgradable")]
@@ -54,3 +187,58 @@ impl<T> ClientUpgrade for http::Response<T> {
             .ok_or(ClientUpgradeError::ConnectionNotUpgradable)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request<()> {
+        let mut req = Request::get("/");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(()).unwrap()
+    }
+
+    #[test]
+    fn lists_the_requested_protocols() {
+        let req = request(&[
+            ("connection", "keep-alive, Upgrade"),
+            ("upgrade", "h2c, websocket"),
+        ]);
+        assert_eq!(requested_protocols(&req), ["h2c", "websocket"]);
+
+        let req = request(&[("upgrade", "websocket")]);
+        assert!(requested_protocols(&req).is_empty());
+
+        let mut req = request(&[("connection", "upgrade"), ("upgrade", "websocket")]);
+        *req.version_mut() = Version::HTTP_10;
+        assert!(requested_protocols(&req).is_empty());
+    }
+
+    #[test]
+    fn validates_switching_protocols_responses() {
+        let requested = vec!["websocket".to_string()];
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::UPGRADE, HeaderValue::from_static("WebSocket"));
+        validate(&requested, &mut headers).unwrap();
+        assert_eq!(headers["connection"], "upgrade");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::UPGRADE, HeaderValue::from_static("h2c"));
+        assert!(validate(&requested, &mut headers).is_err());
+
+        let mut headers = HeaderMap::new();
+        assert!(validate(&requested, &mut headers).is_err());
+        assert!(validate(&[], &mut headers).is_err());
+    }
+
+    #[test]
+    fn builds_upgrade_required_responses() {
+        let res = upgrade_required("websocket").body(()).unwrap();
+        assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(res.headers()["upgrade"], "websocket");
+        assert_eq!(res.headers()["connection"], "upgrade");
+    }
+}
@@ -0,0 +1,414 @@
+//! WebSocket helpers.
+//!
+//! Touche doesn't implement the WebSocket framing itself, which is left to libraries such as
+//! [tungstenite](https://docs.rs/tungstenite) running on top of an [upgraded](crate::upgrade)
+//! connection. This module provides the pieces that build on top of the HTTP handshake.
+use std::io;
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use http::{header, HeaderValue, Request};
+
+/// The `permessage-deflate` extension ([RFC 7692](https://datatracker.ietf.org/doc/html/rfc7692)),
+/// which compresses the payload of messages.
+///
+/// The extension is negotiated from the client offers on the handshake request, and the accepted
+/// parameters must be echoed back in the `Sec-WebSocket-Extensions` header of the handshake
+/// response. When the client doesn't offer the extension (or none of its offers are acceptable),
+/// messages are simply exchanged uncompressed.
+///
+/// Compressed messages must be sent with the `RSV1` bit set on their first frame, and received
+/// messages with that bit set must be decompressed. Control frames are never compressed.
+///
+/// # Example
+/// ```no_run
+/// # use std::error::Error;
+/// # use touche::{upgrade::Upgrade, websocket::PerMessageDeflate, Body, Connection, Request, Server};
+/// # fn main() -> std::io::Result<()> {
+/// Server::bind("0.0.0.0:4444").serve(|req: Request<Body>| {
+///     let deflate = PerMessageDeflate::negotiate(&req);
+///
+///     let mut res = tungstenite::handshake::server::create_response(&req.map(|_| ()))?;
+///     if let Some(ref deflate) = deflate {
+///         res.headers_mut()
+///             .insert("sec-websocket-extensions", deflate.response_header());
+///     }
+///
+///     Ok::<_, Box<dyn Error + Send + Sync>>(res.upgrade(move |stream: Connection| {
+///         // Frame the messages, compressing them with `deflate` when it was negotiated
+///     }))
+/// })
+/// # }
+/// ```
+pub struct PerMessageDeflate {
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+    server_max_window_bits: bool,
+    max_message_size: usize,
+    compress: Compress,
+    decompress: Decompress,
+}
+
+/// Every compressed message ends with an empty deflate block, which is removed when sending and
+/// added back when receiving.
+const TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Default limit of the decompressed size of received messages, 64MiB.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 << 20;
+
+impl PerMessageDeflate {
+    /// Negotiates the extension from the `Sec-WebSocket-Extensions` header of a handshake request,
+    /// accepting the first acceptable client offer. Returns `None` when no offer is acceptable.
+    ///
+    /// Offers limiting the server window to less than 15 bits are declined, as the compressor
+    /// always uses the maximum window.
+    pub fn negotiate<B>(req: &Request<B>) -> Option<Self> {
+        req.headers()
+            .get_all(header::SEC_WEBSOCKET_EXTENSIONS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(Self::accept)
+    }
+
+    /// Accepts a single extension offer, like `permessage-deflate; client_max_window_bits`.
+    fn accept(offer: &str) -> Option<Self> {
+        let mut params = offer.split(';').map(str::trim);
+
+        if !params.next()?.eq_ignore_ascii_case("permessage-deflate") {
+            return None;
+        }
+
+        let mut deflate = Self::new();
+        let mut seen = Vec::new();
+
+        for param in params {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+
+            // Offers with repeated parameters must be declined
+            let name = name.to_ascii_lowercase();
+            if seen.contains(&name) {
+                return None;
+            }
+
+            match (name.as_str(), value) {
+                ("server_no_context_takeover", None) => deflate.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => deflate.client_no_context_takeover = true,
+                ("server_max_window_bits", Some("15")) => deflate.server_max_window_bits = true,
+                // Received messages are always decompressed with the maximum window, so any
+                // window the client uses is fine
+                ("client_max_window_bits", None) => {}
+                ("client_max_window_bits", Some(bits)) if window_bits(bits) => {}
+                _ => return None,
+            }
+
+            seen.push(name);
+        }
+
+        Some(deflate)
+    }
+
+    fn new() -> Self {
+        Self {
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+            server_max_window_bits: false,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+        }
+    }
+
+    /// Sets the maximum size of received messages once decompressed, 64MiB by default. A small
+    /// compressed message can inflate to a huge one, so messages exceeding the limit fail to
+    /// decompress.
+    pub fn max_message_size(self, max_message_size: usize) -> Self {
+        Self {
+            max_message_size,
+            ..self
+        }
+    }
+
+    /// The value of the `Sec-WebSocket-Extensions` header to be sent on the handshake response,
+    /// echoing the accepted parameters.
+    pub fn response_header(&self) -> HeaderValue {
+        let mut value = String::from("permessage-deflate");
+        if self.server_no_context_takeover {
+            value.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            value.push_str("; client_no_context_takeover");
+        }
+        if self.server_max_window_bits {
+            value.push_str("; server_max_window_bits=15");
+        }
+        HeaderValue::try_from(value).unwrap()
+    }
+
+    /// Compresses the payload of a message to be sent.
+    pub fn compress(&mut self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(payload.len() / 2 + 64);
+        let start = self.compress.total_in();
+
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity());
+            }
+
+            self.compress
+                .compress_vec(&payload[consumed..], &mut output, FlushCompress::Sync)
+                .map_err(io::Error::other)?;
+
+            let consumed = (self.compress.total_in() - start) as usize;
+            if consumed == payload.len() && output.len() < output.capacity() {
+                break;
+            }
+        }
+
+        if output.ends_with(&TAIL) {
+            output.truncate(output.len() - TAIL.len());
+        }
+
+        if self.server_no_context_takeover {
+            self.compress.reset();
+        }
+
+        Ok(output)
+    }
+
+    /// Decompresses the payload of a received message.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the payload is not valid or decompresses to
+    /// more than the [maximum message size](Self::max_message_size), after which the connection
+    /// should be failed, as the decompression context is no longer usable.
+    pub fn decompress(&mut self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let input = [payload, &TAIL].concat();
+        let limit = self.max_message_size.saturating_add(1);
+        let mut output = Vec::with_capacity((payload.len() * 2 + 64).min(limit));
+        let start = self.decompress.total_in();
+
+        loop {
+            let consumed = (self.decompress.total_in() - start) as usize;
+            if output.len() == output.capacity() {
+                output.reserve_exact(output.capacity().min(limit - output.len()));
+            }
+
+            let status = self
+                .decompress
+                .decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+            // Checked as it goes, so huge messages are never fully inflated
+            if output.len() > self.max_message_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "message exceeds the maximum size",
+                ));
+            }
+
+            let consumed = (self.decompress.total_in() - start) as usize;
+            if status == Status::StreamEnd
+                || (consumed == input.len() && output.len() < output.capacity())
+            {
+                break;
+            }
+        }
+
+        if self.client_no_context_takeover {
+            self.decompress.reset(false);
+        }
+
+        Ok(output)
+    }
+}
+
+impl std::fmt::Debug for PerMessageDeflate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PerMessageDeflate")
+            .field(
+                "server_no_context_takeover",
+                &self.server_no_context_takeover,
+            )
+            .field(
+                "client_no_context_takeover",
+                &self.client_no_context_takeover,
+            )
+            .finish()
+    }
+}
+
+fn window_bits(bits: &str) -> bool {
+    matches!(bits.parse::<u8>(), Ok(8..=15)) && !bits.starts_with('0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offer(extensions: &[&str]) -> Option<PerMessageDeflate> {
+        let mut req = Request::get("/chat");
+        for extension in extensions {
+            req = req.header("sec-websocket-extensions", *extension);
+        }
+        PerMessageDeflate::negotiate(&req.body(()).unwrap())
+    }
+
+    fn accepted(extensions: &[&str]) -> Option<HeaderValue> {
+        offer(extensions).map(|deflate| deflate.response_header())
+    }
+
+    #[test]
+    fn negotiates_the_extension() {
+        assert_eq!(accepted(&[]), None);
+        assert_eq!(accepted(&["x-webkit-deflate-frame"]), None);
+        assert_eq!(
+            accepted(&["permessage-deflate"]).unwrap(),
+            "permessage-deflate"
+        );
+        assert_eq!(
+            accepted(&["permessage-deflate; client_max_window_bits"]).unwrap(),
+            "permessage-deflate"
+        );
+        assert_eq!(
+            accepted(&["permessage-deflate; client_max_window_bits=10"]).unwrap(),
+            "permessage-deflate"
+        );
+        assert_eq!(
+            accepted(&[
+                "permessage-deflate; server_no_context_takeover; client_no_context_takeover"
+            ])
+            .unwrap(),
+            "permessage-deflate; server_no_context_takeover; client_no_context_takeover"
+        );
+        assert_eq!(
+            accepted(&["permessage-deflate; server_max_window_bits=\"15\""]).unwrap(),
+            "permessage-deflate; server_max_window_bits=15"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_next_acceptable_offer() {
+        assert_eq!(
+            accepted(&[
+                "permessage-deflate; server_max_window_bits=10, permessage-deflate; client_no_context_takeover"
+            ])
+            .unwrap(),
+            "permessage-deflate; client_no_context_takeover"
+        );
+        assert_eq!(
+            accepted(&[
+                "x-lol",
+                "permessage-deflate; lol=wut",
+                "permessage-deflate; server_no_context_takeover",
+            ])
+            .unwrap(),
+            "permessage-deflate; server_no_context_takeover"
+        );
+    }
+
+    #[test]
+    fn declines_invalid_offers() {
+        assert_eq!(
+            accepted(&["permessage-deflate; server_max_window_bits=10"]),
+            None
+        );
+        assert_eq!(
+            accepted(&["permessage-deflate; server_max_window_bits"]),
+            None
+        );
+        assert_eq!(
+            accepted(&["permessage-deflate; client_max_window_bits=16"]),
+            None
+        );
+        assert_eq!(
+            accepted(&["permessage-deflate; client_max_window_bits=08"]),
+            None
+        );
+        assert_eq!(
+            accepted(&["permessage-deflate; server_no_context_takeover=1"]),
+            None
+        );
+        assert_eq!(
+            accepted(&[
+                "permessage-deflate; client_no_context_takeover; client_no_context_takeover"
+            ]),
+            None
+        );
+        assert_eq!(accepted(&["permessage-deflate; lol"]), None);
+    }
+
+    #[test]
+    fn decompresses_messages() {
+        // https://datatracker.ietf.org/doc/html/rfc7692#section-7.2.3.2
+        let mut deflate = offer(&["permessage-deflate"]).unwrap();
+        let hello = [0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00];
+        assert_eq!(deflate.decompress(&hello).unwrap(), b"Hello");
+        assert_eq!(
+            deflate.decompress(&[0xf2, 0x00, 0x11, 0x00, 0x00]).unwrap(),
+            b"Hello"
+        );
+
+        let mut deflate = offer(&["permessage-deflate; client_no_context_takeover"]).unwrap();
+        assert_eq!(deflate.decompress(&hello).unwrap(), b"Hello");
+        assert_eq!(deflate.decompress(&hello).unwrap(), b"Hello");
+
+        assert!(deflate.decompress(&[0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn limits_the_size_of_decompressed_messages() {
+        let mut deflate = offer(&["permessage-deflate"]).unwrap();
+        let message = vec![0; 1 << 20];
+        let compressed = deflate.compress(&message).unwrap();
+        assert!(compressed.len() < 2048);
+
+        let mut client = offer(&["permessage-deflate"])
+            .unwrap()
+            .max_message_size(1 << 20);
+        assert_eq!(client.decompress(&compressed).unwrap(), message);
+
+        let mut client = offer(&["permessage-deflate"])
+            .unwrap()
+            .max_message_size(64 * 1024);
+        let err = client.decompress(&compressed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn compresses_messages() {
+        let mut deflate = offer(&["permessage-deflate"]).unwrap();
+        let mut client = offer(&["permessage-deflate"]).unwrap();
+
+        let message = "lolwut ".repeat(10_000);
+        let compressed = deflate.compress(message.as_bytes()).unwrap();
+        assert!(compressed.len() < 1000);
+        assert!(!compressed.ends_with(&TAIL));
+        assert_eq!(client.decompress(&compressed).unwrap(), message.as_bytes());
+
+        // The context is taken over, so repeated messages are even smaller
+        let repeated = deflate.compress(message.as_bytes()).unwrap();
+        assert!(repeated.len() < compressed.len());
+        assert_eq!(client.decompress(&repeated).unwrap(), message.as_bytes());
+
+        assert_eq!(
+            client.decompress(&deflate.compress(b"").unwrap()).unwrap(),
+            b""
+        );
+    }
+
+    #[test]
+    fn resets_the_context_when_not_taken_over() {
+        let mut deflate = offer(&["permessage-deflate; server_no_context_takeover"]).unwrap();
+        let mut client = offer(&["permessage-deflate; client_no_context_takeover"]).unwrap();
+
+        let first = deflate.compress(b"Hello").unwrap();
+        let second = deflate.compress(b"Hello").unwrap();
+        assert_eq!(first, second);
+
+        assert_eq!(client.decompress(&first).unwrap(), b"Hello");
+        assert_eq!(client.decompress(&second).unwrap(), b"Hello");
+    }
+}